@@ -0,0 +1,136 @@
+//! Writes the messages of the decoders' unit tests as seed corpus:
+//! `cargo run --example seed_corpus` from this directory.
+
+use std::{fs, path::Path, sync::Arc};
+
+use bytes::BytesMut;
+use miibgpd::{
+    packets::{
+        capability::Capability,
+        message::Message,
+        notification::{CeaseSubcode, ErrorCode},
+        update::UpdateMessage,
+    },
+    path_attribute::{AsPath, Ipv6NextHop, MpReachNlri, MpUnreachNlri, Origin, PathAttribute},
+};
+
+fn messages() -> Vec<(&'static str, Message)> {
+    let update = UpdateMessage::new(
+        Arc::new(vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(AsPath::sequence(vec![64513.into(), 64514.into()])),
+            PathAttribute::NextHop("10.200.100.3".parse().unwrap()),
+        ]),
+        vec!["10.100.220.0/24".parse().unwrap()],
+        vec!["10.100.221.0/24".parse().unwrap()],
+    );
+    let ipv6_update = UpdateMessage::new(
+        Arc::new(vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+            PathAttribute::MpReachNlri(MpReachNlri {
+                next_hop: Ipv6NextHop {
+                    global: "2001:db8::1".parse().unwrap(),
+                    link_local: Some("fe80::1".parse().unwrap()),
+                },
+                nlri: vec!["2001:db8:100::/48".parse().unwrap()],
+            }),
+            PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                withdrawn_routes: vec!["2001:db8:2::/48".parse().unwrap()],
+            }),
+        ]),
+        vec![],
+        vec![],
+    );
+
+    vec![
+        (
+            "open",
+            Message::new_open(
+                64512.into(),
+                "127.0.0.1".parse().unwrap(),
+                180u16.into(),
+                vec![],
+            ),
+        ),
+        (
+            "open-capabilities",
+            Message::new_open(
+                4_200_000_000.into(),
+                "127.0.0.1".parse().unwrap(),
+                90u16.into(),
+                vec![
+                    "ipv4-unicast".parse().unwrap(),
+                    "ipv6-unicast".parse().unwrap(),
+                    "route-refresh".parse().unwrap(),
+                    Capability::FourOctetAsNumber(4_200_000_000.into()),
+                ],
+            ),
+        ),
+        ("keepalive", Message::new_keepalive()),
+        ("update", Message::Update(update)),
+        ("update-ipv6", Message::Update(ipv6_update)),
+        (
+            "notification",
+            Message::new_notification(ErrorCode::HoldTimerExpired, BytesMut::new()),
+        ),
+        (
+            "notification-cease",
+            Message::new_notification(
+                ErrorCode::Cease(CeaseSubcode::AdministrativeShutdown),
+                BytesMut::new(),
+            ),
+        ),
+    ]
+}
+
+/// Path attributes, preceded by the AS number width the target reads first.
+fn path_attributes() -> Vec<(&'static str, Vec<u8>)> {
+    let as_path =
+        PathAttribute::AsPath(AsPath::sequence(vec![64512.into(), 4_200_000_000.into()]));
+    let mut two_octet_as_path = vec![0];
+    two_octet_as_path.extend_from_slice(&as_path.to_bytes(false));
+    let mut four_octet_as_path = vec![1];
+    four_octet_as_path.extend_from_slice(&as_path.to_bytes(true));
+
+    vec![
+        ("as-path-two-octet", two_octet_as_path),
+        ("as-path-four-octet", four_octet_as_path),
+        // MP_REACH_NLRI of AFI 1, SAFI 2, kept raw
+        (
+            "mp-reach-nlri-ipv4-multicast",
+            vec![1, 0x80, 14, 11, 0, 1, 2, 4, 10, 0, 0, 1, 0, 8, 10],
+        ),
+    ]
+}
+
+fn write(target: &str, name: &str, bytes: &[u8]) {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("corpus")
+        .join(target);
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join(format!("seed-{name}")), bytes).unwrap();
+}
+
+fn main() {
+    for (name, message) in messages() {
+        let bytes = BytesMut::from(message);
+        write("message", name, &bytes);
+        write("round_trip", name, &bytes);
+        if name.starts_with("open") {
+            write("open_message", name, &bytes);
+        }
+        if name.starts_with("update") {
+            write("update_message", name, &bytes);
+            let withdrawn_routes_length = usize::from(u16::from_be_bytes([bytes[19], bytes[20]]));
+            let start = 23 + withdrawn_routes_length;
+            let length = usize::from(u16::from_be_bytes([bytes[start - 2], bytes[start - 1]]));
+            let mut seed = vec![1];
+            seed.extend_from_slice(&bytes[start..start + length]);
+            write("path_attributes", name, &seed);
+        }
+    }
+    for (name, seed) in path_attributes() {
+        write("path_attributes", name, &seed);
+    }
+}
@@ -0,0 +1,9 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use miibgpd::packets::open::OpenMessage;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = OpenMessage::try_from(BytesMut::from(data));
+});
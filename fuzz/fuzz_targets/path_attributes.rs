@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use miibgpd::path_attribute::PathAttribute;
+
+// The first byte picks the AS number width, as the session would.
+fuzz_target!(|data: &[u8]| {
+    if let [four_octet_as, path_attributes @ ..] = data {
+        let _ = PathAttribute::from_u8_slice(path_attributes, four_octet_as & 1 == 1);
+    }
+});
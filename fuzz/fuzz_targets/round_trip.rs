@@ -0,0 +1,23 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use miibgpd::packets::message::Message;
+
+// Whatever decodes must encode to bytes that decode to the same message.
+// UPDATEs decoded in spite of attribute errors leave the malformed
+// attributes out, so they are not expected to round-trip.
+fuzz_target!(|data: &[u8]| {
+    let Ok(message) = Message::try_from(BytesMut::from(data)) else {
+        return;
+    };
+    if let Message::Update(update) = &message
+        && !update.errors().is_empty()
+    {
+        return;
+    }
+    let bytes = BytesMut::from(message.clone());
+    let decoded = Message::try_from(bytes.clone())
+        .unwrap_or_else(|e| panic!("cannot decode {:?} encoded as {:?}: {}", message, bytes, e));
+    assert_eq!(decoded, message);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use miibgpd::packets::update::UpdateMessage;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = UpdateMessage::try_from(BytesMut::from(data));
+});
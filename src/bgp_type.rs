@@ -1,20 +1,43 @@
-use crate::error::ConvertBytesToBgpMessageError;
+use std::time::Duration;
+
+use bytes::BytesMut;
+
+use crate::{
+    error::ConvertBytesToBgpMessageError,
+    packets::notification::{ErrorCode, NotificationMessage, OpenMessageErrorSubcode},
+};
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
-pub struct AutonomousSystemNumber(u16);
+pub struct AutonomousSystemNumber(u32);
 
-impl From<AutonomousSystemNumber> for u16 {
-    fn from(as_number: AutonomousSystemNumber) -> u16 {
+impl From<AutonomousSystemNumber> for u32 {
+    fn from(as_number: AutonomousSystemNumber) -> u32 {
         as_number.0
     }
 }
 
-impl From<u16> for AutonomousSystemNumber {
-    fn from(as_number: u16) -> Self {
+impl From<u32> for AutonomousSystemNumber {
+    fn from(as_number: u32) -> Self {
         Self(as_number)
     }
 }
 
+impl AutonomousSystemNumber {
+    /// Placeholder for a four-octet AS number on the wire of a speaker that
+    /// only understands two-octet ones (RFC 6793 §9).
+    pub const AS_TRANS: AutonomousSystemNumber = AutonomousSystemNumber(23456);
+
+    pub fn is_four_octet(&self) -> bool {
+        self.0 > u16::MAX.into()
+    }
+
+    /// The AS number as carried in a two-octet field, substituting AS_TRANS
+    /// for four-octet AS numbers.
+    pub fn as_two_octet(&self) -> u16 {
+        u16::try_from(self.0).unwrap_or(Self::AS_TRANS.0 as u16)
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
 pub struct HoldTime(u16);
 
@@ -30,9 +53,15 @@ impl From<u16> for HoldTime {
     }
 }
 
+impl From<HoldTime> for Duration {
+    fn from(value: HoldTime) -> Self {
+        Duration::from_secs(value.0.into())
+    }
+}
+
 impl Default for HoldTime {
     fn default() -> Self {
-        Self(0)
+        Self(90)
     }
 }
 
@@ -40,6 +69,21 @@ impl HoldTime {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// RFC 4271 §4.2: the hold time must be either zero or at least three seconds.
+    pub fn is_acceptable(&self) -> bool {
+        self.0 == 0 || self.0 >= 3
+    }
+
+    /// The session uses the smaller of the two hold times advertised in the OPENs.
+    pub fn negotiate(self, remote: HoldTime) -> HoldTime {
+        self.min(remote)
+    }
+
+    /// KEEPALIVEs are sent at one third of the hold time; zero disables them.
+    pub fn keepalive_interval(&self) -> Duration {
+        Duration::from_secs((self.0 / 3).into())
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
@@ -54,12 +98,20 @@ impl From<Version> for u8 {
 impl TryFrom<u8> for Version {
     type Error = ConvertBytesToBgpMessageError;
 
+    /// Only BGP-4 is supported. The NOTIFICATION for any other version
+    /// carries the version we support, as RFC 4271 §6.2 requires.
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        if value <= 4 {
-            Ok(Self(value))
+        let supported = Self::default();
+        if value == supported.0 {
+            Ok(supported)
         } else {
             Err(Self::Error::from(anyhow::anyhow!(
-                "excepted version is 1~4, but got {value}",
+                "excepted version is {}, but got {value}",
+                supported.0
+            ))
+            .with_notification(NotificationMessage::new(
+                ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnsupportedVersionNumber),
+                BytesMut::from(&u16::from(supported.0).to_be_bytes()[..]),
             )))
         }
     }
@@ -76,3 +128,106 @@ impl Version {
         Self::default()
     }
 }
+
+/// Address Family Identifier, as assigned by IANA.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum Afi {
+    Ipv4,
+    Ipv6,
+}
+
+impl TryFrom<u16> for Afi {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Afi::Ipv4),
+            2 => Ok(Afi::Ipv6),
+            _ => Err(anyhow::anyhow!("cannot convert to Afi: {:?}", value)),
+        }
+    }
+}
+
+impl From<Afi> for u16 {
+    fn from(value: Afi) -> Self {
+        match value {
+            Afi::Ipv4 => 1,
+            Afi::Ipv6 => 2,
+        }
+    }
+}
+
+/// Subsequent Address Family Identifier, as assigned by IANA.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum Safi {
+    Unicast,
+}
+
+impl TryFrom<u8> for Safi {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Safi::Unicast),
+            _ => Err(anyhow::anyhow!("cannot convert to Safi: {:?}", value)),
+        }
+    }
+}
+
+impl From<Safi> for u8 {
+    fn from(value: Safi) -> Self {
+        match value {
+            Safi::Unicast => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hold_time_of_one_or_two_seconds_is_unacceptable() {
+        assert!(HoldTime::from(0).is_acceptable());
+        assert!(!HoldTime::from(1).is_acceptable());
+        assert!(!HoldTime::from(2).is_acceptable());
+        assert!(HoldTime::from(3).is_acceptable());
+    }
+
+    #[test]
+    fn negotiated_hold_time_is_the_minimum_of_both_sides() {
+        let negotiated = HoldTime::from(90).negotiate(HoldTime::from(30));
+
+        assert_eq!(negotiated, HoldTime::from(30));
+        assert_eq!(negotiated.keepalive_interval(), Duration::from_secs(10));
+        assert_eq!(
+            HoldTime::from(0)
+                .negotiate(HoldTime::from(90))
+                .keepalive_interval(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn four_octet_as_number_is_carried_as_as_trans_in_two_octets() {
+        assert_eq!(AutonomousSystemNumber::from(64512).as_two_octet(), 64512);
+        assert!(!AutonomousSystemNumber::from(65535).is_four_octet());
+
+        let as_number = AutonomousSystemNumber::from(4_200_000_000);
+        assert!(as_number.is_four_octet());
+        assert_eq!(as_number.as_two_octet(), 23456);
+    }
+
+    #[test]
+    fn version_other_than_four_is_unsupported() {
+        assert_eq!(Version::try_from(4).unwrap(), Version::new());
+
+        let error = Version::try_from(3).unwrap_err();
+        let notification = error.notification().unwrap();
+        assert_eq!(
+            notification.error_code,
+            ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnsupportedVersionNumber)
+        );
+        assert_eq!(&notification.data[..], &[0, 4]);
+    }
+}
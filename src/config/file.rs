@@ -0,0 +1,752 @@
+use std::{collections::BTreeMap, fmt, net::Ipv4Addr, str::FromStr};
+
+use anyhow::Context;
+use rtnetlink::packet::RT_TABLE_MAIN;
+
+use crate::{
+    bgp_type::{Afi, AutonomousSystemNumber},
+    error::ConfigFileError,
+    packets::capability::Capability,
+    routing::{IpNetwork, RouteMap},
+};
+
+use super::{
+    Config, GlobalConfig, Mode, NeighborConfig, parse_as_number, parse_connect_retry,
+    parse_delay_open, parse_hold_time, parse_redistribute,
+};
+
+/// A `HEADER... {` line, the statements up to the matching `}`, and the
+/// line numbers of each.
+#[derive(Debug)]
+struct Block<'a> {
+    line: usize,
+    header: Vec<&'a str>,
+    statements: Vec<(usize, Vec<&'a str>)>,
+}
+
+/// A neighbor being read. Its peer group's statements are applied before
+/// its own, so the neighbor's win.
+struct NeighborStatements {
+    config: NeighborConfig,
+    remote_as: Option<AutonomousSystemNumber>,
+    address_families: Vec<Capability>,
+}
+
+type Errors = Vec<(Option<usize>, anyhow::Error)>;
+
+impl Config {
+    /// Reads a configuration file made of blocks with one statement per line;
+    /// `#` starts a comment.
+    ///
+    /// ```text
+    /// global {
+    ///     local-as 64512
+    ///     local-ip 10.200.100.2
+    ///     local-ipv6 2001:db8::2
+    ///     graceful
+    ///     recursive-next-hop
+    /// }
+    /// policy LOW {
+    ///     set-med 100
+    ///     set-local-pref 50
+    /// }
+    /// address-family ipv4-unicast {
+    ///     network 10.100.210.0/24
+    ///     redistribute static table 254 prefix 10.0.0.0/8 policy LOW
+    /// }
+    /// address-family ipv6-unicast {
+    ///     network 2001:db8:210::/48
+    ///     redistribute connected
+    /// }
+    /// peer-group UPSTREAM {
+    ///     mode active
+    ///     hold-time 30
+    /// }
+    /// neighbor 10.200.100.3 {
+    ///     peer-group UPSTREAM
+    ///     remote-as 64513
+    ///     connect-retry 5
+    ///     delay-open 0
+    ///     require-capability route-refresh
+    ///     address-family ipv4-unicast
+    ///     import-policy LOW
+    /// }
+    /// ```
+    ///
+    /// Blocks may come in any order. A neighbor's mode defaults to `both`
+    /// and, without `address-family` statements, both IPv4 and IPv6 unicast
+    /// are negotiated. Every error found is reported, not only the first.
+    pub fn parse_file(s: &str) -> Result<Self, ConfigFileError> {
+        let mut errors = vec![];
+        let blocks = Self::blocks(s, &mut errors);
+
+        let mut policies = BTreeMap::new();
+        let mut peer_groups = BTreeMap::new();
+        for block in &blocks {
+            let name = match block.header[..] {
+                ["policy" | "peer-group", name] => name,
+                ["policy" | "peer-group", ..] => {
+                    errors.push((
+                        Some(block.line),
+                        anyhow::anyhow!("{} requires exactly one name", block.header[0]),
+                    ));
+                    continue;
+                }
+                _ => continue,
+            };
+            if policies.contains_key(name) || peer_groups.contains_key(name) {
+                errors.push((
+                    Some(block.line),
+                    anyhow::anyhow!("{name} is defined more than once"),
+                ));
+            } else if block.header[0] == "policy" {
+                policies.insert(name.to_owned(), Self::policy(block, &mut errors));
+            } else {
+                peer_groups.insert(name, block);
+            }
+        }
+        // Checked on their own, once every policy they may use is known, so
+        // their errors are not repeated for every neighbor using them.
+        for block in peer_groups.values() {
+            let mut neighbor = Self::neighbor_statements(Ipv4Addr::UNSPECIFIED);
+            for (line, statement) in &block.statements {
+                if let Err(e) = Self::neighbor_statement(&mut neighbor, statement, &policies) {
+                    errors.push((Some(*line), e));
+                }
+            }
+        }
+
+        let mut global = None;
+        let mut networks = vec![];
+        let mut redistribute = vec![];
+        let mut neighbors: Vec<NeighborConfig> = vec![];
+        let mut neighbor_lines = BTreeMap::new();
+        for block in &blocks {
+            match block.header[..] {
+                ["policy" | "peer-group", ..] => {}
+                ["global"] if global.is_some() => {
+                    errors.push((
+                        Some(block.line),
+                        anyhow::anyhow!("global is defined more than once"),
+                    ));
+                }
+                ["global"] => global = Some(Self::global(block, &mut errors)),
+                ["address-family", family] => {
+                    let Some(afi) = unicast_family(family) else {
+                        errors.push((
+                            Some(block.line),
+                            anyhow::anyhow!(
+                                "only ipv4-unicast and ipv6-unicast routes can be originated, not {family}"
+                            ),
+                        ));
+                        continue;
+                    };
+                    for (line, statement) in &block.statements {
+                        let result = match statement[..] {
+                            ["network", value] => value
+                                .parse::<IpNetwork>()
+                                .context(format!("cannot parse {value} as network"))
+                                .and_then(|network| {
+                                    anyhow::ensure!(
+                                        network.afi() == afi,
+                                        "network {value} is not of address-family {family}"
+                                    );
+                                    networks.push(network);
+                                    Ok(())
+                                }),
+                            ["redistribute", ..] => {
+                                parse_redistribute(&mut statement[1..].iter(), &policies, Some(afi))
+                                    .map(|rules| redistribute.extend(rules))
+                            }
+                            _ => Err(Self::unknown_statement(statement)),
+                        };
+                        if let Err(e) = result {
+                            errors.push((Some(*line), e));
+                        }
+                    }
+                }
+                ["neighbor", remote_ip] => {
+                    let Ok(remote_ip) = remote_ip.parse::<Ipv4Addr>() else {
+                        errors.push((
+                            Some(block.line),
+                            anyhow::anyhow!("cannot parse {remote_ip} as ip-address"),
+                        ));
+                        continue;
+                    };
+                    if let Some(line) = neighbor_lines.insert(remote_ip, block.line) {
+                        errors.push((
+                            Some(block.line),
+                            anyhow::anyhow!(
+                                "neighbor {remote_ip} is already configured on line {line}"
+                            ),
+                        ));
+                        continue;
+                    }
+                    if let Some(neighbor) =
+                        Self::neighbor(remote_ip, block, &peer_groups, &policies, &mut errors)
+                    {
+                        neighbors.push(neighbor);
+                    }
+                }
+                _ => {
+                    errors.push((
+                        Some(block.line),
+                        anyhow::anyhow!("unknown block {}", block.header.join(" ")),
+                    ));
+                }
+            }
+        }
+
+        if global.is_none() {
+            errors.push((None, anyhow::anyhow!("global block is missing")));
+        }
+        let (Some(global), true) = (global.flatten(), errors.is_empty()) else {
+            errors.sort_by_key(|(line, _)| line.unwrap_or(usize::MAX));
+            return Err(ConfigFileError::from(errors));
+        };
+
+        Ok(Config {
+            global: GlobalConfig {
+                networks,
+                redistribute,
+                ..global
+            },
+            neighbors,
+        })
+    }
+
+    fn blocks<'a>(s: &'a str, errors: &mut Errors) -> Vec<Block<'a>> {
+        let mut blocks = vec![];
+        let mut current: Option<Block> = None;
+        for (i, line) in s.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens[..] {
+                [] => {}
+                [ref header @ .., "{"] => {
+                    if let Some(block) = current.take() {
+                        errors.push((
+                            Some(block.line),
+                            anyhow::anyhow!("block is not closed before line {line_number}"),
+                        ));
+                        blocks.push(block);
+                    }
+                    if header.is_empty() {
+                        errors.push((Some(line_number), anyhow::anyhow!("block has no name")));
+                    }
+                    current = Some(Block {
+                        line: line_number,
+                        header: header.to_vec(),
+                        statements: vec![],
+                    });
+                }
+                ["}"] => match current.take() {
+                    Some(block) => blocks.push(block),
+                    None => errors.push((Some(line_number), anyhow::anyhow!("unexpected '}}'"))),
+                },
+                _ => match current.as_mut() {
+                    Some(block) => block.statements.push((line_number, tokens)),
+                    None => errors.push((
+                        Some(line_number),
+                        anyhow::anyhow!("statement outside of a block"),
+                    )),
+                },
+            }
+        }
+        if let Some(block) = current {
+            errors.push((Some(block.line), anyhow::anyhow!("block is not closed")));
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    fn unknown_statement(statement: &[&str]) -> anyhow::Error {
+        anyhow::anyhow!("unknown or malformed statement {}", statement.join(" "))
+    }
+
+    /// The global settings without networks and redistribute rules, or
+    /// `None` if the block lacks local-as or local-ip.
+    fn global(block: &Block, errors: &mut Errors) -> Option<GlobalConfig> {
+        let mut local_as = None;
+        let mut local_ip = None;
+        let mut local_ipv6 = None;
+        let mut graceful = false;
+        let mut recursive_next_hop = false;
+        for (line, statement) in &block.statements {
+            let result = match statement[..] {
+                ["local-as", value] => parse_as_number(value).map(|v| local_as = Some(v)),
+                ["local-ip", value] => value
+                    .parse()
+                    .map(|v| local_ip = Some(v))
+                    .context(format!("cannot parse {value} as ip-address")),
+                ["local-ipv6", value] => value
+                    .parse()
+                    .map(|v| local_ipv6 = Some(v))
+                    .context(format!("cannot parse {value} as ipv6-address")),
+                ["graceful"] => {
+                    graceful = true;
+                    Ok(())
+                }
+                ["recursive-next-hop"] => {
+                    recursive_next_hop = true;
+                    Ok(())
+                }
+                _ => Err(Self::unknown_statement(statement)),
+            };
+            if let Err(e) = result {
+                errors.push((Some(*line), e));
+            }
+        }
+
+        match (local_as, local_ip) {
+            (Some(local_as), Some(local_ip)) => Some(GlobalConfig {
+                local_as,
+                local_ip,
+                local_ipv6,
+                networks: vec![],
+                graceful,
+                redistribute: vec![],
+                recursive_next_hop,
+            }),
+            _ => {
+                errors.push((
+                    Some(block.line),
+                    anyhow::anyhow!("global requires local-as and local-ip"),
+                ));
+                None
+            }
+        }
+    }
+
+    fn policy(block: &Block, errors: &mut Errors) -> RouteMap {
+        let mut route_map = RouteMap::default();
+        for (line, statement) in &block.statements {
+            let result = match statement[..] {
+                ["set-med", value] => value
+                    .parse()
+                    .map(|v| route_map.multi_exit_disc = Some(v))
+                    .context(format!("cannot parse {value} as set-med")),
+                ["set-local-pref", value] => value
+                    .parse()
+                    .map(|v| route_map.local_pref = Some(v))
+                    .context(format!("cannot parse {value} as set-local-pref")),
+                _ => Err(Self::unknown_statement(statement)),
+            };
+            if let Err(e) = result {
+                errors.push((Some(*line), e));
+            }
+        }
+        route_map
+    }
+
+    fn neighbor_statements(remote_ip: Ipv4Addr) -> NeighborStatements {
+        NeighborStatements {
+            config: NeighborConfig::new(AutonomousSystemNumber::from(0), remote_ip, Mode::Both),
+            remote_as: None,
+            address_families: vec![],
+        }
+    }
+
+    fn neighbor(
+        remote_ip: Ipv4Addr,
+        block: &Block,
+        peer_groups: &BTreeMap<&str, &Block>,
+        policies: &BTreeMap<String, RouteMap>,
+        errors: &mut Errors,
+    ) -> Option<NeighborConfig> {
+        let mut neighbor = Self::neighbor_statements(remote_ip);
+        for (line, statement) in &block.statements {
+            let ["peer-group", name] = statement[..] else {
+                continue;
+            };
+            match peer_groups.get(name) {
+                Some(peer_group) => {
+                    for (_, statement) in &peer_group.statements {
+                        // Errors were reported when the peer group was read.
+                        let _ = Self::neighbor_statement(&mut neighbor, statement, policies);
+                    }
+                }
+                None => errors.push((
+                    Some(*line),
+                    anyhow::anyhow!("peer-group {name} is not defined"),
+                )),
+            }
+        }
+        for (line, statement) in &block.statements {
+            if let ["peer-group", _] = statement[..] {
+                continue;
+            }
+            if let Err(e) = Self::neighbor_statement(&mut neighbor, statement, policies) {
+                errors.push((Some(*line), e));
+            }
+        }
+
+        let Some(remote_as) = neighbor.remote_as else {
+            errors.push((
+                Some(block.line),
+                anyhow::anyhow!("neighbor {remote_ip} requires remote-as"),
+            ));
+            return None;
+        };
+        neighbor.config.remote_as = remote_as;
+        if !neighbor.address_families.is_empty() {
+            neighbor.config.address_families = neighbor.address_families;
+        }
+        Some(neighbor.config)
+    }
+
+    /// A statement of a `neighbor` or `peer-group` block. `import-policy
+    /// NAME` sets the import route map to one of `policies`.
+    fn neighbor_statement(
+        neighbor: &mut NeighborStatements,
+        statement: &[&str],
+        policies: &BTreeMap<String, RouteMap>,
+    ) -> anyhow::Result<()> {
+        let config = &mut neighbor.config;
+        match statement[..] {
+            ["remote-as", value] => neighbor.remote_as = Some(parse_as_number(value)?),
+            ["mode", value] => config.mode = Mode::from_str(value)?,
+            ["hold-time", value] => config.hold_time = parse_hold_time(value)?,
+            ["connect-retry", value] => config.connect_retry_time = parse_connect_retry(value)?,
+            ["delay-open", value] => config.delay_open_time = parse_delay_open(value)?,
+            ["require-capability", value] => config.required_capabilities.push(
+                value
+                    .parse()
+                    .context(format!("cannot parse {value} as capability"))?,
+            ),
+            ["address-family", value] => {
+                let family: Capability = value
+                    .parse()
+                    .context(format!("cannot parse {value} as address-family"))?;
+                if !matches!(family, Capability::MultiprotocolExtensions { .. }) {
+                    anyhow::bail!("{value} is not an address family");
+                }
+                neighbor.address_families.push(family);
+            }
+            ["import-policy", name] => {
+                config.import = Some(
+                    *policies
+                        .get(name)
+                        .context(format!("policy {name} is not defined"))?,
+                )
+            }
+            ["import-set-med", value] => {
+                config.import.get_or_insert_default().multi_exit_disc = Some(
+                    value
+                        .parse()
+                        .context(format!("cannot parse {value} as import-set-med"))?,
+                )
+            }
+            ["import-set-local-pref", value] => {
+                config.import.get_or_insert_default().local_pref = Some(
+                    value
+                        .parse()
+                        .context(format!("cannot parse {value} as import-set-local-pref"))?,
+                )
+            }
+            _ => return Err(Self::unknown_statement(statement)),
+        }
+        Ok(())
+    }
+}
+
+/// The address family of an `address-family` section originating routes.
+fn unicast_family(family: &str) -> Option<Afi> {
+    match family {
+        "ipv4-unicast" => Some(Afi::Ipv4),
+        "ipv6-unicast" => Some(Afi::Ipv6),
+        _ => None,
+    }
+}
+
+/// Writes the configuration back in the format [`Config::parse_file`]
+/// reads. Peer groups and policies were folded into the neighbors and
+/// redistribute rules using them, so they come out spelled in full.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let global = &self.global;
+        writeln!(f, "global {{")?;
+        writeln!(f, "    local-as {}", u32::from(global.local_as))?;
+        writeln!(f, "    local-ip {}", global.local_ip)?;
+        if let Some(local_ipv6) = global.local_ipv6 {
+            writeln!(f, "    local-ipv6 {local_ipv6}")?;
+        }
+        if global.graceful {
+            writeln!(f, "    graceful")?;
+        }
+        if global.recursive_next_hop {
+            writeln!(f, "    recursive-next-hop")?;
+        }
+        writeln!(f, "}}")?;
+
+        for (afi, family) in [(Afi::Ipv4, "ipv4-unicast"), (Afi::Ipv6, "ipv6-unicast")] {
+            let networks: Vec<&IpNetwork> = global
+                .networks
+                .iter()
+                .filter(|network| network.afi() == afi)
+                .collect();
+            let rules: Vec<_> = global
+                .redistribute
+                .iter()
+                .filter(|rule| rule.afi == afi)
+                .collect();
+            if networks.is_empty() && rules.is_empty() {
+                continue;
+            }
+            writeln!(f, "address-family {family} {{")?;
+            for network in networks {
+                writeln!(f, "    network {network}")?;
+            }
+            for rule in rules {
+                write!(f, "    redistribute {}", rule.source)?;
+                if rule.table != RT_TABLE_MAIN {
+                    write!(f, " table {}", rule.table)?;
+                }
+                for prefix in &rule.prefixes {
+                    write!(f, " prefix {prefix}")?;
+                }
+                if let Some(route_map) = rule.route_map {
+                    if let Some(multi_exit_disc) = route_map.multi_exit_disc {
+                        write!(f, " set-med {multi_exit_disc}")?;
+                    }
+                    if let Some(local_pref) = route_map.local_pref {
+                        write!(f, " set-local-pref {local_pref}")?;
+                    }
+                }
+                writeln!(f)?;
+            }
+            writeln!(f, "}}")?;
+        }
+
+        for neighbor in &self.neighbors {
+            writeln!(f, "neighbor {} {{", neighbor.remote_ip)?;
+            writeln!(f, "    remote-as {}", u32::from(neighbor.remote_as))?;
+            writeln!(f, "    mode {}", neighbor.mode)?;
+            writeln!(f, "    hold-time {}", u16::from(neighbor.hold_time))?;
+            writeln!(
+                f,
+                "    connect-retry {}",
+                neighbor.connect_retry_time.as_secs()
+            )?;
+            writeln!(f, "    delay-open {}", neighbor.delay_open_time.as_secs())?;
+            for capability in &neighbor.required_capabilities {
+                writeln!(f, "    require-capability {capability}")?;
+            }
+            for family in &neighbor.address_families {
+                writeln!(f, "    address-family {family}")?;
+            }
+            if let Some(import) = neighbor.import {
+                if let Some(multi_exit_disc) = import.multi_exit_disc {
+                    writeln!(f, "    import-set-med {multi_exit_disc}")?;
+                }
+                if let Some(local_pref) = import.local_pref {
+                    writeln!(f, "    import-set-local-pref {local_pref}")?;
+                }
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::bgp_type::HoldTime;
+    use crate::config::{Redistribute, RedistributeSource};
+
+    const CONFIG_FILE: &str = "\
+# edge router
+global {
+    local-as 64512
+    local-ip 10.200.100.2
+    local-ipv6 2001:db8::2
+    graceful
+}
+
+policy LOW {
+    set-local-pref 50
+}
+
+address-family ipv4-unicast {
+    network 10.100.210.0/24
+    redistribute static table 100 prefix 10.0.0.0/8 policy LOW
+}
+
+address-family ipv6-unicast {
+    network 2001:db8:210::/48
+    redistribute connected prefix 2001:db8::/32
+}
+
+peer-group UPSTREAM {
+    mode active
+    hold-time 30
+}
+
+neighbor 10.200.100.3 {
+    peer-group UPSTREAM
+    remote-as 64513
+    hold-time 60   # overrides the peer group
+    address-family ipv4-unicast
+}
+
+neighbor 10.200.100.4 {
+    remote-as 64514
+    import-policy LOW
+    import-set-med 10
+}
+";
+
+    #[test]
+    fn config_file_can_be_parsed() {
+        let config = Config::parse_file(CONFIG_FILE).unwrap();
+
+        assert_eq!(config.global.local_as, 64512.into());
+        assert_eq!(config.global.local_ip, Ipv4Addr::new(10, 200, 100, 2));
+        assert_eq!(
+            config.global.local_ipv6,
+            Some("2001:db8::2".parse().unwrap())
+        );
+        assert!(config.global.graceful);
+        assert_eq!(
+            config.global.networks,
+            vec![
+                "10.100.210.0/24".parse().unwrap(),
+                "2001:db8:210::/48".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            config.global.redistribute,
+            vec![
+                Redistribute {
+                    afi: Afi::Ipv4,
+                    source: RedistributeSource::Static,
+                    table: 100,
+                    prefixes: vec!["10.0.0.0/8".parse().unwrap()],
+                    route_map: Some(RouteMap {
+                        multi_exit_disc: None,
+                        local_pref: Some(50),
+                    }),
+                },
+                Redistribute {
+                    afi: Afi::Ipv6,
+                    source: RedistributeSource::Connected,
+                    table: RT_TABLE_MAIN,
+                    prefixes: vec!["2001:db8::/32".parse().unwrap()],
+                    route_map: None,
+                },
+            ]
+        );
+
+        let [upstream, other] = &config.neighbors[..] else {
+            panic!("expected two neighbors, but got {:?}", config.neighbors);
+        };
+        assert_eq!(upstream.remote_as, 64513.into());
+        assert_eq!(upstream.mode, Mode::Active);
+        assert_eq!(upstream.hold_time, HoldTime::from(60));
+        assert_eq!(
+            upstream.address_families,
+            vec!["ipv4-unicast".parse().unwrap()]
+        );
+        assert_eq!(upstream.import, None);
+        assert_eq!(other.mode, Mode::Both);
+        assert_eq!(other.hold_time, HoldTime::new());
+        assert_eq!(other.address_families.len(), 2);
+        assert_eq!(
+            other.import,
+            Some(RouteMap {
+                multi_exit_disc: Some(10),
+                local_pref: Some(50),
+            })
+        );
+    }
+
+    #[test]
+    fn config_file_reports_every_error_with_its_line() {
+        let config = "\
+global {
+    local-as 64512
+    local-ip 10.200.100.256
+}
+neighbor 10.200.100.3 {
+    peer-group MISSING
+    remote-as 64513
+    hold-time 2
+}
+neighbor 10.200.100.3 {
+    remote-as 64513
+}
+neighbor 10.200.100.4 {
+    mode both
+}
+";
+
+        let errors = Config::parse_file(config).unwrap_err();
+        let lines: Vec<Option<usize>> = errors.errors().iter().map(|(line, _)| *line).collect();
+
+        assert_eq!(
+            lines,
+            vec![Some(1), Some(3), Some(6), Some(8), Some(10), Some(13)]
+        );
+        assert!(errors.to_string().starts_with("line 1: "));
+    }
+
+    #[test]
+    fn config_file_rejects_unclosed_blocks_and_stray_statements() {
+        let config = "local-as 64512\nglobal {\n    local-as 64512\n";
+
+        let errors = Config::parse_file(config).unwrap_err();
+        let lines: Vec<Option<usize>> = errors.errors().iter().map(|(line, _)| *line).collect();
+
+        assert_eq!(lines, vec![Some(1), Some(2), Some(2)]);
+    }
+
+    #[test]
+    fn config_file_rejects_routes_outside_their_address_family() {
+        let config = "\
+global {
+    local-as 64512
+    local-ip 10.200.100.2
+}
+address-family ipv6-unicast {
+    network 10.100.210.0/24
+    redistribute static prefix 10.0.0.0/8
+}
+address-family l2vpn-evpn {
+}
+";
+
+        let errors = Config::parse_file(config).unwrap_err();
+        let lines: Vec<Option<usize>> = errors.errors().iter().map(|(line, _)| *line).collect();
+
+        assert_eq!(lines, vec![Some(6), Some(7), Some(9)]);
+    }
+
+    #[test]
+    fn config_file_round_trips() {
+        let config = Config::parse_file(CONFIG_FILE).unwrap();
+
+        assert_eq!(Config::parse_file(&config.to_string()).unwrap(), config);
+    }
+
+    #[test]
+    fn one_line_config_can_be_written_as_config_file() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active connect-retry 5 \
+            delay-open 3 require-capability route-refresh recursive-next-hop \
+            redistribute connected set-med 10 redistribute static prefix 2001:db8::/32 \
+            10.0.0.0/24 2001:db8:1::/48"
+            .parse()
+            .unwrap();
+
+        let parsed = Config::parse_file(&config.to_string()).unwrap();
+
+        assert_eq!(parsed, config);
+        assert_eq!(
+            parsed.neighbors[0].connect_retry_time,
+            Duration::from_secs(5)
+        );
+    }
+}
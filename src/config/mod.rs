@@ -0,0 +1,749 @@
+mod file;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use rtnetlink::packet::{RT_TABLE_MAIN, RTPROT_BOOT, RTPROT_KERNEL, RTPROT_STATIC};
+
+use crate::{
+    bgp_type::{Afi, AutonomousSystemNumber, HoldTime, Safi},
+    error::ConfigParseError,
+    kernel::KernelRoute,
+    packets::capability::Capability,
+    routing::{IpNetwork, RouteMap},
+};
+
+/// Configuration of the daemon: the settings of this router and one entry
+/// per neighbor. Read from a configuration file (see [`Config::parse_file`])
+/// or written on one line as
+/// `LOCAL_AS LOCAL_IP REMOTE_AS REMOTE_IP MODE [options]`, where
+/// `neighbor REMOTE_AS REMOTE_IP MODE` adds another neighbor that the
+/// neighbor options following it apply to.
+#[derive(PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+pub struct Config {
+    pub global: GlobalConfig,
+    pub neighbors: Vec<NeighborConfig>,
+}
+
+/// Settings shared by every session.
+#[derive(PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+pub struct GlobalConfig {
+    pub local_as: AutonomousSystemNumber,
+    pub local_ip: Ipv4Addr,
+    /// Next hop of the IPv6 routes we advertise to eBGP peers; without it
+    /// they only get IPv4 routes.
+    pub local_ipv6: Option<Ipv6Addr>,
+    pub networks: Vec<IpNetwork>,
+    /// Leave the routes we installed in the kernel on shutdown so the host
+    /// keeps forwarding while the daemon restarts.
+    pub graceful: bool,
+    pub redistribute: Vec<Redistribute>,
+    /// Resolve next hops through other BGP routes, not only kernel ones.
+    pub recursive_next_hop: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+pub struct NeighborConfig {
+    pub remote_as: AutonomousSystemNumber,
+    pub remote_ip: Ipv4Addr,
+    pub mode: Mode,
+    pub hold_time: HoldTime,
+    pub connect_retry_time: Duration,
+    pub delay_open_time: Duration,
+    pub required_capabilities: Vec<Capability>,
+    /// Multiprotocol Extensions capabilities advertised in our OPEN.
+    pub address_families: Vec<Capability>,
+    /// Applied to the routes learned from the neighbor as they enter
+    /// Loc-RIB.
+    pub import: Option<RouteMap>,
+}
+
+impl NeighborConfig {
+    fn new(remote_as: AutonomousSystemNumber, remote_ip: Ipv4Addr, mode: Mode) -> Self {
+        Self {
+            remote_as,
+            remote_ip,
+            mode,
+            hold_time: HoldTime::new(),
+            connect_retry_time: DEFAULT_CONNECT_RETRY_TIME,
+            delay_open_time: Duration::ZERO,
+            required_capabilities: vec![],
+            address_families: vec![
+                Capability::MultiprotocolExtensions {
+                    afi: Afi::Ipv4,
+                    safi: Safi::Unicast,
+                },
+                Capability::MultiprotocolExtensions {
+                    afi: Afi::Ipv6,
+                    safi: Safi::Unicast,
+                },
+            ],
+            import: None,
+        }
+    }
+}
+
+/// RFC 4271 §10 suggests 120 seconds for ConnectRetryTime.
+const DEFAULT_CONNECT_RETRY_TIME: Duration = Duration::from_secs(120);
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum Mode {
+    Passive,
+    Active,
+    Both,
+}
+
+impl FromStr for Mode {
+    type Err = ConfigParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passive" | "Passive" => Ok(Mode::Passive),
+            "active" | "Active" => Ok(Mode::Active),
+            "both" | "Both" => Ok(Mode::Both),
+            _ => Err(ConfigParseError::from(anyhow::anyhow!("cannot parse {s}"))),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Passive => write!(f, "passive"),
+            Mode::Active => write!(f, "active"),
+            Mode::Both => write!(f, "both"),
+        }
+    }
+}
+
+/// Kernel routes to originate into Loc-RIB, configured as
+/// `redistribute connected|kernel|static [table N] [prefix P]... [policy NAME] [set-med N] [set-local-pref N]`.
+#[derive(PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+pub struct Redistribute {
+    /// The address family of the routes, given by the `address-family`
+    /// section of a configuration file the rule is in.
+    pub afi: Afi,
+    pub source: RedistributeSource,
+    pub table: u8,
+    /// Only routes within one of these prefixes are redistributed; all of
+    /// them if empty.
+    pub prefixes: Vec<IpNetwork>,
+    pub route_map: Option<RouteMap>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum RedistributeSource {
+    /// Subnets of the interface addresses, added by the kernel itself.
+    Connected,
+    /// Routes added with `proto static`.
+    Static,
+    /// Routes added by hand, which `ip route add` tags with `proto boot`.
+    /// Those of other routing daemons and of DHCP or router advertisements
+    /// are not redistributed.
+    Kernel,
+}
+
+impl FromStr for RedistributeSource {
+    type Err = ConfigParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "connected" => Ok(RedistributeSource::Connected),
+            "static" => Ok(RedistributeSource::Static),
+            "kernel" => Ok(RedistributeSource::Kernel),
+            _ => Err(ConfigParseError::from(anyhow::anyhow!("cannot parse {s}"))),
+        }
+    }
+}
+
+impl fmt::Display for RedistributeSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RedistributeSource::Connected => write!(f, "connected"),
+            RedistributeSource::Static => write!(f, "static"),
+            RedistributeSource::Kernel => write!(f, "kernel"),
+        }
+    }
+}
+
+impl Redistribute {
+    pub fn matches(&self, route: &KernelRoute) -> bool {
+        let source = match route.protocol {
+            RTPROT_KERNEL => RedistributeSource::Connected,
+            RTPROT_STATIC => RedistributeSource::Static,
+            RTPROT_BOOT => RedistributeSource::Kernel,
+            _ => return false,
+        };
+
+        route.network.afi() == self.afi
+            && source == self.source
+            && route.table == self.table
+            && (self.prefixes.is_empty()
+                || self
+                    .prefixes
+                    .iter()
+                    .any(|prefix| route.network.is_subnet_of(prefix)))
+    }
+}
+
+fn parse_as_number(value: &str) -> anyhow::Result<AutonomousSystemNumber> {
+    Ok(AutonomousSystemNumber::from(
+        value
+            .parse::<u32>()
+            .context(format!("cannot parse {value} as as-number"))?,
+    ))
+}
+
+fn parse_hold_time(value: &str) -> anyhow::Result<HoldTime> {
+    let hold_time = HoldTime::from(
+        value
+            .parse::<u16>()
+            .context(format!("cannot parse {value} as hold-time"))?,
+    );
+    if !hold_time.is_acceptable() {
+        anyhow::bail!("hold-time must be 0 or at least 3 seconds, but got {value}");
+    }
+    Ok(hold_time)
+}
+
+fn parse_connect_retry(value: &str) -> anyhow::Result<Duration> {
+    let seconds = value
+        .parse::<u64>()
+        .context(format!("cannot parse {value} as connect-retry"))?;
+    if seconds == 0 {
+        anyhow::bail!("connect-retry must be at least 1 second");
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+fn parse_delay_open(value: &str) -> anyhow::Result<Duration> {
+    Ok(Duration::from_secs(
+        value
+            .parse::<u64>()
+            .context(format!("cannot parse {value} as delay-open"))?,
+    ))
+}
+
+/// Parses `SOURCE [modifiers]` of a redistribute rule, consuming modifiers
+/// from `options` for as long as there are any. `policy NAME` sets the route
+/// map to one of `policies`. The rule is for `afi` if given, as in an
+/// `address-family` section, and otherwise for each family it has prefixes
+/// of, or both if it has none.
+fn parse_redistribute(
+    options: &mut std::slice::Iter<&str>,
+    policies: &BTreeMap<String, RouteMap>,
+    afi: Option<Afi>,
+) -> anyhow::Result<Vec<Redistribute>> {
+    let value = options
+        .next()
+        .context("redistribute requires connected, kernel or static")?;
+    let mut rule = Redistribute {
+        afi: afi.unwrap_or(Afi::Ipv4),
+        source: value
+            .parse()
+            .context(format!("cannot parse {value} as redistribute source"))?,
+        table: RT_TABLE_MAIN,
+        prefixes: vec![],
+        route_map: None,
+    };
+    while let Some(modifier) = options.as_slice().first() {
+        if !["table", "prefix", "policy", "set-med", "set-local-pref"].contains(modifier) {
+            break;
+        }
+        options.next();
+        let value = options
+            .next()
+            .context(format!("{modifier} requires a value"))?;
+        match *modifier {
+            "table" => {
+                rule.table = value
+                    .parse()
+                    .context(format!("cannot parse {value} as table"))?
+            }
+            "prefix" => rule.prefixes.push(
+                value
+                    .parse()
+                    .context(format!("cannot parse {value} as network"))?,
+            ),
+            "policy" => {
+                rule.route_map = Some(
+                    *policies
+                        .get(*value)
+                        .context(format!("policy {value} is not defined"))?,
+                )
+            }
+            "set-med" => {
+                rule.route_map.get_or_insert_default().multi_exit_disc = Some(
+                    value
+                        .parse()
+                        .context(format!("cannot parse {value} as set-med"))?,
+                )
+            }
+            _ => {
+                rule.route_map.get_or_insert_default().local_pref = Some(
+                    value
+                        .parse()
+                        .context(format!("cannot parse {value} as set-local-pref"))?,
+                )
+            }
+        }
+    }
+
+    if let Some(afi) = afi
+        && let Some(prefix) = rule.prefixes.iter().find(|prefix| prefix.afi() != afi)
+    {
+        anyhow::bail!("prefix {prefix} is of another address family than the section");
+    }
+    let families = afi.map_or(vec![Afi::Ipv4, Afi::Ipv6], |afi| vec![afi]);
+    Ok(families
+        .into_iter()
+        .filter_map(|afi| {
+            let prefixes: Vec<IpNetwork> = rule
+                .prefixes
+                .iter()
+                .filter(|prefix| prefix.afi() == afi)
+                .copied()
+                .collect();
+            (rule.prefixes.is_empty() || !prefixes.is_empty()).then(|| Redistribute {
+                afi,
+                prefixes,
+                ..rule.clone()
+            })
+        })
+        .collect())
+}
+
+/// Neighbors are told apart by their address, so it must be unique.
+fn check_duplicate_neighbors(neighbors: &[NeighborConfig]) -> anyhow::Result<()> {
+    if let Some(duplicate) = neighbors
+        .iter()
+        .enumerate()
+        .find(|(i, n)| neighbors[..*i].iter().any(|m| m.remote_ip == n.remote_ip))
+    {
+        anyhow::bail!(
+            "neighbor {0} is configured more than once",
+            duplicate.1.remote_ip
+        );
+    }
+    Ok(())
+}
+
+impl FromStr for Config {
+    type Err = ConfigParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: Vec<&str> = s.split(' ').collect();
+        let [local_as, local_ip, remote_as, remote_ip, mode, ..] = config[..] else {
+            return Err(ConfigParseError::from(anyhow::anyhow!(
+                "config requires local as-number, local ip-address, remote as-number, \
+                 remote ip-address and mode, but config is {0}",
+                s
+            )));
+        };
+
+        let local_as = parse_as_number(local_as).context(format!(
+            "cannot parse 1st part of config, '{0}', as as-number and config is {1}",
+            local_as, s
+        ))?;
+
+        let local_ip: Ipv4Addr = local_ip.parse().context(format!(
+            "cannot parse 2nd part of config, '{0}', as ip-address and config is {1}",
+            local_ip, s
+        ))?;
+
+        let remote_as = parse_as_number(remote_as).context(format!(
+            "cannot parse 3rd part of config, '{0}', as as-number and config is {1}",
+            remote_as, s
+        ))?;
+
+        let remote_ip: Ipv4Addr = remote_ip.parse().context(format!(
+            "cannot parse 4th part of config, '{0}', as ip-address and config is {1}",
+            remote_ip, s
+        ))?;
+
+        let mode = Mode::from_str(mode).context(format!(
+            "cannot parse 5th part of config, '{0}', as mode and config is {1}",
+            mode, s
+        ))?;
+
+        let mut neighbors = vec![];
+        let mut neighbor = NeighborConfig::new(remote_as, remote_ip, mode);
+        let mut networks: Vec<IpNetwork> = vec![];
+        let mut graceful = false;
+        let mut redistribute = vec![];
+        let mut recursive_next_hop = false;
+        let mut local_ipv6 = None;
+        let mut options = config[5..].iter();
+        while let Some(option) = options.next() {
+            if *option == "neighbor" {
+                let [remote_as, remote_ip, mode] = [(); 3].map(|_| options.next());
+                let (Some(remote_as), Some(remote_ip), Some(mode)) = (remote_as, remote_ip, mode)
+                else {
+                    return Err(ConfigParseError::from(anyhow::anyhow!(
+                        "neighbor requires as-number, ip-address and mode and config is {0}",
+                        s
+                    )));
+                };
+                let remote_as = parse_as_number(remote_as).context(format!(
+                    "cannot parse neighbor '{0}' as as-number and config is {1}",
+                    remote_as, s
+                ))?;
+                let remote_ip: Ipv4Addr = remote_ip.parse().context(format!(
+                    "cannot parse neighbor '{0}' as ip-address and config is {1}",
+                    remote_ip, s
+                ))?;
+                let mode = Mode::from_str(mode).context(format!(
+                    "cannot parse neighbor '{0}' as mode and config is {1}",
+                    mode, s
+                ))?;
+                neighbors.push(std::mem::replace(
+                    &mut neighbor,
+                    NeighborConfig::new(remote_as, remote_ip, mode),
+                ));
+                continue;
+            }
+            if *option == "hold-time" {
+                let value = options.next().context(format!(
+                    "hold-time requires a value in seconds and config is {0}",
+                    s
+                ))?;
+                neighbor.hold_time = parse_hold_time(value).context(format!("config is {0}", s))?;
+                continue;
+            }
+            if *option == "connect-retry" {
+                let value = options.next().context(format!(
+                    "connect-retry requires a value in seconds and config is {0}",
+                    s
+                ))?;
+                neighbor.connect_retry_time =
+                    parse_connect_retry(value).context(format!("config is {0}", s))?;
+                continue;
+            }
+            if *option == "delay-open" {
+                let value = options.next().context(format!(
+                    "delay-open requires a value in seconds and config is {0}",
+                    s
+                ))?;
+                neighbor.delay_open_time =
+                    parse_delay_open(value).context(format!("config is {0}", s))?;
+                continue;
+            }
+
+            if *option == "require-capability" {
+                let value = options.next().context(format!(
+                    "require-capability requires a capability name and config is {0}",
+                    s
+                ))?;
+                neighbor
+                    .required_capabilities
+                    .push(value.parse::<Capability>().context(format!(
+                        "cannot parse {0} as capability and config is {1}",
+                        value, s
+                    ))?);
+                continue;
+            }
+            if *option == "import-set-med" {
+                let value = options.next().context(format!(
+                    "import-set-med requires a value and config is {0}",
+                    s
+                ))?;
+                neighbor.import.get_or_insert_default().multi_exit_disc =
+                    Some(value.parse().context(format!(
+                        "cannot parse {0} as import-set-med and config is {1}",
+                        value, s
+                    ))?);
+                continue;
+            }
+            if *option == "import-set-local-pref" {
+                let value = options.next().context(format!(
+                    "import-set-local-pref requires a value and config is {0}",
+                    s
+                ))?;
+                neighbor.import.get_or_insert_default().local_pref =
+                    Some(value.parse().context(format!(
+                        "cannot parse {0} as import-set-local-pref and config is {1}",
+                        value, s
+                    ))?);
+                continue;
+            }
+            if *option == "redistribute" {
+                redistribute.extend(
+                    parse_redistribute(&mut options, &BTreeMap::new(), None)
+                        .context(format!("config is {0}", s))?,
+                );
+                continue;
+            }
+            if *option == "local-ipv6" {
+                let value = options.next().context(format!(
+                    "local-ipv6 requires an ip-address and config is {0}",
+                    s
+                ))?;
+                local_ipv6 = Some(value.parse().context(format!(
+                    "cannot parse {0} as ipv6-address and config is {1}",
+                    value, s
+                ))?);
+                continue;
+            }
+            if *option == "recursive-next-hop" {
+                recursive_next_hop = true;
+                continue;
+            }
+            if *option == "graceful" {
+                graceful = true;
+                continue;
+            }
+
+            networks.push(option.parse().context(format!(
+                "cannot parse {0} as network and config is {1}",
+                option, s
+            ))?);
+        }
+        // As they come out of a configuration file, by address family.
+        redistribute.sort_by_key(|rule| rule.afi);
+
+        neighbors.push(neighbor);
+        check_duplicate_neighbors(&neighbors)?;
+
+        Ok(Config {
+            global: GlobalConfig {
+                local_as,
+                local_ip,
+                local_ipv6,
+                networks,
+                graceful,
+                redistribute,
+                recursive_next_hop,
+            },
+            neighbors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_can_parse_hold_time_and_networks() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active hold-time 30 10.0.0.0/24"
+            .parse()
+            .unwrap();
+
+        assert_eq!(config.neighbors[0].hold_time, HoldTime::from(30));
+        assert_eq!(
+            config.neighbors[0].connect_retry_time,
+            DEFAULT_CONNECT_RETRY_TIME
+        );
+        assert_eq!(config.global.networks, vec!["10.0.0.0/24".parse().unwrap()]);
+    }
+
+    #[test]
+    fn config_can_parse_connect_retry() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active connect-retry 5"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.neighbors[0].connect_retry_time,
+            Duration::from_secs(5)
+        );
+        assert_eq!(config.neighbors[0].delay_open_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn config_rejects_hold_time_of_one_or_two_seconds() {
+        let config = "64512 127.0.0.1 64513 127.0.0.2 active hold-time 2".parse::<Config>();
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn config_rejects_missing_parts_instead_of_panicking() {
+        let config = "64512 127.0.0.1 64513".parse::<Config>();
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn config_can_parse_both_mode() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 both".parse().unwrap();
+
+        assert_eq!(config.neighbors[0].mode, Mode::Both);
+        assert!(!config.global.graceful);
+    }
+
+    #[test]
+    fn config_can_parse_import_route_map() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active \
+            import-set-local-pref 200 neighbor 64514 127.0.0.3 active import-set-med 10"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.neighbors[0].import,
+            Some(RouteMap {
+                multi_exit_disc: None,
+                local_pref: Some(200),
+            })
+        );
+        assert_eq!(
+            config.neighbors[1].import,
+            Some(RouteMap {
+                multi_exit_disc: Some(10),
+                local_pref: None,
+            })
+        );
+    }
+
+    #[test]
+    fn config_can_parse_redistribute() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active \
+            redistribute connected \
+            redistribute kernel table 100 prefix 10.0.0.0/8 set-local-pref 200 \
+            10.0.0.0/24"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.global.redistribute,
+            vec![
+                Redistribute {
+                    afi: Afi::Ipv4,
+                    source: RedistributeSource::Connected,
+                    table: RT_TABLE_MAIN,
+                    prefixes: vec![],
+                    route_map: None,
+                },
+                Redistribute {
+                    afi: Afi::Ipv4,
+                    source: RedistributeSource::Kernel,
+                    table: 100,
+                    prefixes: vec!["10.0.0.0/8".parse().unwrap()],
+                    route_map: Some(RouteMap {
+                        multi_exit_disc: None,
+                        local_pref: Some(200),
+                    }),
+                },
+                Redistribute {
+                    afi: Afi::Ipv6,
+                    source: RedistributeSource::Connected,
+                    table: RT_TABLE_MAIN,
+                    prefixes: vec![],
+                    route_map: None,
+                },
+            ]
+        );
+        assert_eq!(config.global.networks, vec!["10.0.0.0/24".parse().unwrap()]);
+    }
+
+    #[test]
+    fn redistribute_matches_routes_of_either_family_by_protocol() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active \
+            redistribute kernel prefix 2001:db8::/32 prefix 10.0.0.0/8"
+            .parse()
+            .unwrap();
+        let matches = |network: &str, protocol| {
+            let route = KernelRoute {
+                network: network.parse().unwrap(),
+                table: RT_TABLE_MAIN,
+                gateway: None,
+                output_interface: Some(2),
+                metric: 0,
+                protocol,
+            };
+            config
+                .global
+                .redistribute
+                .iter()
+                .any(|rule| rule.matches(&route))
+        };
+
+        assert!(matches("2001:db8:1::/48", RTPROT_BOOT));
+        assert!(matches("10.1.0.0/16", RTPROT_BOOT));
+        assert!(!matches("2001:db9::/48", RTPROT_BOOT));
+        assert!(!matches("10.1.0.0/16", RTPROT_STATIC));
+        // e.g. installed by DHCP or another routing daemon
+        assert!(!matches("10.1.0.0/16", 16));
+        assert!(!matches("10.1.0.0/16", 186));
+    }
+
+    #[test]
+    fn config_can_parse_graceful_flag() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active graceful 10.0.0.0/24"
+            .parse()
+            .unwrap();
+
+        assert!(config.global.graceful);
+        assert!(!config.global.recursive_next_hop);
+        assert_eq!(config.global.local_ipv6, None);
+        assert_eq!(config.global.networks, vec!["10.0.0.0/24".parse().unwrap()]);
+    }
+
+    #[test]
+    fn config_can_parse_multiple_neighbors() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active hold-time 30 \
+            10.0.0.0/24 \
+            neighbor 64514 127.0.0.3 passive connect-retry 5"
+            .parse()
+            .unwrap();
+
+        assert_eq!(config.global.networks, vec!["10.0.0.0/24".parse().unwrap()]);
+        assert_eq!(config.neighbors.len(), 2);
+        assert_eq!(config.neighbors[0].hold_time, HoldTime::from(30));
+        assert_eq!(
+            config.neighbors[0].connect_retry_time,
+            DEFAULT_CONNECT_RETRY_TIME
+        );
+        assert_eq!(config.neighbors[1].remote_as, 64514.into());
+        assert_eq!(config.neighbors[1].mode, Mode::Passive);
+        assert_eq!(config.neighbors[1].hold_time, HoldTime::new());
+        assert_eq!(
+            config.neighbors[1].connect_retry_time,
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn config_rejects_duplicate_neighbors() {
+        let config = "64512 127.0.0.1 64513 127.0.0.2 active neighbor 64514 127.0.0.2 passive"
+            .parse::<Config>();
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn config_can_parse_four_octet_as_numbers() {
+        let config: Config = "4200000000 127.0.0.1 64513 127.0.0.2 active"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.global.local_as,
+            AutonomousSystemNumber::from(4_200_000_000)
+        );
+    }
+
+    #[test]
+    fn config_can_parse_local_ipv6() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active local-ipv6 2001:db8::1"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.global.local_ipv6,
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert!(
+            "64512 127.0.0.1 64513 127.0.0.2 active local-ipv6 10.0.0.1"
+                .parse::<Config>()
+                .is_err()
+        );
+    }
+}
@@ -1,112 +1,190 @@
-use std::io;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::result::Result::Ok;
+use std::sync::Mutex;
 
 use anyhow::{Context, Result};
-use bytes::{BufMut, BytesMut};
+use futures::{FutureExt, SinkExt, StreamExt};
 use tokio::{
-    io::AsyncWriteExt,
-    net::{TcpListener, TcpStream},
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpSocket, TcpStream},
+    sync::mpsc::UnboundedSender,
 };
-use tracing::info;
+use tokio_util::codec::Framed;
+use tracing::{info, warn};
 
 use crate::{
-    config::{Config, Mode},
-    error::CreateConnectionError,
-    packets::message::Message,
+    error::{
+        ConvertBytesToBgpMessageError, CreateConnectionError, ReceiveMessageError, SendMessageError,
+    },
+    packets::{codec::MessageCodec, message::Message},
 };
 
 const BGP_PORT: u16 = 179;
 
+/// Byte stream a BGP session runs over; a `TcpStream` in production and an
+/// in-memory duplex stream in tests.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + Debug {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Debug> Stream for T {}
+
 #[derive(Debug)]
 pub struct Connection {
-    connection: TcpStream,
-    buffer: BytesMut,
+    framed: Framed<Box<dyn Stream>, MessageCodec>,
+    initiated_locally: bool,
+    closed: bool,
 }
 
 impl Connection {
-    pub async fn connect(config: &Config) -> Result<Self> {
-        let connection = match config.mode {
-            Mode::Active => Self::connect_to_remote_peer(config).await,
-            Mode::Passive => Self::wait_connection_from_remote_peer(config).await,
-        }?;
-
-        let buffer = BytesMut::with_capacity(1500);
+    pub async fn connect(local_ip: Ipv4Addr, remote_ip: Ipv4Addr) -> Result<Self> {
+        let connection = Self::connect_to_remote_peer(local_ip, remote_ip).await?;
 
-        Ok(Self { connection, buffer })
+        Ok(Self::from_stream(connection, true))
     }
 
-    pub async fn get_message(&mut self) -> Option<Message> {
-        self.read_data_from_tcp_connection().await;
+    pub fn from_stream(stream: impl Stream + 'static, initiated_locally: bool) -> Self {
+        let stream: Box<dyn Stream> = Box::new(stream);
 
-        let buffer = self.split_buffer_at_message_separator()?;
-
-        Message::try_from(buffer).ok()
-    }
-
-    async fn read_data_from_tcp_connection(&mut self) {
-        loop {
-            let mut buf: Vec<u8> = vec![];
-
-            match self.connection.try_read_buf(&mut buf) {
-                Ok(0) => {}
-                Ok(n) => self.buffer.put(&buf[..]),
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                Err(e) => panic!(
-                    "An error occured while reading data from TCP connection: {:?}",
-                    e
-                ),
-            }
+        Self {
+            framed: Framed::new(stream, MessageCodec::default()),
+            initiated_locally,
+            closed: false,
         }
     }
 
-    fn split_buffer_at_message_separator(&mut self) -> Option<BytesMut> {
-        let index = self.get_index_of_message_separator().ok()?;
+    pub fn is_initiated_locally(&self) -> bool {
+        self.initiated_locally
+    }
 
-        if self.buffer.len() < index {
-            return None;
-        }
+    /// Switches UPDATE encoding to four-octet AS numbers once both speakers
+    /// advertised the capability (RFC 6793).
+    pub fn set_four_octet_as(&mut self, four_octet_as: bool) {
+        self.framed.codec_mut().set_four_octet_as(four_octet_as);
+    }
 
-        Some(self.buffer.split_to(index))
+    /// Whether the remote peer has closed the connection or it failed.
+    /// Messages received before are still returned by `get_message` first.
+    pub fn is_closed(&self) -> bool {
+        self.closed
     }
 
-    fn get_index_of_message_separator(&self) -> Result<usize> {
-        let minimum_message_length = 19;
+    /// The next message if one has been received in full, without waiting
+    /// for one.
+    pub async fn get_message(&mut self) -> Result<Option<Message>, ConvertBytesToBgpMessageError> {
+        self.receive().now_or_never().unwrap_or(Ok(None))
+    }
 
-        if self.buffer.len() < 19 {
-            return Err(anyhow::anyhow!(
-                "Message length is too short: {}",
-                self.buffer.len()
-            ));
+    /// Waits for the next message; `None` once the connection is closed.
+    pub async fn receive(&mut self) -> Result<Option<Message>, ConvertBytesToBgpMessageError> {
+        if self.closed {
+            return Ok(None);
         }
 
-        Ok(u16::from_be_bytes([self.buffer[16], self.buffer[17]]) as usize)
+        match self.framed.next().await {
+            Some(Ok(message)) => Ok(Some(message)),
+            Some(Err(ReceiveMessageError::Message(e))) => Err(e),
+            Some(Err(ReceiveMessageError::Io(e))) => {
+                warn!("cannot read data from TCP connection, error={:?}", e);
+                self.closed = true;
+                Ok(None)
+            }
+            None => {
+                self.closed = true;
+                Ok(None)
+            }
+        }
     }
 
-    pub async fn send(&mut self, message: Message) {
-        let bytes: BytesMut = message.into();
-        self.connection.write_all(&bytes[..]).await;
+    /// Sends `message`. The connection counts as closed once writing to it
+    /// failed.
+    pub async fn send(&mut self, message: Message) -> Result<(), SendMessageError> {
+        let result = self.framed.send(message).await;
+        if let Err(SendMessageError::Io(_)) = result {
+            self.closed = true;
+        }
+        result
     }
 
-    async fn connect_to_remote_peer(config: &Config) -> Result<TcpStream> {
+    async fn connect_to_remote_peer(local_ip: Ipv4Addr, remote_ip: Ipv4Addr) -> Result<TcpStream> {
         info!(
             "connecting to remote peer, remote-ip={:?}, bgp-port={}",
-            config.remote_ip, BGP_PORT
+            remote_ip, BGP_PORT
         );
-        TcpStream::connect((config.remote_ip, BGP_PORT))
+        let socket = TcpSocket::new_v4()?;
+        socket
+            .bind((local_ip, 0).into())
+            .context(format!("cannot bind to local-ip={:?}", local_ip))?;
+        socket
+            .connect((remote_ip, BGP_PORT).into())
             .await
             .context(format!(
                 "cannot connect to remote peer, remote-ip={:?}, bgp-port={}",
-                config.remote_ip, BGP_PORT
+                remote_ip, BGP_PORT
             ))
     }
+}
 
-    async fn wait_connection_from_remote_peer(config: &Config) -> Result<TcpStream> {
+/// The daemon's single listener on the BGP port, handing each accepted
+/// connection to the peer configured for its source address.
+#[derive(Debug)]
+pub struct Listener {
+    listener: TcpListener,
+    peers: Mutex<HashMap<Ipv4Addr, UnboundedSender<Connection>>>,
+}
+
+impl Listener {
+    pub async fn bind(local_ip: Ipv4Addr) -> Result<Self> {
         info!(
-            "waiting connection from remote peer, local-ip={:?}, bgp-port={}",
-            config.local_ip, BGP_PORT
+            "waiting connection from remote peers, local-ip={:?}, bgp-port={}",
+            local_ip, BGP_PORT
         );
-        let listener = TcpListener::bind((config.local_ip, BGP_PORT)).await?;
+        let listener = TcpListener::bind((local_ip, BGP_PORT))
+            .await
+            .context(format!(
+                "cannot listen on local-ip={:?}, bgp-port={}",
+                local_ip, BGP_PORT
+            ))?;
+
+        Ok(Self {
+            listener,
+            peers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Connections from `remote_ip` are sent to `peer` from now on.
+    pub fn register(&self, remote_ip: Ipv4Addr, peer: UnboundedSender<Connection>) {
+        self.peers.lock().unwrap().insert(remote_ip, peer);
+    }
 
-        Ok(listener.accept().await?.0)
+    /// Connections from `remote_ip` are refused from now on.
+    pub fn unregister(&self, remote_ip: Ipv4Addr) {
+        self.peers.lock().unwrap().remove(&remote_ip);
+    }
+
+    pub async fn run(&self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, addr)) => self.dispatch(stream, addr),
+                Err(e) => warn!("cannot accept TCP connection, error={:?}", e),
+            }
+        }
+    }
+
+    fn dispatch(&self, stream: TcpStream, addr: SocketAddr) {
+        let peers = self.peers.lock().unwrap();
+        let peer = match addr.ip() {
+            IpAddr::V4(ip) => peers.get(&ip),
+            IpAddr::V6(_) => None,
+        };
+        let Some(peer) = peer else {
+            warn!("refusing connection from unknown peer, addr={:?}", addr);
+            return;
+        };
+
+        info!("connection accepted, remote-addr={:?}", addr);
+        if peer.send(Connection::from_stream(stream, false)).is_err() {
+            warn!("peer is gone, dropping connection, addr={:?}", addr);
+        }
     }
 }
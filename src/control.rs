@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tracing::warn;
+
+/// A command read from the control socket.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ControlCommand {
+    /// Re-read the configuration, as on SIGHUP.
+    Reload,
+}
+
+/// A command waiting for its outcome, which is written back to the client.
+#[derive(Debug)]
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: oneshot::Sender<Result<(), String>>,
+}
+
+impl ControlRequest {
+    pub fn reply(self, result: Result<(), String>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+/// A Unix socket taking one command per connection, answered with `ok` or
+/// `error: REASON` on one line:
+///
+/// ```text
+/// $ echo reload | socat - UNIX-CONNECT:/run/miibgpd.sock
+/// ok
+/// ```
+#[derive(Debug)]
+pub struct ControlSocket {
+    path: PathBuf,
+    requests: mpsc::UnboundedReceiver<ControlRequest>,
+    listener: JoinHandle<()>,
+}
+
+impl ControlSocket {
+    /// Listens on `path`, replacing the socket a previous run left behind.
+    pub fn bind(path: &Path) -> Result<Self> {
+        if path.exists() {
+            std::fs::remove_file(path).context(format!("cannot remove {}", path.display()))?;
+        }
+        let listener =
+            UnixListener::bind(path).context(format!("cannot listen on {}", path.display()))?;
+        let (sender, requests) = mpsc::unbounded_channel();
+        let listener = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve(stream, sender.clone()));
+                    }
+                    Err(e) => warn!("cannot accept control connection, error={:?}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_owned(),
+            requests,
+            listener,
+        })
+    }
+
+    /// Waits for the next command. Cancel safe.
+    pub async fn next(&mut self) -> Option<ControlRequest> {
+        self.requests.recv().await
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.listener.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reads the command of one connection and writes back its outcome.
+async fn serve(stream: UnixStream, requests: mpsc::UnboundedSender<ControlRequest>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    if let Err(e) = BufReader::new(reader).read_line(&mut line).await {
+        warn!("cannot read control command, error={:?}", e);
+        return;
+    }
+
+    let result = match line.trim() {
+        "reload" => {
+            let (reply, outcome) = oneshot::channel();
+            let request = ControlRequest {
+                command: ControlCommand::Reload,
+                reply,
+            };
+            match requests.send(request) {
+                Ok(()) => outcome
+                    .await
+                    .unwrap_or_else(|_| Err("daemon is shutting down".to_owned())),
+                Err(_) => Err("daemon is shutting down".to_owned()),
+            }
+        }
+        command => Err(format!("unknown command {command:?}")),
+    };
+    let response = match result {
+        Ok(()) => "ok\n".to_owned(),
+        Err(e) => format!("error: {e}\n"),
+    };
+    let _ = writer.write_all(response.as_bytes()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixStream,
+    };
+
+    use super::{ControlCommand, ControlSocket};
+
+    async fn send(path: &std::path::Path, command: &str) -> String {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        stream.write_all(command.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn control_socket_passes_on_commands_and_answers_with_their_outcome() {
+        let path = std::env::temp_dir().join(format!("miibgpd-{}.sock", std::process::id()));
+        let mut control = ControlSocket::bind(&path).unwrap();
+
+        let client = tokio::spawn({
+            let path = path.clone();
+            async move { send(&path, "reload\n").await }
+        });
+        let request = control.next().await.unwrap();
+        assert_eq!(request.command, ControlCommand::Reload);
+        request.reply(Err("no such file".to_owned()));
+        assert_eq!(client.await.unwrap(), "error: no such file\n");
+
+        assert_eq!(
+            send(&path, "restart\n").await,
+            "error: unknown command \"restart\"\n"
+        );
+
+        drop(control);
+        assert!(!path.exists());
+    }
+}
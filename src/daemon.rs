@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use futures::future::join_all;
+use tokio::{
+    sync::{mpsc::UnboundedSender, watch},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tracing::{info, warn};
+
+use crate::{
+    config::{Config, GlobalConfig, Mode},
+    connection::{Connection, Listener},
+    fib::Fib,
+    kernel::KernelRoutingTable,
+    peer::{Peer, PeerCommand},
+    routing::{IpNetwork, LocRib},
+};
+
+/// How long peers are given to close their sessions on shutdown.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to wait before retrying kernel route changes that failed, e.g.
+/// because the next hop's interface is not up yet.
+const FIB_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A peer running in its own task.
+#[derive(Debug)]
+struct RunningPeer {
+    commands: UnboundedSender<PeerCommand>,
+    connections: UnboundedSender<Connection>,
+    handle: JoinHandle<()>,
+}
+
+/// The peers, listener and RIB task running one configuration.
+/// [`Daemon::reload`] moves them to another configuration, only touching
+/// what changed.
+#[derive(Debug)]
+pub struct Daemon {
+    config: Config,
+    loc_rib: Arc<Mutex<LocRib>>,
+    global: watch::Sender<GlobalConfig>,
+    rib: JoinHandle<()>,
+    listener: Option<(Arc<Listener>, JoinHandle<()>)>,
+    peers: HashMap<Ipv4Addr, RunningPeer>,
+}
+
+impl Daemon {
+    pub async fn start(config: Config) -> Result<Self> {
+        let kernel_routing_table = KernelRoutingTable::new().await?;
+        let loc_rib = Arc::new(Mutex::new(LocRib::new(
+            &config.global,
+            &kernel_routing_table,
+        )));
+        let (global, global_changes) = watch::channel(config.global.clone());
+        let rib = tokio::spawn(run_rib(
+            global_changes,
+            Arc::clone(&loc_rib),
+            kernel_routing_table,
+            Fib::new()?,
+        ));
+
+        let mut daemon = Self {
+            config: Config {
+                global: config.global.clone(),
+                neighbors: vec![],
+            },
+            loc_rib,
+            global,
+            rib,
+            listener: None,
+            peers: HashMap::new(),
+        };
+        daemon.reload(config).await?;
+        Ok(daemon)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Switches to `config`: neighbors no longer configured are closed with
+    /// a Cease, new ones are started, and the others are handed their new
+    /// configuration to decide whether their session must be reset. If the
+    /// listener cannot be bound, nothing is changed.
+    pub async fn reload(&mut self, config: Config) -> Result<()> {
+        let listen = config.neighbors.iter().any(|n| n.mode != Mode::Active);
+        let rebind = listen
+            && (self.listener.is_none() || config.global.local_ip != self.config.global.local_ip);
+        let new_listener = if rebind {
+            Some(Arc::new(Listener::bind(config.global.local_ip).await?))
+        } else {
+            None
+        };
+
+        if config.global != self.config.global {
+            info!("global configuration changed");
+            self.global.send_replace(config.global.clone());
+        }
+
+        let removed: Vec<Ipv4Addr> = self
+            .peers
+            .keys()
+            .filter(|remote_ip| !config.neighbors.iter().any(|n| n.remote_ip == **remote_ip))
+            .copied()
+            .collect();
+        for remote_ip in removed {
+            info!("neighbor removed, remote-ip={:?}", remote_ip);
+            if let Some(peer) = self.peers.remove(&remote_ip) {
+                let _ = peer.commands.send(PeerCommand::Deconfigure);
+            }
+            if let Some((listener, _)) = &self.listener {
+                listener.unregister(remote_ip);
+            }
+        }
+
+        for neighbor in &config.neighbors {
+            if let Some(peer) = self.peers.get(&neighbor.remote_ip) {
+                let old = self
+                    .config
+                    .neighbors
+                    .iter()
+                    .find(|n| n.remote_ip == neighbor.remote_ip);
+                if old != Some(neighbor) || config.global != self.config.global {
+                    info!("neighbor changed, remote-ip={:?}", neighbor.remote_ip);
+                    let _ = peer.commands.send(PeerCommand::Reconfigure(
+                        config.global.clone(),
+                        neighbor.clone(),
+                    ));
+                }
+                continue;
+            }
+
+            info!("neighbor added, remote-ip={:?}", neighbor.remote_ip);
+            let mut peer = Peer::new(
+                config.global.clone(),
+                neighbor.clone(),
+                Arc::clone(&self.loc_rib),
+            );
+            let running = RunningPeer {
+                commands: peer.command_sender(),
+                connections: peer.connection_sender(),
+                handle: tokio::spawn(async move { peer.run().await }),
+            };
+            if let Some((listener, _)) = &self.listener {
+                listener.register(neighbor.remote_ip, running.connections.clone());
+            }
+            self.peers.insert(neighbor.remote_ip, running);
+        }
+
+        let replaced = !listen || new_listener.is_some();
+        if replaced && let Some((_, handle)) = self.listener.take() {
+            handle.abort();
+        }
+        if let Some(listener) = new_listener {
+            for (remote_ip, peer) in &self.peers {
+                listener.register(*remote_ip, peer.connections.clone());
+            }
+            let handle = tokio::spawn({
+                let listener = Arc::clone(&listener);
+                async move { listener.run().await }
+            });
+            self.listener = Some((listener, handle));
+        }
+
+        self.config = config;
+        Ok(())
+    }
+
+    /// Closes every session with a Cease, giving the peers up to
+    /// `SHUTDOWN_TIMEOUT` to send it, then stops every task, as the process
+    /// is about to exit.
+    pub async fn shutdown(self) {
+        let mut peers: Vec<JoinHandle<()>> = self
+            .peers
+            .into_values()
+            .map(|peer| {
+                let _ = peer.commands.send(PeerCommand::Shutdown);
+                peer.handle
+            })
+            .collect();
+        let stopped = join_all(peers.iter_mut());
+        if timeout(SHUTDOWN_TIMEOUT, stopped).await.is_err() {
+            warn!("peers did not stop in time, aborting them");
+        }
+
+        let mut handles = vec![self.rib];
+        handles.extend(self.listener.map(|(_, handle)| handle));
+        handles.extend(peers.into_iter().filter(|handle| !handle.is_finished()));
+        for handle in &handles {
+            handle.abort();
+        }
+        for handle in handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+/// Keeps the Loc-RIB shared by all peers in line with the kernel and the
+/// global configuration: originates networks, resolves next hops of newly
+/// learned paths and installs best paths into the FIB. Sleeps until the
+/// kernel, the Loc-RIB or the global configuration changes, or until it is
+/// time to retry installing routes the kernel refused.
+async fn run_rib(
+    mut global_changes: watch::Receiver<GlobalConfig>,
+    loc_rib: Arc<Mutex<LocRib>>,
+    mut kernel_routing_table: KernelRoutingTable,
+    mut fib: Fib,
+) {
+    let mut loc_rib_changes = loc_rib.lock().unwrap().subscribe();
+    let mut global = global_changes.borrow_and_update().clone();
+    let mut global_changed = false;
+    let mut kernel_changes: Vec<IpNetwork> = vec![];
+    loop {
+        {
+            let mut loc_rib = loc_rib.lock().unwrap();
+            if global_changed || !kernel_changes.is_empty() {
+                loc_rib.originate_networks(&global, &kernel_routing_table);
+            }
+            if global_changed {
+                loc_rib.invalidate_next_hops();
+            }
+            loc_rib.resolve_next_hops(&global, &kernel_routing_table, &kernel_changes);
+        }
+
+        let synced = fib.is_synced_with(&loc_rib.lock().unwrap());
+        if !synced {
+            fib.sync(&loc_rib, &global).await;
+        }
+        let retry = !fib.is_synced_with(&loc_rib.lock().unwrap());
+
+        global_changed = false;
+        kernel_changes.clear();
+        tokio::select! {
+            event = kernel_routing_table.next_event() => {
+                kernel_changes.extend(event.route_network());
+            }
+            Ok(()) = global_changes.changed() => {
+                global = global_changes.borrow_and_update().clone();
+                global_changed = true;
+            }
+            Ok(()) = loc_rib_changes.changed() => {}
+            _ = sleep(FIB_RETRY_INTERVAL), if retry => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::config::Config;
+    use crate::daemon::Daemon;
+
+    #[tokio::test]
+    async fn daemon_reload_adds_removes_and_keeps_neighbors() {
+        let config: Config = "64512 127.0.6.1 64513 127.0.6.2 passive \
+            neighbor 64514 127.0.6.3 active connect-retry 60"
+            .parse()
+            .unwrap();
+        let mut daemon = Daemon::start(config).await.unwrap();
+        assert!(daemon.listener.is_some());
+        let kept = Ipv4Addr::new(127, 0, 6, 3);
+        let kept_handle = daemon.peers[&kept].handle.id();
+
+        let config: Config = "64512 127.0.6.1 64514 127.0.6.3 active connect-retry 60 \
+            neighbor 64515 127.0.6.4 active"
+            .parse()
+            .unwrap();
+        daemon.reload(config.clone()).await.unwrap();
+
+        let mut remote_ips: Vec<Ipv4Addr> = daemon.peers.keys().copied().collect();
+        remote_ips.sort();
+        assert_eq!(remote_ips, vec![kept, Ipv4Addr::new(127, 0, 6, 4)]);
+        assert_eq!(daemon.peers[&kept].handle.id(), kept_handle);
+        assert!(daemon.listener.is_none());
+        assert_eq!(daemon.config(), &config);
+
+        daemon.shutdown().await;
+    }
+}
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::packets::notification::NotificationMessage;
+
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub struct ConfigParseError {
@@ -7,6 +9,37 @@ pub struct ConfigParseError {
     source: anyhow::Error,
 }
 
+/// Every problem found in a configuration file, each with the line it is
+/// on unless it concerns the file as a whole.
+#[derive(Error, Debug)]
+#[error("{}", format_config_file_errors(.errors))]
+pub struct ConfigFileError {
+    errors: Vec<(Option<usize>, anyhow::Error)>,
+}
+
+fn format_config_file_errors(errors: &[(Option<usize>, anyhow::Error)]) -> String {
+    errors
+        .iter()
+        .map(|(line, error)| match line {
+            Some(line) => format!("line {line}: {error:#}"),
+            None => format!("{error:#}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl From<Vec<(Option<usize>, anyhow::Error)>> for ConfigFileError {
+    fn from(errors: Vec<(Option<usize>, anyhow::Error)>) -> Self {
+        Self { errors }
+    }
+}
+
+impl ConfigFileError {
+    pub fn errors(&self) -> &[(Option<usize>, anyhow::Error)] {
+        &self.errors
+    }
+}
+
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub struct CreateConnectionError {
@@ -15,10 +48,48 @@ pub struct CreateConnectionError {
 }
 
 #[derive(Error, Debug)]
-#[error(transparent)]
+#[error("{error}")]
 pub struct ConvertBytesToBgpMessageError {
-    #[from]
-    source: anyhow::Error,
+    error: anyhow::Error,
+    notification: Option<NotificationMessage>,
+    in_notification: bool,
+}
+
+impl From<anyhow::Error> for ConvertBytesToBgpMessageError {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            error,
+            notification: None,
+            in_notification: false,
+        }
+    }
+}
+
+impl ConvertBytesToBgpMessageError {
+    /// Attaches the NOTIFICATION to send for this error, unless a decoder
+    /// closer to the offending bytes already attached a more specific one.
+    pub fn with_notification(mut self, notification: NotificationMessage) -> Self {
+        if !self.in_notification {
+            self.notification.get_or_insert(notification);
+        }
+        self
+    }
+
+    /// Marks the error as found in a received NOTIFICATION, which is never
+    /// answered with another one (RFC 4271 §4.5).
+    pub fn in_notification(mut self) -> Self {
+        self.in_notification = true;
+        self.notification = None;
+        self
+    }
+
+    pub fn is_in_notification(&self) -> bool {
+        self.in_notification
+    }
+
+    pub fn notification(&self) -> Option<&NotificationMessage> {
+        self.notification.as_ref()
+    }
 }
 
 #[derive(Error, Debug)]
@@ -28,9 +99,36 @@ pub struct ConvertbgpMessageToBytesError {
     source: anyhow::Error,
 }
 
+/// Error reading a message off a connection: either the transport failed or
+/// the bytes received are not a valid message.
+#[derive(Error, Debug)]
+pub enum ReceiveMessageError {
+    #[error("cannot read from connection: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Message(#[from] ConvertBytesToBgpMessageError),
+}
+
+/// Error writing a message to a connection: either the transport failed or
+/// the message cannot be encoded, e.g. because it is too long.
+#[derive(Error, Debug)]
+pub enum SendMessageError {
+    #[error("cannot write to connection: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Message(#[from] ConvertbgpMessageToBytesError),
+}
+
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub struct ConstructIpv4NetworkError {
     #[from]
     source: anyhow::Error,
 }
+
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub struct ConstructIpv6NetworkError {
+    #[from]
+    source: anyhow::Error,
+}
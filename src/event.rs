@@ -1,11 +1,70 @@
-use crate::packets::{keepalive::KeepaliveMessage, open::OpenMessage, update::UpdateMessage};
+use crate::packets::{
+    header::MessageType, keepalive::KeepaliveMessage, notification::NotificationMessage,
+    open::OpenMessage, route_refresh::RouteRefreshMessage, update::UpdateMessage,
+};
 
+/// BGP FSM events, in the order they are defined in RFC 4271 §8.1, followed
+/// by the ROUTE-REFRESH message of RFC 2918.
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub enum Event {
     ManualStart,
+    ManualStop,
+    AutomaticStart,
+    ManualStartWithPassiveTcpEstablishment,
+    AutomaticStartWithPassiveTcpEstablishment,
+    AutomaticStartWithDampPeerOscillations,
+    AutomaticStartWithDampPeerOscillationsAndPassiveTcpEstablishment,
+    AutomaticStop,
+    ConnectRetryTimerExpires,
+    HoldTimerExpires,
+    KeepaliveTimerExpires,
+    DelayOpenTimerExpires,
+    IdleHoldTimerExpires,
+    TcpConnectionValid,
+    TcpCrInvalid,
+    TcpCrAcked,
     TcpConnectionConfirmed,
+    TcpConnectionFails,
     BgpOpen(OpenMessage),
+    BgpOpenWithDelayOpenTimerRunning(OpenMessage),
+    BgpHeaderErr(NotificationMessage),
+    BgpOpenMsgErr(NotificationMessage),
+    OpenCollisionDump,
+    NotifMsgVerErr(NotificationMessage),
+    NotifMsg(NotificationMessage),
     KeepaliveMsg(KeepaliveMessage),
     UpdateMsg(UpdateMessage),
-    Established,
+    UpdateMsgErr(NotificationMessage),
+    RouteRefreshMsg(RouteRefreshMessage),
+}
+
+impl Event {
+    /// Events 1 and 3-7, which all ask the FSM to leave Idle.
+    pub fn is_start(&self) -> bool {
+        matches!(
+            self,
+            Event::ManualStart
+                | Event::AutomaticStart
+                | Event::ManualStartWithPassiveTcpEstablishment
+                | Event::AutomaticStartWithPassiveTcpEstablishment
+                | Event::AutomaticStartWithDampPeerOscillations
+                | Event::AutomaticStartWithDampPeerOscillationsAndPassiveTcpEstablishment
+        )
+    }
+
+    /// Type of the received message that raised this event, reported in the
+    /// data field of an RFC 6608 Finite State Machine Error.
+    pub fn message_type(&self) -> Option<MessageType> {
+        match self {
+            Event::BgpOpen(_) | Event::BgpOpenWithDelayOpenTimerRunning(_) => {
+                Some(MessageType::Open)
+            }
+            Event::BgpOpenMsgErr(_) => Some(MessageType::Open),
+            Event::UpdateMsg(_) | Event::UpdateMsgErr(_) => Some(MessageType::Update),
+            Event::NotifMsgVerErr(_) | Event::NotifMsg(_) => Some(MessageType::Notification),
+            Event::KeepaliveMsg(_) => Some(MessageType::Keepalive),
+            Event::RouteRefreshMsg(_) => Some(MessageType::RouteRefresh),
+            _ => None,
+        }
+    }
 }
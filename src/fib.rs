@@ -0,0 +1,294 @@
+use std::{collections::BTreeMap, net::IpAddr, sync::Mutex};
+
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use rtnetlink::{Handle, IpVersion, new_connection, packet::RouteMessage};
+use tracing::{info, warn};
+
+use crate::{
+    config::GlobalConfig,
+    routing::{IpNetwork, LocRib, PathSource},
+};
+
+/// Routing protocol ID our kernel routes are tagged with ("bgp" in
+/// /etc/iproute2/rt_protos), so they can be told apart from everyone else's.
+pub const ROUTE_PROTOCOL: u8 = 186;
+
+/// The errno the kernel answers the deletion of a route it does not have
+/// with.
+const ESRCH: i32 = 3;
+
+/// Best paths of Loc-RIB installed into the kernel's main routing table.
+#[derive(Debug)]
+pub struct Fib {
+    handle: Handle,
+    routes: BTreeMap<IpNetwork, IpAddr>,
+    loc_rib_version: Option<u64>,
+}
+
+impl Fib {
+    pub fn new() -> Result<Self> {
+        let (connection, handle, _) = new_connection().context("cannot open netlink connection")?;
+        tokio::spawn(connection);
+
+        Ok(Self {
+            handle,
+            routes: BTreeMap::new(),
+            loc_rib_version: None,
+        })
+    }
+
+    pub fn is_synced_with(&self, loc_rib: &LocRib) -> bool {
+        self.loc_rib_version == Some(loc_rib.version())
+    }
+
+    /// Installs the best paths of `loc_rib`, replacing routes whose
+    /// next hop changed and deleting those no longer there. Networks we
+    /// originate are already in the kernel and are left alone. The Loc-RIB is
+    /// only locked while working out the changes, not while applying them.
+    /// The FIB only counts as synced if every change was applied; otherwise
+    /// the next call retries those that failed.
+    pub async fn sync(&mut self, loc_rib: &Mutex<LocRib>, config: &GlobalConfig) {
+        let (version, installs, removals) = {
+            let loc_rib = loc_rib.lock().unwrap();
+            let (installs, removals) =
+                Self::diff(&self.routes, &loc_rib, &PathSource::local(config));
+            (loc_rib.version(), installs, removals)
+        };
+
+        let mut failed = false;
+        for network in removals {
+            match self.delete(network).await {
+                Ok(()) => {
+                    info!("route deleted from kernel, network={:?}", network);
+                    self.routes.remove(&network);
+                }
+                Err(e) => {
+                    warn!(
+                        "cannot delete route from kernel, network={:?}, error={:?}",
+                        network, e
+                    );
+                    failed = true;
+                }
+            }
+        }
+        for (network, next_hop) in installs {
+            match self.install(network, next_hop).await {
+                Ok(()) => {
+                    info!(
+                        "route installed into kernel, network={:?}, next-hop={:?}",
+                        network, next_hop
+                    );
+                    self.routes.insert(network, next_hop);
+                }
+                Err(e) => {
+                    warn!(
+                        "cannot install route into kernel, network={:?}, next-hop={:?}, error={:?}",
+                        network, next_hop, e
+                    );
+                    failed = true;
+                }
+            }
+        }
+        self.loc_rib_version = if failed { None } else { Some(version) };
+    }
+
+    /// Routes to install or replace, and networks to delete, for the kernel
+    /// to match the best paths of `loc_rib`.
+    fn diff(
+        routes: &BTreeMap<IpNetwork, IpAddr>,
+        loc_rib: &LocRib,
+        local: &PathSource,
+    ) -> (Vec<(IpNetwork, IpAddr)>, Vec<IpNetwork>) {
+        let best_routes: BTreeMap<IpNetwork, IpAddr> = loc_rib
+            .best_paths()
+            .filter(|(_, path)| path.source != *local)
+            .filter_map(|(network, path)| Some((*network, path.forwarding_next_hop(network)?)))
+            .collect();
+
+        let installs = best_routes
+            .iter()
+            .filter(|(network, next_hop)| routes.get(network) != Some(next_hop))
+            .map(|(network, next_hop)| (*network, *next_hop))
+            .collect();
+        let removals = routes
+            .keys()
+            .filter(|network| !best_routes.contains_key(network))
+            .copied()
+            .collect();
+        (installs, removals)
+    }
+
+    /// IPv6 routes take the global next hop of MP_REACH_NLRI; a next hop of
+    /// the other family than the network cannot be installed.
+    async fn install(&self, network: IpNetwork, next_hop: IpAddr) -> Result<()> {
+        let request = self.handle.route().add().protocol(ROUTE_PROTOCOL);
+        match (network, next_hop) {
+            (IpNetwork::V4(network), IpAddr::V4(next_hop)) => {
+                request
+                    .v4()
+                    .destination_prefix(network.ip(), network.prefix())
+                    .gateway(next_hop)
+                    .replace()
+                    .execute()
+                    .await?
+            }
+            (IpNetwork::V6(network), IpAddr::V6(next_hop)) => {
+                request
+                    .v6()
+                    .destination_prefix(network.ip(), network.prefix())
+                    .gateway(next_hop)
+                    .replace()
+                    .execute()
+                    .await?
+            }
+            _ => anyhow::bail!("next hop {next_hop} is not of the family of the network"),
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, network: IpNetwork) -> Result<()> {
+        let request = self.handle.route().add().protocol(ROUTE_PROTOCOL);
+        let message = match network {
+            IpNetwork::V4(network) => request
+                .v4()
+                .destination_prefix(network.ip(), network.prefix())
+                .message_mut()
+                .clone(),
+            IpNetwork::V6(network) => request
+                .v6()
+                .destination_prefix(network.ip(), network.prefix())
+                .message_mut()
+                .clone(),
+        };
+        match self.handle.route().del(message).execute().await {
+            // Already gone, e.g. with the interface it went through.
+            Err(rtnetlink::Error::NetlinkError(e)) if e.code == -ESRCH => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Deletes every kernel route of either family tagged with our protocol
+    /// ID, including those left behind by an earlier run.
+    pub async fn flush(&mut self) -> Result<()> {
+        let mut routes: Vec<RouteMessage> = vec![];
+        for version in [IpVersion::V4, IpVersion::V6] {
+            let found: Vec<RouteMessage> = self
+                .handle
+                .route()
+                .get(version)
+                .execute()
+                .try_filter(|route| futures::future::ready(route.header.protocol == ROUTE_PROTOCOL))
+                .try_collect()
+                .await?;
+            routes.extend(found);
+        }
+
+        info!("flushing routes from kernel, count={}", routes.len());
+        for route in routes {
+            self.handle.route().del(route).execute().await?;
+        }
+        self.routes.clear();
+        self.loc_rib_version = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    use crate::config::Config;
+    use crate::fib::Fib;
+    use crate::path_attribute::{AsPath, Ipv6NextHop, MpReachNlri, Origin, PathAttribute};
+    use crate::routing::{CandidatePath, IpNetwork, Ipv4Network, Ipv6Network, LocRib, PathSource};
+
+    fn learned_path(peer_address: Ipv4Addr, next_hop: Ipv4Addr) -> CandidatePath {
+        CandidatePath {
+            source: PathSource {
+                peer_address,
+                router_id: peer_address,
+                peer_as: 64513.into(),
+                ibgp: false,
+                local: false,
+            },
+            path_attributes: Arc::new(vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+                PathAttribute::NextHop(next_hop),
+            ]),
+            igp_metric: Some(0),
+            immediate_next_hop: None,
+        }
+    }
+
+    #[test]
+    fn fib_installs_changed_best_paths_and_deletes_withdrawn_ones() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active".parse().unwrap();
+        let local = PathSource::local(&config.global);
+        let peer = Ipv4Addr::new(127, 0, 0, 2);
+        let unchanged: IpNetwork = "10.100.210.0/24".parse::<Ipv4Network>().unwrap().into();
+        let changed: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let withdrawn: IpNetwork = "10.100.230.0/24".parse::<Ipv4Network>().unwrap().into();
+        let originated: IpNetwork = "10.100.240.0/24".parse::<Ipv4Network>().unwrap().into();
+
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(unchanged, learned_path(peer, peer));
+        loc_rib.insert(changed, learned_path(peer, Ipv4Addr::new(10, 0, 0, 9)));
+        loc_rib.insert(
+            originated,
+            CandidatePath {
+                source: local,
+                ..learned_path(peer, config.global.local_ip)
+            },
+        );
+        let routes = BTreeMap::from([
+            (unchanged, IpAddr::V4(peer)),
+            (changed, IpAddr::V4(peer)),
+            (withdrawn, IpAddr::V4(peer)),
+        ]);
+
+        let (installs, removals) = Fib::diff(&routes, &loc_rib, &local);
+
+        assert_eq!(installs, vec![(changed, "10.0.0.9".parse().unwrap())]);
+        assert_eq!(removals, vec![withdrawn]);
+    }
+
+    #[test]
+    fn fib_installs_ipv6_best_paths_via_mp_reach_next_hop() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active".parse().unwrap();
+        let peer = Ipv4Addr::new(127, 0, 0, 2);
+        let ipv4_network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let ipv6_network: IpNetwork = "2001:db8:100::/48".parse::<Ipv6Network>().unwrap().into();
+        // Both prefixes came in the same UPDATE and share its attributes.
+        let mut path = learned_path(peer, peer);
+        Arc::make_mut(&mut path.path_attributes).push(PathAttribute::MpReachNlri(MpReachNlri {
+            next_hop: Ipv6NextHop {
+                global: "2001:db8::2".parse().unwrap(),
+                link_local: None,
+            },
+            nlri: vec![],
+        }));
+
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(ipv4_network, path.clone());
+        loc_rib.insert(ipv6_network, path);
+
+        let (installs, removals) = Fib::diff(
+            &BTreeMap::new(),
+            &loc_rib,
+            &PathSource::local(&config.global),
+        );
+
+        assert_eq!(
+            installs,
+            vec![
+                (ipv4_network, IpAddr::V4(peer)),
+                (ipv6_network, "2001:db8::2".parse().unwrap()),
+            ]
+        );
+        assert!(removals.is_empty());
+    }
+}
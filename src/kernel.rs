@@ -0,0 +1,430 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use anyhow::{Context, Result};
+use futures::{StreamExt, TryStreamExt, channel::mpsc::UnboundedReceiver};
+use rtnetlink::{
+    IpVersion, new_connection,
+    packet::{
+        AF_INET, AF_INET6, AddressMessage, IFF_UP, LinkMessage, NetlinkMessage, NetlinkPayload,
+        RT_TABLE_LOCAL, RT_TABLE_MAIN, RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV4_ROUTE,
+        RTNLGRP_IPV6_IFADDR, RTNLGRP_IPV6_ROUTE, RTNLGRP_LINK, RouteMessage, RtnlMessage,
+        nlas::{address::Nla, route::Nla as RouteNla},
+    },
+    sys::{AsyncSocket, SocketAddr},
+};
+use tracing::{debug, info};
+
+use crate::{
+    fib::ROUTE_PROTOCOL,
+    routing::{IpNetwork, Ipv4Network, Ipv6Network},
+};
+
+/// Multicast groups whose notifications keep the mirror up to date.
+const GROUPS: [u32; 5] = [
+    RTNLGRP_LINK,
+    RTNLGRP_IPV4_IFADDR,
+    RTNLGRP_IPV4_ROUTE,
+    RTNLGRP_IPV6_IFADDR,
+    RTNLGRP_IPV6_ROUTE,
+];
+
+/// A route of one of the kernel's routing tables.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct KernelRoute {
+    pub network: IpNetwork,
+    pub table: u8,
+    pub gateway: Option<IpAddr>,
+    pub output_interface: Option<u32>,
+    pub metric: u32,
+    /// Who installed the route, e.g. `RTPROT_KERNEL` or `RTPROT_STATIC`.
+    pub protocol: u8,
+}
+
+/// An address assigned to an interface.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct KernelAddress {
+    pub interface: u32,
+    pub address: IpAddr,
+    pub prefix: u8,
+}
+
+/// A change of the kernel state, as announced on the netlink multicast
+/// groups.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum KernelEvent {
+    RouteAdded(KernelRoute),
+    RouteRemoved(KernelRoute),
+    AddressAdded(KernelAddress),
+    AddressRemoved(KernelAddress),
+    LinkChanged { interface: u32, up: bool },
+}
+
+impl KernelEvent {
+    /// The network of the route added or removed, if a route changed.
+    pub fn route_network(&self) -> Option<IpNetwork> {
+        match self {
+            KernelEvent::RouteAdded(route) | KernelEvent::RouteRemoved(route) => {
+                Some(route.network)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Mirror of the kernel's routes, addresses and link states, dumped once
+/// and then kept up to date from netlink multicast notifications instead
+/// of being read again.
+#[derive(Debug, Default)]
+pub struct KernelRoutingTable {
+    routes: BTreeSet<KernelRoute>,
+    addresses: BTreeSet<KernelAddress>,
+    links_up: BTreeSet<u32>,
+    /// Bumped on every change so users know when to look again.
+    version: u64,
+    messages: Option<UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>>,
+}
+
+impl KernelRoutingTable {
+    /// Subscribes to kernel notifications, then dumps the current state so
+    /// that no change in between is missed.
+    pub async fn new() -> Result<Self> {
+        let (mut connection, handle, messages) =
+            new_connection().context("cannot open netlink connection")?;
+        let groups = GROUPS
+            .iter()
+            .fold(0, |groups, group| groups | 1 << (group - 1));
+        connection
+            .socket_mut()
+            .socket_mut()
+            .bind(&SocketAddr::new(0, groups))
+            .context("cannot subscribe to netlink multicast groups")?;
+        tokio::spawn(connection);
+
+        let mut table = Self {
+            messages: Some(messages),
+            ..Self::default()
+        };
+        let links: Vec<LinkMessage> = handle.link().get().execute().try_collect().await?;
+        let addresses: Vec<AddressMessage> = handle.address().get().execute().try_collect().await?;
+        let mut routes: Vec<RouteMessage> = handle
+            .route()
+            .get(IpVersion::V4)
+            .execute()
+            .try_collect()
+            .await?;
+        routes.extend(
+            handle
+                .route()
+                .get(IpVersion::V6)
+                .execute()
+                .try_collect::<Vec<_>>()
+                .await?,
+        );
+        links
+            .into_iter()
+            .for_each(|l| _ = table.apply(RtnlMessage::NewLink(l)));
+        addresses
+            .into_iter()
+            .for_each(|a| _ = table.apply(RtnlMessage::NewAddress(a)));
+        routes
+            .into_iter()
+            .for_each(|r| _ = table.apply(RtnlMessage::NewRoute(r)));
+        info!(
+            "kernel routing table loaded, routes={}, addresses={}",
+            table.routes.len(),
+            table.addresses.len()
+        );
+
+        Ok(table)
+    }
+
+    /// Waits for the next notification that changes the mirror, applies it
+    /// and returns the change. Never completes once the netlink connection
+    /// is gone, or for a table not subscribed to notifications. Cancel safe.
+    pub async fn next_event(&mut self) -> KernelEvent {
+        loop {
+            let Some(messages) = self.messages.as_mut() else {
+                return std::future::pending().await;
+            };
+            let Some((message, _)) = messages.next().await else {
+                info!("netlink connection closed, kernel routing table no longer followed");
+                self.messages = None;
+                continue;
+            };
+            if let NetlinkPayload::InnerMessage(message) = message.payload
+                && let Some(event) = self.apply(message)
+            {
+                debug!("kernel state changed, event={:?}", event);
+                return event;
+            }
+        }
+    }
+
+    fn apply(&mut self, message: RtnlMessage) -> Option<KernelEvent> {
+        let event = match message {
+            RtnlMessage::NewRoute(route) => {
+                let route = kernel_route(&route)?;
+                self.routes
+                    .insert(route)
+                    .then_some(KernelEvent::RouteAdded(route))
+            }
+            RtnlMessage::DelRoute(route) => {
+                let route = kernel_route(&route)?;
+                self.routes
+                    .remove(&route)
+                    .then_some(KernelEvent::RouteRemoved(route))
+            }
+            RtnlMessage::NewAddress(address) => {
+                let address = kernel_address(&address)?;
+                self.addresses
+                    .insert(address)
+                    .then_some(KernelEvent::AddressAdded(address))
+            }
+            RtnlMessage::DelAddress(address) => {
+                let address = kernel_address(&address)?;
+                self.addresses
+                    .remove(&address)
+                    .then_some(KernelEvent::AddressRemoved(address))
+            }
+            RtnlMessage::NewLink(link) => {
+                let interface = link.header.index;
+                let up = link.header.flags & IFF_UP != 0;
+                let changed = if up {
+                    self.links_up.insert(interface)
+                } else {
+                    self.links_up.remove(&interface)
+                };
+                changed.then_some(KernelEvent::LinkChanged { interface, up })
+            }
+            RtnlMessage::DelLink(link) => {
+                let interface = link.header.index;
+                self.links_up
+                    .remove(&interface)
+                    .then_some(KernelEvent::LinkChanged {
+                        interface,
+                        up: false,
+                    })
+            }
+            _ => None,
+        }?;
+        self.version += 1;
+        Some(event)
+    }
+
+    pub(crate) fn insert_route(&mut self, route: KernelRoute) -> Option<KernelEvent> {
+        self.routes.insert(route).then_some(())?;
+        self.version += 1;
+        Some(KernelEvent::RouteAdded(route))
+    }
+
+    pub(crate) fn remove_route(&mut self, route: KernelRoute) -> Option<KernelEvent> {
+        self.routes.remove(&route).then_some(())?;
+        self.version += 1;
+        Some(KernelEvent::RouteRemoved(route))
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item = &KernelRoute> {
+        self.routes.iter()
+    }
+
+    /// Whether the main routing table has a route for exactly `network`.
+    pub fn contains(&self, network: &IpNetwork) -> bool {
+        self.routes
+            .iter()
+            .any(|r| r.network == *network && r.table == RT_TABLE_MAIN)
+    }
+
+    /// The most specific route of the main table towards `addr`, preferring
+    /// the lowest metric among equally specific ones.
+    pub fn lookup(&self, addr: IpAddr) -> Option<&KernelRoute> {
+        self.routes
+            .iter()
+            .filter(|r| r.table == RT_TABLE_MAIN && r.network.contains(addr))
+            .max_by_key(|r| (r.network.prefix(), Reverse(r.metric)))
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = &KernelAddress> {
+        self.addresses.iter()
+    }
+
+    pub fn is_link_up(&self, interface: u32) -> bool {
+        self.links_up.contains(&interface)
+    }
+}
+
+/// Routes of the local table are not forwarding routes and are left out,
+/// as are the ones we installed ourselves.
+fn kernel_route(route: &RouteMessage) -> Option<KernelRoute> {
+    if route.header.table == RT_TABLE_LOCAL || route.header.protocol == ROUTE_PROTOCOL {
+        return None;
+    }
+    let network = match route.destination_prefix() {
+        Some((IpAddr::V4(addr), prefix)) => Ipv4Network::new(addr, prefix).ok()?.into(),
+        Some((IpAddr::V6(addr), prefix)) => Ipv6Network::new(addr, prefix).ok()?.into(),
+        None if u16::from(route.header.address_family) == AF_INET => {
+            Ipv4Network::new(Ipv4Addr::UNSPECIFIED, 0).ok()?.into()
+        }
+        None if u16::from(route.header.address_family) == AF_INET6 => {
+            Ipv6Network::new(Ipv6Addr::UNSPECIFIED, 0).ok()?.into()
+        }
+        None => return None,
+    };
+
+    Some(KernelRoute {
+        network,
+        table: route.header.table,
+        gateway: route.gateway(),
+        output_interface: route.output_interface(),
+        metric: route
+            .nlas
+            .iter()
+            .find_map(|nla| match nla {
+                RouteNla::Priority(metric) => Some(*metric),
+                _ => None,
+            })
+            .unwrap_or(0),
+        protocol: route.header.protocol,
+    })
+}
+
+fn kernel_address(address: &AddressMessage) -> Option<KernelAddress> {
+    let bytes = address.nlas.iter().find_map(|nla| match nla {
+        Nla::Address(bytes) => Some(bytes),
+        _ => None,
+    })?;
+    let address_family = u16::from(address.header.family);
+    let ip = if address_family == AF_INET {
+        IpAddr::from(<[u8; 4]>::try_from(&bytes[..]).ok()?)
+    } else if address_family == AF_INET6 {
+        IpAddr::from(<[u8; 16]>::try_from(&bytes[..]).ok()?)
+    } else {
+        return None;
+    };
+
+    Some(KernelAddress {
+        interface: address.header.index,
+        address: ip,
+        prefix: address.header.prefix_len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{FutureExt, channel::mpsc::unbounded};
+    use rtnetlink::packet::{
+        AF_INET, NetlinkMessage, RT_TABLE_LOCAL, RT_TABLE_MAIN, RTPROT_STATIC, RouteMessage,
+        RtnlMessage, nlas::route::Nla,
+    };
+    use rtnetlink::sys::SocketAddr;
+
+    use crate::fib::ROUTE_PROTOCOL;
+    use crate::kernel::{KernelEvent, KernelRoute, KernelRoutingTable};
+    use crate::routing::{IpNetwork, Ipv4Network};
+
+    fn route_message(network: &str, table: u8, protocol: u8) -> RouteMessage {
+        let network: Ipv4Network = network.parse().unwrap();
+        let mut route = RouteMessage::default();
+        route.header.address_family = AF_INET as u8;
+        route.header.destination_prefix_length = network.prefix();
+        route.header.table = table;
+        route.header.protocol = protocol;
+        route
+            .nlas
+            .push(Nla::Destination(network.ip().octets().to_vec()));
+        route.nlas.push(Nla::Gateway(vec![10, 0, 0, 1]));
+        route
+    }
+
+    #[test]
+    fn kernel_routing_table_follows_route_notifications() {
+        let mut table = KernelRoutingTable::default();
+        let network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let route = KernelRoute {
+            network,
+            table: RT_TABLE_MAIN,
+            gateway: Some("10.0.0.1".parse().unwrap()),
+            output_interface: None,
+            metric: 0,
+            protocol: RTPROT_STATIC,
+        };
+
+        let event = table.apply(RtnlMessage::NewRoute(route_message(
+            "10.100.220.0/24",
+            RT_TABLE_MAIN,
+            RTPROT_STATIC,
+        )));
+        assert_eq!(event, Some(KernelEvent::RouteAdded(route)));
+        assert!(table.contains(&network));
+
+        let event = table.apply(RtnlMessage::DelRoute(route_message(
+            "10.100.220.0/24",
+            RT_TABLE_MAIN,
+            RTPROT_STATIC,
+        )));
+        assert_eq!(event, Some(KernelEvent::RouteRemoved(route)));
+        assert!(!table.contains(&network));
+        assert_eq!(table.version(), 2);
+    }
+
+    #[test]
+    fn kernel_routing_table_ignores_local_and_own_routes() {
+        let mut table = KernelRoutingTable::default();
+
+        let event = table.apply(RtnlMessage::NewRoute(route_message(
+            "10.100.220.1/32",
+            RT_TABLE_LOCAL,
+            RTPROT_STATIC,
+        )));
+        assert_eq!(event, None);
+
+        let event = table.apply(RtnlMessage::NewRoute(route_message(
+            "10.100.220.0/24",
+            RT_TABLE_MAIN,
+            ROUTE_PROTOCOL,
+        )));
+        assert_eq!(event, None);
+        assert_eq!(table.routes().count(), 0);
+        assert_eq!(table.version(), 0);
+    }
+
+    #[tokio::test]
+    async fn kernel_routing_table_waits_for_changing_notifications() {
+        let (sender, messages) = unbounded();
+        let mut table = KernelRoutingTable {
+            messages: Some(messages),
+            ..KernelRoutingTable::default()
+        };
+        for (network, table) in [
+            ("10.100.220.1/32", RT_TABLE_LOCAL),
+            ("10.100.220.0/24", RT_TABLE_MAIN),
+        ] {
+            let message = RtnlMessage::NewRoute(route_message(network, table, RTPROT_STATIC));
+            sender
+                .unbounded_send((NetlinkMessage::from(message), SocketAddr::new(0, 0)))
+                .unwrap();
+        }
+
+        let event = table.next_event().await;
+        let network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        assert_eq!(event.route_network(), Some(network));
+        assert!(table.contains(&network));
+
+        drop(sender);
+        assert!(table.next_event().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn kernel_routing_table_can_dump_kernel_routes() {
+        let network: IpNetwork = "192.168.0.0/16".parse::<Ipv4Network>().unwrap().into();
+        let table = KernelRoutingTable::new().await.unwrap();
+        assert!(table.contains(&network));
+    }
+}
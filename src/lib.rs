@@ -2,12 +2,17 @@
 
 mod bgp_type;
 pub mod config;
-mod connection;
+pub mod connection;
+pub mod control;
+pub mod daemon;
 mod error;
 mod event;
 mod event_queue;
-mod packets;
-mod path_attribute;
+pub mod fib;
+pub mod kernel;
+pub mod packets;
+pub mod path_attribute;
 pub mod peer;
-mod routing;
+pub mod routing;
 mod state;
+mod timer;
@@ -1,39 +1,125 @@
-use std::{env, str::FromStr, time::Duration};
+use std::{env, fmt, fs, path::PathBuf, process, str::FromStr};
 
-use miibgpd::{config::Config, peer::Peer};
-use tokio::time::sleep;
+use miibgpd::{
+    config::Config,
+    control::{ControlCommand, ControlRequest, ControlSocket},
+    daemon::Daemon,
+    fib::Fib,
+};
+use tokio::signal::unix::{SignalKind, signal};
 
-use tracing::info;
+use tracing::{info, warn};
 
 #[tokio::main]
 async fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let check = args.iter().any(|arg| arg == "--check");
+    args.retain(|arg| arg != "--check");
+    let mut control_path = None;
+    if let Some(i) = args.iter().position(|arg| arg == "--control") {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("--control requires a socket path");
+            process::exit(1);
+        };
+        control_path = Some(PathBuf::from(path));
+        args.drain(i..i + 2);
+    }
+    let config = match read_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    if check {
+        print!("{config}");
+        return;
+    }
+
     tracing_subscriber::fmt::init();
     info!("miibgpd started");
 
-    let config = env::args().skip(1).fold("".to_owned(), |mut acc, s| {
-        acc += &(s.to_owned() + " ");
-        acc
-    });
-    let config = config.trim_end();
-    let configs = vec![Config::from_str(&config).unwrap()];
+    let mut daemon = or_exit(Daemon::start(config).await, "cannot start");
+    let mut control = or_exit(
+        control_path
+            .map(|path| ControlSocket::bind(&path))
+            .transpose(),
+        "cannot start",
+    );
+    let mut hangup = or_exit(signal(SignalKind::hangup()), "cannot handle SIGHUP");
+    let mut terminate = or_exit(signal(SignalKind::terminate()), "cannot handle SIGTERM");
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+            _ = hangup.recv() => {
+                info!("reloading configuration on SIGHUP");
+                let _ = reload(&mut daemon, &args).await;
+            }
+            Some(request) = next_request(&mut control) => match request.command {
+                ControlCommand::Reload => {
+                    info!("reloading configuration on request");
+                    let result = reload(&mut daemon, &args).await;
+                    request.reply(result);
+                }
+            },
+        }
+    }
+
+    info!("shutting down");
+    let graceful = daemon.config().global.graceful;
+    daemon.shutdown().await;
 
-    let mut peers: Vec<Peer> = configs.into_iter().map(Peer::new).collect();
-    for peer in &mut peers {
-        peer.start();
+    if graceful {
+        info!("keeping installed routes in the kernel");
+        return;
     }
+    let mut fib = or_exit(Fib::new(), "cannot flush routes from kernel");
+    if let Err(e) = fib.flush().await {
+        warn!("cannot flush routes from kernel, error={:?}", e);
+    }
+}
 
-    let mut handles = vec![];
-    for mut peer in peers {
-        let handle = tokio::spawn(async move {
-            loop {
-                peer.next().await;
-                sleep(Duration::from_millis(10)).await;
-            }
-        });
-        handles.push(handle);
+/// The value of `result`, or exits reporting its error after `context`.
+fn or_exit<T, E: fmt::Display>(result: Result<T, E>, context: &str) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{context}: {e:#}");
+        process::exit(1);
+    })
+}
+
+/// Switches the daemon to the configuration read anew, keeping the running
+/// one if it cannot be read or applied.
+async fn reload(daemon: &mut Daemon, args: &[String]) -> Result<(), String> {
+    let result = match read_config(args) {
+        Ok(config) => daemon.reload(config).await.map_err(|e| format!("{e:#}")),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = &result {
+        warn!(
+            "cannot reload configuration, keeping the running one, error={}",
+            e
+        );
     }
+    result
+}
+
+/// The next command of the control socket; never ready without one.
+async fn next_request(control: &mut Option<ControlSocket>) -> Option<ControlRequest> {
+    match control {
+        Some(control) => control.next().await,
+        None => std::future::pending().await,
+    }
+}
 
-    for handle in handles {
-        let _ = handle.await;
+/// `--config PATH` reads the configuration file at PATH; otherwise the
+/// arguments are the one-line configuration.
+fn read_config(args: &[String]) -> Result<Config, String> {
+    if let [flag, path] = args
+        && flag == "--config"
+    {
+        let contents = fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        return Config::parse_file(&contents).map_err(|e| format!("{path}:\n{e}"));
     }
+    Config::from_str(&args.join(" ")).map_err(|e| format!("{e:#}"))
 }
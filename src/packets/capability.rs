@@ -0,0 +1,232 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::Context;
+use bytes::{BufMut, BytesMut};
+
+use crate::{
+    bgp_type::{Afi, AutonomousSystemNumber, Safi},
+    error::{ConfigParseError, ConvertBytesToBgpMessageError},
+};
+
+/// Optional parameter type of the Capabilities parameter (RFC 5492 §4).
+pub const CAPABILITIES_PARAMETER_TYPE: u8 = 2;
+
+/// A capability advertised in the Capabilities optional parameter of an
+/// OPEN message (RFC 5492). Capabilities we do not understand are kept as
+/// raw TLVs so they can still be compared and logged.
+#[derive(PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+pub enum Capability {
+    MultiprotocolExtensions { afi: Afi, safi: Safi },
+    RouteRefresh,
+    FourOctetAsNumber(AutonomousSystemNumber),
+    Unknown { code: u8, value: BytesMut },
+}
+
+impl Capability {
+    pub fn code(&self) -> u8 {
+        match self {
+            Capability::MultiprotocolExtensions { .. } => 1,
+            Capability::RouteRefresh => 2,
+            Capability::FourOctetAsNumber(_) => 65,
+            Capability::Unknown { code, .. } => *code,
+        }
+    }
+
+    /// Whether both capabilities announce the same feature. The four-octet
+    /// AS capability carries each speaker's own AS number, so only its code
+    /// is compared.
+    pub fn matches(&self, other: &Capability) -> bool {
+        match (self, other) {
+            (Capability::FourOctetAsNumber(_), Capability::FourOctetAsNumber(_)) => true,
+            _ => self == other,
+        }
+    }
+
+    /// Capabilities advertised by both speakers, in our order. Only these
+    /// may be used on the session.
+    pub fn negotiate(local: &[Capability], remote: &[Capability]) -> Vec<Capability> {
+        local
+            .iter()
+            .filter(|capability| remote.iter().any(|r| capability.matches(r)))
+            .cloned()
+            .collect()
+    }
+
+    /// Parses the value of one Capabilities optional parameter, which may
+    /// hold any number of capability TLVs.
+    pub fn from_u8_slice(bytes: &[u8]) -> Result<Vec<Capability>, ConvertBytesToBgpMessageError> {
+        let mut capabilities = vec![];
+        let mut i = 0;
+        while bytes.len() > i {
+            let [code, length, ..] = bytes[i..] else {
+                return Err(ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
+                    "truncated capability: {:?}",
+                    &bytes[i..]
+                )));
+            };
+            let value_start_index = i + 2;
+            let value_end_index = value_start_index + length as usize;
+            let value = bytes
+                .get(value_start_index..value_end_index)
+                .context(format!("truncated capability: {:?}", &bytes[i..]))?;
+
+            capabilities.push(Capability::from_code_and_value(code, value));
+            i = value_end_index;
+        }
+
+        Ok(capabilities)
+    }
+
+    fn from_code_and_value(code: u8, value: &[u8]) -> Capability {
+        let capability = match (code, value) {
+            (1, [afi_high, afi_low, _reserved, safi]) => {
+                Afi::try_from(u16::from_be_bytes([*afi_high, *afi_low]))
+                    .and_then(|afi| Ok((afi, Safi::try_from(*safi)?)))
+                    .ok()
+                    .map(|(afi, safi)| Capability::MultiprotocolExtensions { afi, safi })
+            }
+            (2, []) => Some(Capability::RouteRefresh),
+            (65, [a, b, c, d]) => Some(Capability::FourOctetAsNumber(
+                u32::from_be_bytes([*a, *b, *c, *d]).into(),
+            )),
+            _ => None,
+        };
+
+        capability.unwrap_or_else(|| Capability::Unknown {
+            code,
+            value: BytesMut::from(value),
+        })
+    }
+
+    /// Encodes the capabilities as a single Capabilities optional parameter.
+    pub fn optional_parameter(capabilities: &[Capability]) -> BytesMut {
+        let value: BytesMut = capabilities.iter().flat_map(BytesMut::from).collect();
+
+        let mut bytes = BytesMut::new();
+        bytes.put_u8(CAPABILITIES_PARAMETER_TYPE);
+        bytes.put_u8(value.len() as u8);
+        bytes.put(&value[..]);
+        bytes
+    }
+}
+
+impl From<&Capability> for BytesMut {
+    fn from(capability: &Capability) -> Self {
+        let mut value = BytesMut::new();
+        match capability {
+            Capability::MultiprotocolExtensions { afi, safi } => {
+                value.put_u16((*afi).into());
+                value.put_u8(0);
+                value.put_u8((*safi).into());
+            }
+            Capability::RouteRefresh => {}
+            Capability::FourOctetAsNumber(as_number) => value.put_u32((*as_number).into()),
+            Capability::Unknown { value: v, .. } => value.put(&v[..]),
+        }
+
+        let mut bytes = BytesMut::new();
+        bytes.put_u8(capability.code());
+        bytes.put_u8(value.len() as u8);
+        bytes.put(&value[..]);
+        bytes
+    }
+}
+
+/// Names accepted by the `require-capability` config option.
+impl FromStr for Capability {
+    type Err = ConfigParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ipv4-unicast" => Ok(Capability::MultiprotocolExtensions {
+                afi: Afi::Ipv4,
+                safi: Safi::Unicast,
+            }),
+            "ipv6-unicast" => Ok(Capability::MultiprotocolExtensions {
+                afi: Afi::Ipv6,
+                safi: Safi::Unicast,
+            }),
+            "route-refresh" => Ok(Capability::RouteRefresh),
+            _ => Err(ConfigParseError::from(anyhow::anyhow!(
+                "cannot parse {s} as capability"
+            ))),
+        }
+    }
+}
+
+/// The name [`Capability::from_str`] parses back.
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Capability::MultiprotocolExtensions {
+                afi: Afi::Ipv4,
+                safi: Safi::Unicast,
+            } => write!(f, "ipv4-unicast"),
+            Capability::MultiprotocolExtensions {
+                afi: Afi::Ipv6,
+                safi: Safi::Unicast,
+            } => write!(f, "ipv6-unicast"),
+            Capability::RouteRefresh => write!(f, "route-refresh"),
+            Capability::FourOctetAsNumber(_) => write!(f, "four-octet-as"),
+            Capability::Unknown { code, .. } => write!(f, "capability-{code}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_round_trip_and_keep_unknown_ones() {
+        let capabilities = vec![
+            Capability::MultiprotocolExtensions {
+                afi: Afi::Ipv4,
+                safi: Safi::Unicast,
+            },
+            Capability::RouteRefresh,
+            Capability::FourOctetAsNumber(4_200_000_000.into()),
+            Capability::Unknown {
+                code: 128,
+                value: BytesMut::from(&[1, 2, 3][..]),
+            },
+        ];
+
+        let bytes = Capability::optional_parameter(&capabilities);
+        assert_eq!(bytes[0], CAPABILITIES_PARAMETER_TYPE);
+        assert_eq!(bytes[1] as usize, bytes.len() - 2);
+        assert_eq!(
+            Capability::from_u8_slice(&bytes[2..]).unwrap(),
+            capabilities
+        );
+    }
+
+    #[test]
+    fn truncated_capability_is_rejected() {
+        assert!(Capability::from_u8_slice(&[1, 4, 0, 1]).is_err());
+        assert!(Capability::from_u8_slice(&[1]).is_err());
+    }
+
+    #[test]
+    fn negotiated_capabilities_are_the_intersection() {
+        let ipv4_unicast: Capability = "ipv4-unicast".parse().unwrap();
+        let local = vec![
+            ipv4_unicast.clone(),
+            Capability::RouteRefresh,
+            Capability::FourOctetAsNumber(64512.into()),
+        ];
+        let remote = vec![
+            Capability::Unknown {
+                code: 128,
+                value: BytesMut::new(),
+            },
+            Capability::FourOctetAsNumber(4_200_000_000.into()),
+            ipv4_unicast.clone(),
+        ];
+
+        assert_eq!(
+            Capability::negotiate(&local, &remote),
+            vec![ipv4_unicast, Capability::FourOctetAsNumber(64512.into())]
+        );
+    }
+}
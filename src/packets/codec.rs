@@ -0,0 +1,175 @@
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::error::{
+    ConvertBytesToBgpMessageError, ConvertbgpMessageToBytesError, ReceiveMessageError,
+    SendMessageError,
+};
+
+use super::{
+    header::HEADER_LENGTH,
+    message::Message,
+    notification::{ErrorCode, MessageHeaderErrorSubcode, NotificationMessage},
+};
+
+const MARKER: [u8; 16] = [0xff; 16];
+/// RFC 4271 §4.1: messages are between 19 and 4096 octets long.
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// Frames BGP messages on a byte stream: each starts with a 16-octet marker
+/// of all ones and a 2-octet length covering the whole message (RFC 4271
+/// §4.1). A bad marker or length is reported with the NOTIFICATION to send,
+/// as the stream cannot be resynchronized after it.
+#[derive(Debug, Default)]
+pub struct MessageCodec {
+    four_octet_as: bool,
+}
+
+impl MessageCodec {
+    /// Switches UPDATEs to four-octet AS numbers once both speakers
+    /// advertised the capability (RFC 6793).
+    pub fn set_four_octet_as(&mut self, four_octet_as: bool) {
+        self.four_octet_as = four_octet_as;
+    }
+}
+
+impl Decoder for MessageCodec {
+    type Item = Message;
+    type Error = ReceiveMessageError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+
+        if src[..MARKER.len()] != MARKER {
+            return Err(ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
+                "Invalid marker: {:?}",
+                &src[..MARKER.len()]
+            ))
+            .with_notification(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::ConnectionNotSynchronized),
+                BytesMut::new(),
+            ))
+            .into());
+        }
+
+        let length = usize::from(u16::from_be_bytes([src[16], src[17]]));
+        if !(HEADER_LENGTH..=MAX_MESSAGE_LENGTH).contains(&length) {
+            return Err(ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
+                "Invalid message length: {}",
+                length
+            ))
+            .with_notification(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength),
+                BytesMut::from(&src[16..18]),
+            ))
+            .into());
+        }
+
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+        let bytes = src.split_to(length);
+        Ok(Some(Message::decode(bytes, self.four_octet_as)?))
+    }
+}
+
+impl Encoder<Message> for MessageCodec {
+    type Error = SendMessageError;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let message = if self.four_octet_as {
+            message
+        } else {
+            message.into_two_octet_as()
+        };
+        let bytes = BytesMut::from(message);
+        if bytes.len() > MAX_MESSAGE_LENGTH {
+            return Err(ConvertbgpMessageToBytesError::from(anyhow::anyhow!(
+                "Message is too long: {}",
+                bytes.len()
+            ))
+            .into());
+        }
+        dst.extend_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_error(error: ReceiveMessageError) -> ConvertBytesToBgpMessageError {
+        match error {
+            ReceiveMessageError::Message(error) => error,
+            error => panic!("expected message error, but got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn codec_waits_for_whole_messages() {
+        let mut codec = MessageCodec::default();
+        let keepalive = BytesMut::from(Message::new_keepalive());
+        let mut src = BytesMut::from(&keepalive[..10]);
+
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&keepalive[10..]);
+        src.extend_from_slice(&keepalive[..]);
+
+        assert!(matches!(
+            codec.decode(&mut src).unwrap(),
+            Some(Message::Keepalive(_))
+        ));
+        assert!(matches!(
+            codec.decode(&mut src).unwrap(),
+            Some(Message::Keepalive(_))
+        ));
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn codec_rejects_bad_marker() {
+        let mut codec = MessageCodec::default();
+        let mut src = BytesMut::from(Message::new_keepalive());
+        src[3] = 0;
+
+        let error = message_error(codec.decode(&mut src).unwrap_err());
+
+        assert_eq!(
+            error.notification().unwrap().error_code,
+            ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::ConnectionNotSynchronized)
+        );
+    }
+
+    #[test]
+    fn codec_rejects_length_out_of_bounds() {
+        for length in [18u16, 4097] {
+            let mut codec = MessageCodec::default();
+            let mut src = BytesMut::from(Message::new_keepalive());
+            src[16..18].copy_from_slice(&length.to_be_bytes());
+
+            let error = message_error(codec.decode(&mut src).unwrap_err());
+            let notification = error.notification().unwrap();
+
+            assert_eq!(
+                notification.error_code,
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength)
+            );
+            assert_eq!(notification.data, BytesMut::from(&length.to_be_bytes()[..]));
+        }
+    }
+
+    #[test]
+    fn codec_encodes_messages_with_header() {
+        let mut codec = MessageCodec::default();
+        let mut dst = BytesMut::new();
+
+        codec.encode(Message::new_keepalive(), &mut dst).unwrap();
+
+        assert_eq!(dst, BytesMut::from(Message::new_keepalive()));
+        assert_eq!(&dst[..16], &MARKER[..]);
+    }
+}
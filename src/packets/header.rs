@@ -2,6 +2,10 @@ use bytes::{BufMut, BytesMut};
 
 use crate::error::ConvertBytesToBgpMessageError;
 
+use super::notification::{ErrorCode, MessageHeaderErrorSubcode, NotificationMessage};
+
+pub const HEADER_LENGTH: usize = 19;
+
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct Header {
     length: u16,
@@ -12,15 +16,47 @@ impl Header {
     pub fn new(length: u16, type_: MessageType) -> Self {
         Self { length, type_ }
     }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
 }
 
 impl TryFrom<BytesMut> for Header {
     type Error = ConvertBytesToBgpMessageError;
 
     fn try_from(bytes: BytesMut) -> Result<Self, Self::Error> {
+        if bytes.len() < HEADER_LENGTH {
+            return Err(Self::Error::from(anyhow::anyhow!(
+                "Header length is too short: {}",
+                bytes.len()
+            ))
+            .with_notification(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength),
+                BytesMut::from(&(bytes.len() as u16).to_be_bytes()[..]),
+            )));
+        }
+
         let marker = &bytes[0..16];
+        if marker != [255u8; 16] {
+            return Err(
+                Self::Error::from(anyhow::anyhow!("Invalid marker: {:?}", marker))
+                    .with_notification(NotificationMessage::new(
+                        ErrorCode::MessageHeaderError(
+                            MessageHeaderErrorSubcode::ConnectionNotSynchronized,
+                        ),
+                        BytesMut::new(),
+                    )),
+            );
+        }
+
         let length = u16::from_be_bytes([bytes[16], bytes[17]]);
-        let type_ = bytes[18].try_into()?;
+        let type_ = MessageType::try_from(bytes[18]).map_err(|e| {
+            e.with_notification(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageType),
+                BytesMut::from(&bytes[18..19]),
+            ))
+        })?;
         Ok(Self { length, type_ })
     }
 }
@@ -46,6 +82,8 @@ pub enum MessageType {
     Open,
     Keepalive,
     Update,
+    Notification,
+    RouteRefresh,
 }
 
 impl TryFrom<u8> for MessageType {
@@ -55,7 +93,9 @@ impl TryFrom<u8> for MessageType {
         match value {
             1 => Ok(Self::Open),
             2 => Ok(Self::Update),
+            3 => Ok(Self::Notification),
             4 => Ok(Self::Keepalive),
+            5 => Ok(Self::RouteRefresh),
             _ => Err(Self::Error::from(anyhow::anyhow!(
                 "Invalid message type: {}",
                 value
@@ -69,7 +109,9 @@ impl From<MessageType> for u8 {
         match value {
             MessageType::Open => 1,
             MessageType::Update => 2,
+            MessageType::Notification => 3,
             MessageType::Keepalive => 4,
+            MessageType::RouteRefresh => 5,
         }
     }
 }
@@ -78,7 +120,7 @@ impl From<MessageType> for u8 {
 mod tests {
     use bytes::BytesMut;
 
-    use super::{Header, MessageType};
+    use super::{ErrorCode, Header, MessageHeaderErrorSubcode, MessageType};
 
     #[test]
     fn convert_bytes_to_header_and_header_to_bytes() {
@@ -88,4 +130,27 @@ mod tests {
 
         assert_eq!(header, header2);
     }
+
+    #[test]
+    fn header_with_unknown_message_type_carries_bad_message_type_notification() {
+        let mut header_bytes: BytesMut = Header::new(19, MessageType::Keepalive).into();
+        header_bytes[18] = 7;
+        let error = Header::try_from(header_bytes).unwrap_err();
+
+        assert_eq!(
+            error.notification().unwrap().error_code,
+            ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageType)
+        );
+    }
+
+    #[test]
+    fn truncated_header_carries_bad_message_length_notification() {
+        let header_bytes: BytesMut = Header::new(19, MessageType::Keepalive).into();
+        let error = Header::try_from(BytesMut::from(&header_bytes[..18])).unwrap_err();
+
+        assert_eq!(
+            error.notification().unwrap().error_code,
+            ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength)
+        );
+    }
 }
@@ -2,40 +2,85 @@ use std::net::Ipv4Addr;
 
 use bytes::BytesMut;
 
-use crate::{bgp_type::AutonomousSystemNumber, error::ConvertBytesToBgpMessageError};
+use crate::{
+    bgp_type::{AutonomousSystemNumber, HoldTime},
+    error::ConvertBytesToBgpMessageError,
+};
 
 use super::{
-    header::{Header, MessageType},
+    capability::Capability,
+    header::{HEADER_LENGTH, Header, MessageType},
     keepalive::KeepaliveMessage,
+    notification::{
+        ErrorCode, MessageHeaderErrorSubcode, NotificationMessage, OpenMessageErrorSubcode,
+        UpdateMessageErrorSubcode,
+    },
     open::OpenMessage,
+    route_refresh::RouteRefreshMessage,
     update::UpdateMessage,
 };
 
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Message {
     Open(OpenMessage),
     Keepalive(KeepaliveMessage),
     Update(UpdateMessage),
+    Notification(NotificationMessage),
+    RouteRefresh(RouteRefreshMessage),
 }
 
 impl TryFrom<BytesMut> for Message {
     type Error = ConvertBytesToBgpMessageError;
 
     fn try_from(bytes: BytesMut) -> Result<Self, Self::Error> {
-        let header_bytes_length = 19;
+        Self::decode(bytes, true)
+    }
+}
+
+impl Message {
+    /// Decodes a message received on a session whose AS numbers are
+    /// `four_octet_as` wide; only UPDATEs depend on it.
+    pub fn decode(
+        bytes: BytesMut,
+        four_octet_as: bool,
+    ) -> Result<Self, ConvertBytesToBgpMessageError> {
+        let header = Header::try_from(bytes.clone())?;
 
-        if bytes.len() < header_bytes_length {
-            return Err(ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
-                "Message length is too short: {}",
+        // RFC 4271 §6.1: the length must cover the whole message and at
+        // least the fixed part of its type.
+        let length = usize::from(header.length());
+        let valid_length = match header.type_ {
+            MessageType::Open => length >= 29,
+            MessageType::Update => length >= 23,
+            MessageType::Notification => length >= 21,
+            MessageType::Keepalive => length == HEADER_LENGTH,
+            MessageType::RouteRefresh => length == 23,
+        };
+        if length != bytes.len() || !valid_length {
+            let error = ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
+                "Invalid length {} for {:?} message of {} bytes",
+                length,
+                header.type_,
                 bytes.len()
+            ));
+            let error = match header.type_ {
+                MessageType::Notification => error.in_notification(),
+                _ => error,
+            };
+            return Err(error.with_notification(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength),
+                BytesMut::from(&header.length().to_be_bytes()[..]),
             )));
         }
 
-        let header = Header::try_from(BytesMut::from(&bytes[0..header_bytes_length]))?;
-
         match header.type_ {
             MessageType::Open => {
-                let open_message = OpenMessage::try_from(bytes)?;
+                let open_message = OpenMessage::try_from(bytes).map_err(|e| {
+                    e.with_notification(NotificationMessage::new(
+                        ErrorCode::OpenMessageError(OpenMessageErrorSubcode::Unspecific),
+                        BytesMut::new(),
+                    ))
+                })?;
                 Ok(Self::Open(open_message))
             }
             MessageType::Keepalive => {
@@ -43,9 +88,33 @@ impl TryFrom<BytesMut> for Message {
                 Ok(Self::Keepalive(keepalive_message))
             }
             MessageType::Update => {
-                let update_message = UpdateMessage::try_from(bytes)?;
+                let update_message = UpdateMessage::decode(bytes, four_octet_as).map_err(|e| {
+                    e.with_notification(NotificationMessage::new(
+                        ErrorCode::UpdateMessageError(
+                            UpdateMessageErrorSubcode::MalformedAttributeList,
+                        ),
+                        BytesMut::new(),
+                    ))
+                })?;
                 Ok(Self::Update(update_message))
             }
+            MessageType::Notification => {
+                let notification_message = NotificationMessage::try_from(bytes)
+                    .map_err(ConvertBytesToBgpMessageError::in_notification)?;
+                Ok(Self::Notification(notification_message))
+            }
+            MessageType::RouteRefresh => {
+                let route_refresh_message = RouteRefreshMessage::try_from(bytes)?;
+                Ok(Self::RouteRefresh(route_refresh_message))
+            }
+        }
+    }
+
+    /// Converts an UPDATE for a session without four-octet AS numbers.
+    pub fn into_two_octet_as(self) -> Self {
+        match self {
+            Message::Update(update) => Message::Update(update.into_two_octet_as()),
+            message => message,
         }
     }
 }
@@ -56,13 +125,20 @@ impl From<Message> for BytesMut {
             Message::Open(open) => open.into(),
             Message::Keepalive(keepalive) => keepalive.into(),
             Message::Update(update) => update.into(),
+            Message::Notification(notification) => notification.into(),
+            Message::RouteRefresh(route_refresh) => route_refresh.into(),
         }
     }
 }
 
 impl Message {
-    pub fn new_open(my_as_number: AutonomousSystemNumber, my_ip_addr: Ipv4Addr) -> Self {
-        let open_message = OpenMessage::new(my_as_number, my_ip_addr);
+    pub fn new_open(
+        my_as_number: AutonomousSystemNumber,
+        my_ip_addr: Ipv4Addr,
+        hold_time: HoldTime,
+        capabilities: Vec<Capability>,
+    ) -> Self {
+        let open_message = OpenMessage::new(my_as_number, my_ip_addr, hold_time, capabilities);
         Self::Open(open_message)
     }
 
@@ -70,4 +146,92 @@ impl Message {
         let keepalive_message = KeepaliveMessage::new();
         Self::Keepalive(keepalive_message)
     }
+
+    pub fn new_notification(error_code: ErrorCode, data: BytesMut) -> Self {
+        let notification_message = NotificationMessage::new(error_code, data);
+        Self::Notification(notification_message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bgp_type::{Afi, Safi};
+
+    use super::*;
+
+    #[test]
+    fn convert_bytes_to_notification_message() {
+        let message = Message::new_notification(ErrorCode::HoldTimerExpired, BytesMut::new());
+        let message_bytes: BytesMut = message.into();
+        let message = Message::try_from(message_bytes).unwrap();
+
+        assert!(matches!(
+            message,
+            Message::Notification(NotificationMessage {
+                error_code: ErrorCode::HoldTimerExpired,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn malformed_open_message_carries_open_message_error_notification() {
+        let message = Message::new_open(
+            64512.into(),
+            "127.0.0.1".parse().unwrap(),
+            HoldTime::new(),
+            vec![],
+        );
+        let mut message_bytes: BytesMut = message.into();
+        message_bytes[19] = 5;
+        let error = Message::try_from(message_bytes).unwrap_err();
+
+        assert_eq!(
+            error.notification().unwrap().error_code,
+            ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnsupportedVersionNumber)
+        );
+    }
+
+    #[test]
+    fn truncated_messages_are_rejected_without_panicking() {
+        let messages = [
+            Message::new_open(
+                64512.into(),
+                "127.0.0.1".parse().unwrap(),
+                HoldTime::new(),
+                vec!["ipv4-unicast".parse().unwrap()],
+            ),
+            Message::new_keepalive(),
+            Message::Update(UpdateMessage::new(
+                std::sync::Arc::new(vec![]),
+                vec!["10.0.0.0/8".parse().unwrap()],
+                vec!["10.1.0.0/16".parse().unwrap()],
+            )),
+            Message::new_notification(ErrorCode::HoldTimerExpired, BytesMut::from(&[1][..])),
+            Message::RouteRefresh(RouteRefreshMessage::new(Afi::Ipv4, Safi::Unicast)),
+        ];
+
+        for message in messages {
+            let message_bytes: BytesMut = message.into();
+            for length in 0..message_bytes.len() {
+                let truncated = BytesMut::from(&message_bytes[..length]);
+                assert!(Message::try_from(truncated).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn keepalive_longer_than_header_carries_bad_message_length_notification() {
+        let mut message_bytes: BytesMut = Message::new_keepalive().into();
+        message_bytes.extend_from_slice(&[0]);
+        message_bytes[16..18].copy_from_slice(&20u16.to_be_bytes());
+        let error = Message::try_from(message_bytes).unwrap_err();
+        let notification = error.notification().unwrap();
+
+        assert_eq!(
+            notification.error_code,
+            ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength)
+        );
+        assert_eq!(notification.data, BytesMut::from(&20u16.to_be_bytes()[..]));
+    }
 }
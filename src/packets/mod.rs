@@ -1,5 +1,9 @@
+pub mod capability;
+pub mod codec;
 pub mod header;
 pub mod keepalive;
 pub mod message;
+pub mod notification;
 pub mod open;
+pub mod route_refresh;
 pub mod update;
@@ -0,0 +1,394 @@
+use bytes::{BufMut, BytesMut};
+
+use crate::error::ConvertBytesToBgpMessageError;
+
+use super::header::{Header, MessageType};
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct NotificationMessage {
+    header: Header,
+    pub error_code: ErrorCode,
+    pub data: BytesMut,
+}
+
+impl NotificationMessage {
+    pub fn new(error_code: ErrorCode, data: BytesMut) -> Self {
+        let header_minimum_length = 19u16;
+        let header = Header::new(
+            header_minimum_length + 2 + data.len() as u16,
+            MessageType::Notification,
+        );
+
+        Self {
+            header,
+            error_code,
+            data,
+        }
+    }
+}
+
+impl TryFrom<BytesMut> for NotificationMessage {
+    type Error = ConvertBytesToBgpMessageError;
+
+    fn try_from(bytes: BytesMut) -> Result<Self, Self::Error> {
+        if bytes.len() < 21 {
+            return Err(Self::Error::from(anyhow::anyhow!(
+                "Notification message length is too short: {}",
+                bytes.len()
+            )));
+        }
+
+        let header = Header::try_from(BytesMut::from(&bytes[0..19]))?;
+        let error_code = ErrorCode::try_from((bytes[19], bytes[20]))?;
+        let data = BytesMut::from(&bytes[21..]);
+
+        Ok(Self {
+            header,
+            error_code,
+            data,
+        })
+    }
+}
+
+impl From<NotificationMessage> for BytesMut {
+    fn from(message: NotificationMessage) -> Self {
+        let mut bytes = BytesMut::new();
+        let (error_code, error_subcode): (u8, u8) = message.error_code.into();
+
+        bytes.put::<BytesMut>(message.header.into());
+        bytes.put_u8(error_code);
+        bytes.put_u8(error_subcode);
+        bytes.put(&message.data[..]);
+
+        bytes
+    }
+}
+
+/// Error Code and Error Subcode of a NOTIFICATION message (RFC 4271 §4.5).
+/// Subcodes defined after this list was written, like Cease Hard Reset
+/// (RFC 8538), decode as `Unknown` so they can still be logged.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum ErrorCode {
+    MessageHeaderError(MessageHeaderErrorSubcode),
+    OpenMessageError(OpenMessageErrorSubcode),
+    UpdateMessageError(UpdateMessageErrorSubcode),
+    HoldTimerExpired,
+    FiniteStateMachineError(FiniteStateMachineErrorSubcode),
+    Cease(CeaseSubcode),
+}
+
+impl TryFrom<(u8, u8)> for ErrorCode {
+    type Error = ConvertBytesToBgpMessageError;
+
+    fn try_from((code, subcode): (u8, u8)) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(Self::MessageHeaderError(subcode.into())),
+            2 => Ok(Self::OpenMessageError(subcode.into())),
+            3 => Ok(Self::UpdateMessageError(subcode.into())),
+            4 => Ok(Self::HoldTimerExpired),
+            5 => Ok(Self::FiniteStateMachineError(subcode.into())),
+            6 => Ok(Self::Cease(subcode.into())),
+            _ => Err(Self::Error::from(anyhow::anyhow!(
+                "Invalid error code: {}",
+                code
+            ))),
+        }
+    }
+}
+
+impl From<ErrorCode> for (u8, u8) {
+    fn from(value: ErrorCode) -> Self {
+        match value {
+            ErrorCode::MessageHeaderError(subcode) => (1, subcode.into()),
+            ErrorCode::OpenMessageError(subcode) => (2, subcode.into()),
+            ErrorCode::UpdateMessageError(subcode) => (3, subcode.into()),
+            ErrorCode::HoldTimerExpired => (4, 0),
+            ErrorCode::FiniteStateMachineError(subcode) => (5, subcode.into()),
+            ErrorCode::Cease(subcode) => (6, subcode.into()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum MessageHeaderErrorSubcode {
+    Unspecific,
+    ConnectionNotSynchronized,
+    BadMessageLength,
+    BadMessageType,
+    Unknown(u8),
+}
+
+impl From<u8> for MessageHeaderErrorSubcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Unspecific,
+            1 => Self::ConnectionNotSynchronized,
+            2 => Self::BadMessageLength,
+            3 => Self::BadMessageType,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<MessageHeaderErrorSubcode> for u8 {
+    fn from(value: MessageHeaderErrorSubcode) -> Self {
+        match value {
+            MessageHeaderErrorSubcode::Unspecific => 0,
+            MessageHeaderErrorSubcode::ConnectionNotSynchronized => 1,
+            MessageHeaderErrorSubcode::BadMessageLength => 2,
+            MessageHeaderErrorSubcode::BadMessageType => 3,
+            MessageHeaderErrorSubcode::Unknown(value) => value,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum OpenMessageErrorSubcode {
+    Unspecific,
+    UnsupportedVersionNumber,
+    BadPeerAs,
+    BadBgpIdentifier,
+    UnsupportedOptionalParameter,
+    UnacceptableHoldTime,
+    UnsupportedCapability,
+    Unknown(u8),
+}
+
+impl From<u8> for OpenMessageErrorSubcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Unspecific,
+            1 => Self::UnsupportedVersionNumber,
+            2 => Self::BadPeerAs,
+            3 => Self::BadBgpIdentifier,
+            4 => Self::UnsupportedOptionalParameter,
+            6 => Self::UnacceptableHoldTime,
+            7 => Self::UnsupportedCapability,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<OpenMessageErrorSubcode> for u8 {
+    fn from(value: OpenMessageErrorSubcode) -> Self {
+        match value {
+            OpenMessageErrorSubcode::Unspecific => 0,
+            OpenMessageErrorSubcode::UnsupportedVersionNumber => 1,
+            OpenMessageErrorSubcode::BadPeerAs => 2,
+            OpenMessageErrorSubcode::BadBgpIdentifier => 3,
+            OpenMessageErrorSubcode::UnsupportedOptionalParameter => 4,
+            OpenMessageErrorSubcode::UnacceptableHoldTime => 6,
+            OpenMessageErrorSubcode::UnsupportedCapability => 7,
+            OpenMessageErrorSubcode::Unknown(value) => value,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum UpdateMessageErrorSubcode {
+    Unspecific,
+    MalformedAttributeList,
+    UnrecognizedWellKnownAttribute,
+    MissingWellKnownAttribute,
+    AttributeFlagsError,
+    AttributeLengthError,
+    InvalidOriginAttribute,
+    InvalidNextHopAttribute,
+    OptionalAttributeError,
+    InvalidNetworkField,
+    MalformedAsPath,
+    Unknown(u8),
+}
+
+impl From<u8> for UpdateMessageErrorSubcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Unspecific,
+            1 => Self::MalformedAttributeList,
+            2 => Self::UnrecognizedWellKnownAttribute,
+            3 => Self::MissingWellKnownAttribute,
+            4 => Self::AttributeFlagsError,
+            5 => Self::AttributeLengthError,
+            6 => Self::InvalidOriginAttribute,
+            8 => Self::InvalidNextHopAttribute,
+            9 => Self::OptionalAttributeError,
+            10 => Self::InvalidNetworkField,
+            11 => Self::MalformedAsPath,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<UpdateMessageErrorSubcode> for u8 {
+    fn from(value: UpdateMessageErrorSubcode) -> Self {
+        match value {
+            UpdateMessageErrorSubcode::Unspecific => 0,
+            UpdateMessageErrorSubcode::MalformedAttributeList => 1,
+            UpdateMessageErrorSubcode::UnrecognizedWellKnownAttribute => 2,
+            UpdateMessageErrorSubcode::MissingWellKnownAttribute => 3,
+            UpdateMessageErrorSubcode::AttributeFlagsError => 4,
+            UpdateMessageErrorSubcode::AttributeLengthError => 5,
+            UpdateMessageErrorSubcode::InvalidOriginAttribute => 6,
+            UpdateMessageErrorSubcode::InvalidNextHopAttribute => 8,
+            UpdateMessageErrorSubcode::OptionalAttributeError => 9,
+            UpdateMessageErrorSubcode::InvalidNetworkField => 10,
+            UpdateMessageErrorSubcode::MalformedAsPath => 11,
+            UpdateMessageErrorSubcode::Unknown(value) => value,
+        }
+    }
+}
+
+/// Finite State Machine Error subcodes defined in RFC 6608.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum FiniteStateMachineErrorSubcode {
+    Unspecified,
+    ReceiveUnexpectedMessageInOpenSentState,
+    ReceiveUnexpectedMessageInOpenConfirmState,
+    ReceiveUnexpectedMessageInEstablishedState,
+    Unknown(u8),
+}
+
+impl From<u8> for FiniteStateMachineErrorSubcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Unspecified,
+            1 => Self::ReceiveUnexpectedMessageInOpenSentState,
+            2 => Self::ReceiveUnexpectedMessageInOpenConfirmState,
+            3 => Self::ReceiveUnexpectedMessageInEstablishedState,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<FiniteStateMachineErrorSubcode> for u8 {
+    fn from(value: FiniteStateMachineErrorSubcode) -> Self {
+        match value {
+            FiniteStateMachineErrorSubcode::Unspecified => 0,
+            FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenSentState => 1,
+            FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenConfirmState => 2,
+            FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInEstablishedState => 3,
+            FiniteStateMachineErrorSubcode::Unknown(value) => value,
+        }
+    }
+}
+
+/// Cease subcodes defined in RFC 4486.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum CeaseSubcode {
+    Unspecific,
+    MaximumNumberOfPrefixesReached,
+    AdministrativeShutdown,
+    PeerDeconfigured,
+    AdministrativeReset,
+    ConnectionRejected,
+    OtherConfigurationChange,
+    ConnectionCollisionResolution,
+    OutOfResources,
+    Unknown(u8),
+}
+
+impl From<u8> for CeaseSubcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Unspecific,
+            1 => Self::MaximumNumberOfPrefixesReached,
+            2 => Self::AdministrativeShutdown,
+            3 => Self::PeerDeconfigured,
+            4 => Self::AdministrativeReset,
+            5 => Self::ConnectionRejected,
+            6 => Self::OtherConfigurationChange,
+            7 => Self::ConnectionCollisionResolution,
+            8 => Self::OutOfResources,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<CeaseSubcode> for u8 {
+    fn from(value: CeaseSubcode) -> Self {
+        match value {
+            CeaseSubcode::Unspecific => 0,
+            CeaseSubcode::MaximumNumberOfPrefixesReached => 1,
+            CeaseSubcode::AdministrativeShutdown => 2,
+            CeaseSubcode::PeerDeconfigured => 3,
+            CeaseSubcode::AdministrativeReset => 4,
+            CeaseSubcode::ConnectionRejected => 5,
+            CeaseSubcode::OtherConfigurationChange => 6,
+            CeaseSubcode::ConnectionCollisionResolution => 7,
+            CeaseSubcode::OutOfResources => 8,
+            CeaseSubcode::Unknown(value) => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_bytes_to_notification_message_and_notification_message_to_bytes() {
+        let notification_message = NotificationMessage::new(
+            ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnsupportedVersionNumber),
+            BytesMut::from(&[0u8, 4][..]),
+        );
+        let notification_message_bytes: BytesMut = notification_message.clone().into();
+        let notification_message2: NotificationMessage =
+            notification_message_bytes.try_into().unwrap();
+
+        assert_eq!(notification_message, notification_message2);
+    }
+
+    #[test]
+    fn convert_notification_message_to_bytes() {
+        let notification_message = NotificationMessage::new(
+            ErrorCode::Cease(CeaseSubcode::ConnectionCollisionResolution),
+            BytesMut::new(),
+        );
+        let notification_message_bytes: BytesMut = notification_message.into();
+
+        let mut expected = BytesMut::from(&[255u8; 16][..]);
+        expected.put_u16(21);
+        expected.put_u8(3);
+        expected.put_u8(6);
+        expected.put_u8(7);
+        assert_eq!(notification_message_bytes, expected);
+    }
+
+    #[test]
+    fn error_code_and_subcode_round_trip() {
+        let error_codes = [
+            ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageType),
+            ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnacceptableHoldTime),
+            ErrorCode::UpdateMessageError(UpdateMessageErrorSubcode::MalformedAsPath),
+            ErrorCode::HoldTimerExpired,
+            ErrorCode::FiniteStateMachineError(
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInEstablishedState,
+            ),
+            ErrorCode::Cease(CeaseSubcode::OutOfResources),
+        ];
+
+        for error_code in error_codes {
+            let bytes: (u8, u8) = error_code.into();
+            assert_eq!(ErrorCode::try_from(bytes).unwrap(), error_code);
+        }
+    }
+
+    #[test]
+    fn unknown_error_code_is_rejected() {
+        assert!(ErrorCode::try_from((7, 0)).is_err());
+    }
+
+    #[test]
+    fn unknown_subcodes_are_kept() {
+        for (bytes, error_code) in [
+            ((6, 9), ErrorCode::Cease(CeaseSubcode::Unknown(9))),
+            (
+                (2, 8),
+                ErrorCode::OpenMessageError(OpenMessageErrorSubcode::Unknown(8)),
+            ),
+        ] {
+            assert_eq!(ErrorCode::try_from(bytes).unwrap(), error_code);
+            assert_eq!(<(u8, u8)>::from(error_code), bytes);
+        }
+    }
+}
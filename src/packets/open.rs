@@ -8,7 +8,15 @@ use crate::{
     error::ConvertBytesToBgpMessageError,
 };
 
-use super::header::{Header, MessageType};
+use super::{
+    capability::{CAPABILITIES_PARAMETER_TYPE, Capability},
+    header::{Header, MessageType},
+    notification::{
+        ErrorCode, MessageHeaderErrorSubcode, NotificationMessage, OpenMessageErrorSubcode,
+    },
+};
+
+const OPEN_MESSAGE_MINIMUM_LENGTH: usize = 29;
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct OpenMessage {
@@ -17,37 +25,157 @@ pub struct OpenMessage {
     my_as_number: AutonomousSystemNumber,
     hold_time: HoldTime,
     bgp_identifier: Ipv4Addr,
-    optional_parameters_length: u8,
-    optional_parameters: BytesMut,
+    capabilities: Vec<Capability>,
 }
 
 impl OpenMessage {
-    pub fn new(my_as_number: AutonomousSystemNumber, my_ip_addr: Ipv4Addr) -> Self {
-        let header = Header::new(29, MessageType::Open);
+    pub fn new(
+        my_as_number: AutonomousSystemNumber,
+        my_ip_addr: Ipv4Addr,
+        hold_time: HoldTime,
+        capabilities: Vec<Capability>,
+    ) -> Self {
         Self {
-            header,
+            header: Self::header(&capabilities),
             version: Version::new(),
-            my_as_number,
-            hold_time: HoldTime::new(),
+            my_as_number: u32::from(my_as_number.as_two_octet()).into(),
+            hold_time,
             bgp_identifier: my_ip_addr,
-            optional_parameters_length: 0,
-            optional_parameters: BytesMut::new(),
+            capabilities,
+        }
+    }
+
+    fn header(capabilities: &[Capability]) -> Header {
+        let optional_parameters_length = Self::optional_parameters(capabilities).len();
+        Header::new(
+            (OPEN_MESSAGE_MINIMUM_LENGTH + optional_parameters_length) as u16,
+            MessageType::Open,
+        )
+    }
+
+    fn optional_parameters(capabilities: &[Capability]) -> BytesMut {
+        if capabilities.is_empty() {
+            BytesMut::new()
+        } else {
+            Capability::optional_parameter(capabilities)
+        }
+    }
+
+    pub fn hold_time(&self) -> HoldTime {
+        self.hold_time
+    }
+
+    pub fn bgp_identifier(&self) -> Ipv4Addr {
+        self.bgp_identifier
+    }
+
+    /// The sender's AS number: the one in the four-octet AS capability if
+    /// advertised (RFC 6793 §4.1), otherwise the My Autonomous System field.
+    pub fn as_number(&self) -> AutonomousSystemNumber {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability {
+                Capability::FourOctetAsNumber(as_number) => Some(*as_number),
+                _ => None,
+            })
+            .unwrap_or(self.my_as_number)
+    }
+
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+}
+
+fn open_message_error(
+    subcode: OpenMessageErrorSubcode,
+    error: anyhow::Error,
+) -> ConvertBytesToBgpMessageError {
+    ConvertBytesToBgpMessageError::from(error).with_notification(NotificationMessage::new(
+        ErrorCode::OpenMessageError(subcode),
+        BytesMut::new(),
+    ))
+}
+
+fn open_message_error_notification() -> NotificationMessage {
+    NotificationMessage::new(
+        ErrorCode::OpenMessageError(OpenMessageErrorSubcode::Unspecific),
+        BytesMut::new(),
+    )
+}
+
+/// Checks that the optional parameters fill exactly the advertised length
+/// and that every parameter is one we understand, collecting the
+/// capabilities they carry.
+fn parse_optional_parameters(
+    optional_parameters_length: u8,
+    optional_parameters: &[u8],
+) -> Result<Vec<Capability>, ConvertBytesToBgpMessageError> {
+    if usize::from(optional_parameters_length) != optional_parameters.len() {
+        return Err(open_message_error(
+            OpenMessageErrorSubcode::Unspecific,
+            anyhow::anyhow!(
+                "optional parameters length is {}, but {} bytes follow",
+                optional_parameters_length,
+                optional_parameters.len()
+            ),
+        ));
+    }
+
+    let mut capabilities = vec![];
+    let mut rest = optional_parameters;
+    while !rest.is_empty() {
+        let [parameter_type, parameter_length, ..] = *rest else {
+            return Err(open_message_error(
+                OpenMessageErrorSubcode::Unspecific,
+                anyhow::anyhow!("truncated optional parameter: {:?}", rest),
+            ));
+        };
+        let parameter_end = 2 + usize::from(parameter_length);
+        if rest.len() < parameter_end {
+            return Err(open_message_error(
+                OpenMessageErrorSubcode::Unspecific,
+                anyhow::anyhow!("truncated optional parameter: {:?}", rest),
+            ));
         }
+        if parameter_type != CAPABILITIES_PARAMETER_TYPE {
+            return Err(open_message_error(
+                OpenMessageErrorSubcode::UnsupportedOptionalParameter,
+                anyhow::anyhow!("unsupported optional parameter type: {}", parameter_type),
+            ));
+        }
+        capabilities.extend(
+            Capability::from_u8_slice(&rest[2..parameter_end])
+                .map_err(|e| e.with_notification(open_message_error_notification()))?,
+        );
+        rest = &rest[parameter_end..];
     }
+
+    Ok(capabilities)
 }
 
 impl TryFrom<BytesMut> for OpenMessage {
     type Error = ConvertBytesToBgpMessageError;
 
     fn try_from(bytes: BytesMut) -> Result<Self, Self::Error> {
-        let header = Header::try_from(BytesMut::from(&bytes[0..19]))?;
+        if bytes.len() < OPEN_MESSAGE_MINIMUM_LENGTH {
+            return Err(ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
+                "OPEN message length is too short: {}",
+                bytes.len()
+            ))
+            .with_notification(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength),
+                BytesMut::from(&(bytes.len() as u16).to_be_bytes()[..]),
+            )));
+        }
+
+        Header::try_from(BytesMut::from(&bytes[0..19]))?;
         let version: Version = bytes[19].try_into()?;
 
-        let my_as_number = AutonomousSystemNumber::from(u16::from_be_bytes(
+        let my_as_number = AutonomousSystemNumber::from(u32::from(u16::from_be_bytes(
             bytes[20..22]
                 .try_into()
                 .context(format!("cannot parse AS number: {:?}", &bytes[20..22]))?,
-        ));
+        )));
 
         let hold_time = HoldTime::from(u16::from_be_bytes(
             bytes[22..24]
@@ -60,16 +188,17 @@ impl TryFrom<BytesMut> for OpenMessage {
             .context(format!("cannot parse BGP Identifier: {:?}", &bytes[24..28]))?;
         let bgp_identifier = Ipv4Addr::from(bgp_identifier);
         let optional_parameters_length = bytes[28];
-        let optional_parameters = BytesMut::from(&bytes[29..]);
+        let capabilities = parse_optional_parameters(optional_parameters_length, &bytes[29..])?;
 
+        // Capabilities are sent back in a single parameter, whatever the
+        // number of parameters they were received in.
         Ok(Self {
-            header,
+            header: Self::header(&capabilities),
             version,
             my_as_number,
             hold_time,
             bgp_identifier,
-            optional_parameters_length,
-            optional_parameters,
+            capabilities,
         })
     }
 }
@@ -80,11 +209,12 @@ impl From<OpenMessage> for BytesMut {
         let header_bytes: &BytesMut = &message.header.into();
         bytes.put(&header_bytes[..]);
         bytes.put_u8(message.version.into());
-        bytes.put_u16(message.my_as_number.into());
+        bytes.put_u16(message.my_as_number.as_two_octet());
         bytes.put_u16(message.hold_time.into());
         bytes.put(&message.bgp_identifier.octets()[..]);
-        bytes.put_u8(message.optional_parameters_length);
-        bytes.put(&message.optional_parameters[..]);
+        let optional_parameters = OpenMessage::optional_parameters(&message.capabilities);
+        bytes.put_u8(optional_parameters.len() as u8);
+        bytes.put(&optional_parameters[..]);
 
         bytes
     }
@@ -96,10 +226,116 @@ mod tests {
 
     #[test]
     fn convert_bytes_to_open_message_and_open_message_to_bytes() {
-        let open_message = OpenMessage::new(64512.into(), "127.0.0.1".parse().unwrap());
+        let open_message = OpenMessage::new(
+            64512.into(),
+            "127.0.0.1".parse().unwrap(),
+            HoldTime::new(),
+            vec![],
+        );
         let open_message_bytes: BytesMut = open_message.clone().into();
         let open_message2: OpenMessage = open_message_bytes.try_into().unwrap();
 
         assert_eq!(open_message, open_message2);
     }
+
+    fn open_message_bytes_with_optional_parameters(parameters: &[u8]) -> BytesMut {
+        let open_message = OpenMessage::new(
+            64512.into(),
+            "127.0.0.1".parse().unwrap(),
+            HoldTime::new(),
+            vec![],
+        );
+        let mut bytes: BytesMut = open_message.into();
+        bytes[28] = parameters.len() as u8;
+        bytes.put(parameters);
+        let length = (bytes.len() as u16).to_be_bytes();
+        bytes[16..18].copy_from_slice(&length);
+        bytes
+    }
+
+    fn open_message_error_subcode(bytes: BytesMut) -> Option<ErrorCode> {
+        OpenMessage::try_from(bytes)
+            .unwrap_err()
+            .notification()
+            .map(|notification| notification.error_code)
+    }
+
+    #[test]
+    fn open_message_with_unsupported_version_is_rejected() {
+        let mut bytes = open_message_bytes_with_optional_parameters(&[]);
+        bytes[19] = 3;
+
+        assert_eq!(
+            open_message_error_subcode(bytes),
+            Some(ErrorCode::OpenMessageError(
+                OpenMessageErrorSubcode::UnsupportedVersionNumber
+            ))
+        );
+    }
+
+    #[test]
+    fn open_message_accepts_capabilities_optional_parameter() {
+        let bytes = open_message_bytes_with_optional_parameters(&[2, 2, 2, 0]);
+
+        assert_eq!(
+            OpenMessage::try_from(bytes).unwrap().capabilities(),
+            &[Capability::RouteRefresh]
+        );
+    }
+
+    #[test]
+    fn convert_open_message_with_capabilities_to_bytes_and_back() {
+        let open_message = OpenMessage::new(
+            64512.into(),
+            "127.0.0.1".parse().unwrap(),
+            HoldTime::new(),
+            vec!["ipv4-unicast".parse().unwrap(), Capability::RouteRefresh],
+        );
+        let open_message_bytes: BytesMut = open_message.clone().into();
+        assert_eq!(open_message_bytes.len(), 29 + 2 + 6 + 2);
+
+        let open_message2: OpenMessage = open_message_bytes.try_into().unwrap();
+        assert_eq!(open_message, open_message2);
+    }
+
+    #[test]
+    fn open_message_with_unknown_optional_parameter_is_rejected() {
+        let bytes = open_message_bytes_with_optional_parameters(&[1, 2, 0, 0]);
+
+        assert_eq!(
+            open_message_error_subcode(bytes),
+            Some(ErrorCode::OpenMessageError(
+                OpenMessageErrorSubcode::UnsupportedOptionalParameter
+            ))
+        );
+    }
+
+    #[test]
+    fn open_message_with_inconsistent_optional_parameters_length_is_rejected() {
+        let mut bytes = open_message_bytes_with_optional_parameters(&[2, 2, 2, 0]);
+        bytes[28] = 6;
+
+        assert_eq!(
+            open_message_error_subcode(bytes),
+            Some(ErrorCode::OpenMessageError(
+                OpenMessageErrorSubcode::Unspecific
+            ))
+        );
+    }
+
+    #[test]
+    fn four_octet_as_number_is_sent_as_as_trans_with_capability() {
+        let as_number = AutonomousSystemNumber::from(4_200_000_000);
+        let open_message = OpenMessage::new(
+            as_number,
+            "127.0.0.1".parse().unwrap(),
+            HoldTime::new(),
+            vec![Capability::FourOctetAsNumber(as_number)],
+        );
+        let open_message_bytes: BytesMut = open_message.into();
+        assert_eq!(&open_message_bytes[20..22], &23456u16.to_be_bytes());
+
+        let open_message: OpenMessage = open_message_bytes.try_into().unwrap();
+        assert_eq!(open_message.as_number(), as_number);
+    }
 }
@@ -0,0 +1,99 @@
+use bytes::{BufMut, BytesMut};
+
+use crate::{
+    bgp_type::{Afi, Safi},
+    error::ConvertBytesToBgpMessageError,
+};
+
+use super::header::{Header, MessageType};
+
+/// Asks the peer to advertise its Adj-RIB-Out for one address family again
+/// (RFC 2918). AFI and SAFI are kept as received so that a request for a
+/// family we do not know can be ignored, as §4 requires.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct RouteRefreshMessage {
+    header: Header,
+    afi: u16,
+    safi: u8,
+}
+
+impl RouteRefreshMessage {
+    pub fn new(afi: Afi, safi: Safi) -> Self {
+        Self {
+            header: Header::new(23, MessageType::RouteRefresh),
+            afi: afi.into(),
+            safi: safi.into(),
+        }
+    }
+
+    /// The requested address family, or `None` if we do not know it.
+    pub fn address_family(&self) -> Option<(Afi, Safi)> {
+        Some((
+            Afi::try_from(self.afi).ok()?,
+            Safi::try_from(self.safi).ok()?,
+        ))
+    }
+}
+
+impl TryFrom<BytesMut> for RouteRefreshMessage {
+    type Error = ConvertBytesToBgpMessageError;
+
+    fn try_from(bytes: BytesMut) -> Result<Self, Self::Error> {
+        if bytes.len() != 23 {
+            return Err(Self::Error::from(anyhow::anyhow!(
+                "Route refresh message length is not 23: {}",
+                bytes.len()
+            )));
+        }
+
+        let header = Header::try_from(BytesMut::from(&bytes[0..19]))?;
+        if header.type_ != MessageType::RouteRefresh {
+            return Err(anyhow::anyhow!("Invalid message type: {:?}", header.type_).into());
+        }
+
+        Ok(Self {
+            header,
+            afi: u16::from_be_bytes([bytes[19], bytes[20]]),
+            safi: bytes[22],
+        })
+    }
+}
+
+impl From<RouteRefreshMessage> for BytesMut {
+    fn from(message: RouteRefreshMessage) -> Self {
+        let mut bytes = BytesMut::new();
+        bytes.put::<BytesMut>(message.header.into());
+        bytes.put_u16(message.afi);
+        bytes.put_u8(0);
+        bytes.put_u8(message.safi);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use crate::bgp_type::{Afi, Safi};
+
+    use super::RouteRefreshMessage;
+
+    #[test]
+    fn route_refresh_message_round_trips() {
+        let message = RouteRefreshMessage::new(Afi::Ipv6, Safi::Unicast);
+        let bytes: BytesMut = message.clone().into();
+
+        assert_eq!(bytes.len(), 23);
+        assert_eq!(&bytes[19..], &[0, 2, 0, 1]);
+        assert_eq!(RouteRefreshMessage::try_from(bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn route_refresh_for_unknown_family_decodes_without_one() {
+        let mut bytes: BytesMut = RouteRefreshMessage::new(Afi::Ipv4, Safi::Unicast).into();
+        bytes[22] = 128;
+
+        let message = RouteRefreshMessage::try_from(bytes).unwrap();
+        assert_eq!(message.address_family(), None);
+    }
+}
@@ -1,14 +1,68 @@
 use std::net::Ipv4Addr;
 use std::sync::Arc;
 
-use crate::bgp_type::AutonomousSystemNumber;
+use crate::bgp_type::{Afi, AutonomousSystemNumber, Safi};
 use crate::error::ConvertBytesToBgpMessageError;
-use crate::path_attribute::{AsPath, Origin, PathAttribute};
+use crate::path_attribute::{
+    AsPath, Ipv6NextHop, MpReachNlri, MpUnreachNlri, Origin, PathAttribute,
+};
 use crate::routing::Ipv4Network;
-use anyhow::Context;
 use bytes::{BufMut, BytesMut};
 
-use super::header::{Header, MessageType};
+use super::{
+    header::{Header, MessageType},
+    notification::{
+        ErrorCode, MessageHeaderErrorSubcode, NotificationMessage, UpdateMessageErrorSubcode,
+    },
+};
+
+/// Header, Withdrawn Routes Length and Total Path Attribute Length.
+pub const UPDATE_MESSAGE_MINIMUM_LENGTH: usize = 23;
+
+/// How an UPDATE with a malformed attribute is handled (RFC 7606 §2), from
+/// the mildest action to the most drastic. Errors calling for a session
+/// reset fail the decoding instead.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum UpdateErrorAction {
+    AttributeDiscard,
+    TreatAsWithdraw,
+    AfiSafiDisable(Afi, Safi),
+}
+
+/// An attribute error an UPDATE was decoded in spite of.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct UpdateError {
+    pub action: UpdateErrorAction,
+    /// The type code of the malformed attribute, if the error is about one.
+    pub attribute_type_code: Option<u8>,
+    /// The NOTIFICATION RFC 4271 would have reset the session with.
+    pub notification: NotificationMessage,
+    pub reason: String,
+}
+
+impl UpdateError {
+    pub fn new(action: UpdateErrorAction, error: ConvertBytesToBgpMessageError) -> Self {
+        let notification = error.notification().cloned().unwrap_or_else(|| {
+            NotificationMessage::new(
+                ErrorCode::UpdateMessageError(UpdateMessageErrorSubcode::MalformedAttributeList),
+                BytesMut::new(),
+            )
+        });
+        Self {
+            action,
+            attribute_type_code: None,
+            notification,
+            reason: error.to_string(),
+        }
+    }
+}
+
+impl From<UpdateError> for ConvertBytesToBgpMessageError {
+    fn from(error: UpdateError) -> Self {
+        ConvertBytesToBgpMessageError::from(anyhow::anyhow!(error.reason))
+            .with_notification(error.notification)
+    }
+}
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct UpdateMessage {
@@ -18,6 +72,8 @@ pub struct UpdateMessage {
     pub path_attributes: Arc<Vec<PathAttribute>>,
     path_attributes_length: u16, // octets
     pub network_layer_reachability_information: Vec<Ipv4Network>,
+    four_octet_as: bool,      // AS number width of the encoded path attributes
+    errors: Vec<UpdateError>, // found while decoding, never encoded
 }
 
 impl UpdateMessage {
@@ -26,8 +82,24 @@ impl UpdateMessage {
         network_layer_reachability_information: Vec<Ipv4Network>,
         withdrawn_routes: Vec<Ipv4Network>,
     ) -> Self {
-        let path_attributes_length =
-            path_attributes.iter().map(|p| p.bytes_len()).sum::<usize>() as u16;
+        Self::with_as_number_width(
+            path_attributes,
+            network_layer_reachability_information,
+            withdrawn_routes,
+            true,
+        )
+    }
+
+    fn with_as_number_width(
+        path_attributes: Arc<Vec<PathAttribute>>,
+        network_layer_reachability_information: Vec<Ipv4Network>,
+        withdrawn_routes: Vec<Ipv4Network>,
+        four_octet_as: bool,
+    ) -> Self {
+        let path_attributes_length = path_attributes
+            .iter()
+            .map(|p| p.bytes_len(four_octet_as))
+            .sum::<usize>() as u16;
 
         let network_layer_reachability_information_length = network_layer_reachability_information
             .iter()
@@ -56,8 +128,250 @@ impl UpdateMessage {
             path_attributes,
             path_attributes_length,
             network_layer_reachability_information,
+            four_octet_as,
+            errors: vec![],
+        }
+    }
+
+    /// Attribute errors found while decoding the message.
+    pub fn errors(&self) -> &[UpdateError] {
+        &self.errors
+    }
+
+    /// Withdraws every route the message announces, along with those it
+    /// already withdraws (RFC 7606 §2 treat-as-withdraw).
+    pub fn into_withdraw(self) -> Self {
+        let mut withdrawn_ipv6_routes = vec![];
+        for path_attribute in self.path_attributes.iter() {
+            match path_attribute {
+                PathAttribute::MpReachNlri(m) => {
+                    withdrawn_ipv6_routes.extend(m.nlri.iter().copied())
+                }
+                PathAttribute::MpUnreachNlri(m) => {
+                    withdrawn_ipv6_routes.extend(m.withdrawn_routes.iter().copied())
+                }
+                _ => {}
+            }
+        }
+        let path_attributes = if withdrawn_ipv6_routes.is_empty() {
+            vec![]
+        } else {
+            vec![PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                withdrawn_routes: withdrawn_ipv6_routes,
+            })]
+        };
+
+        let mut withdrawn_routes = self.withdrawn_routes;
+        withdrawn_routes.extend(self.network_layer_reachability_information);
+        Self::new(Arc::new(path_attributes), vec![], withdrawn_routes)
+    }
+
+    /// Leaves out MP_REACH_NLRI and MP_UNREACH_NLRI, for a session on which
+    /// IPv6 unicast was disabled (RFC 7606 §2 AFI/SAFI disable).
+    pub fn without_ipv6_unicast(self) -> Self {
+        let path_attributes = self
+            .path_attributes
+            .iter()
+            .filter(|p| {
+                !matches!(
+                    p,
+                    PathAttribute::MpReachNlri(_) | PathAttribute::MpUnreachNlri(_)
+                )
+            })
+            .cloned()
+            .collect();
+        Self::new(
+            Arc::new(path_attributes),
+            self.network_layer_reachability_information,
+            self.withdrawn_routes,
+        )
+    }
+
+    /// Leaves out LOCAL_PREF, for a message received from an external peer
+    /// (RFC 7606 §7.5).
+    pub fn without_local_pref(self) -> Self {
+        if !self
+            .path_attributes
+            .iter()
+            .any(|p| matches!(p, PathAttribute::LocalPref(_)))
+        {
+            return self;
+        }
+        let path_attributes = self
+            .path_attributes
+            .iter()
+            .filter(|p| !matches!(p, PathAttribute::LocalPref(_)))
+            .cloned()
+            .collect();
+        Self::new(
+            Arc::new(path_attributes),
+            self.network_layer_reachability_information,
+            self.withdrawn_routes,
+        )
+    }
+
+    /// Prepares the message for a peer that did not negotiate four-octet AS
+    /// numbers (RFC 6793 §4.2.2).
+    pub fn into_two_octet_as(self) -> Self {
+        if !self.four_octet_as {
+            return self;
         }
+
+        Self::with_as_number_width(
+            Arc::new(PathAttribute::to_two_octet_as(&self.path_attributes)),
+            self.network_layer_reachability_information,
+            self.withdrawn_routes,
+            false,
+        )
     }
+
+    /// Decodes an UPDATE whose AS numbers are `four_octet_as` wide. Messages
+    /// from two-octet AS speakers are rebuilt from AS4_PATH and
+    /// AS4_AGGREGATOR so the rest of the daemon only sees four-octet paths.
+    pub fn decode(
+        bytes: BytesMut,
+        four_octet_as: bool,
+    ) -> Result<Self, ConvertBytesToBgpMessageError> {
+        if bytes.len() < UPDATE_MESSAGE_MINIMUM_LENGTH {
+            return Err(ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
+                "UPDATE message length is too short: {}",
+                bytes.len()
+            ))
+            .with_notification(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength),
+                BytesMut::from(&(bytes.len() as u16).to_be_bytes()[..]),
+            )));
+        }
+        Header::try_from(BytesMut::from(&bytes[0..19]))?;
+
+        // RFC 4271 §6.3: lengths running past the end of the message make
+        // the attribute list malformed.
+        let withdrawn_routes_length = read_u16(&bytes, 19).unwrap_or_default();
+        let withdrawn_routes_end_index = 21 + withdrawn_routes_length as usize;
+        let path_attributes_start_index = withdrawn_routes_end_index + 2;
+        let total_path_attribute_length =
+            read_u16(&bytes, withdrawn_routes_end_index).ok_or_else(|| {
+                update_message_error(
+                    UpdateMessageErrorSubcode::MalformedAttributeList,
+                    &[],
+                    anyhow::anyhow!(
+                        "withdrawn routes length {} exceeds the message",
+                        withdrawn_routes_length
+                    ),
+                )
+            })?;
+        let network_layer_reachability_information_start_index =
+            path_attributes_start_index + total_path_attribute_length as usize;
+        let path_attributes_bytes = bytes
+            .get(path_attributes_start_index..network_layer_reachability_information_start_index)
+            .ok_or_else(|| {
+                update_message_error(
+                    UpdateMessageErrorSubcode::MalformedAttributeList,
+                    &[],
+                    anyhow::anyhow!(
+                        "total path attribute length {} exceeds the message",
+                        total_path_attribute_length
+                    ),
+                )
+            })?;
+
+        let withdrawn_routes = Ipv4Network::from_u8_slice(&bytes[21..withdrawn_routes_end_index])?;
+        let (path_attributes, mut errors) =
+            PathAttribute::decode_list(path_attributes_bytes, four_octet_as)?;
+        let network_layer_reachability_information = Ipv4Network::from_u8_slice(
+            &bytes[network_layer_reachability_information_start_index..],
+        )?;
+
+        // RFC 7606 §3 (d): routes announced without a well-known mandatory
+        // attribute are treated as withdrawn. It is not looked for again if
+        // malformed, as the error already withdraws them.
+        if !errors
+            .iter()
+            .any(|e| e.action == UpdateErrorAction::TreatAsWithdraw)
+        {
+            errors.extend(missing_well_known_attributes(
+                &path_attributes,
+                !network_layer_reachability_information.is_empty(),
+            ));
+        }
+
+        let path_attributes = if four_octet_as {
+            path_attributes
+        } else {
+            PathAttribute::from_two_octet_as(path_attributes)
+        };
+        // Lengths are computed anew, as attributes are kept in their
+        // canonical encoding rather than as received.
+        let mut update = Self::new(
+            Arc::new(path_attributes),
+            network_layer_reachability_information,
+            withdrawn_routes,
+        );
+        update.errors = errors;
+        Ok(update)
+    }
+}
+
+/// Errors for the well-known mandatory attributes missing from an UPDATE
+/// announcing routes. NEXT_HOP is only required for IPv4 NLRI, as
+/// MP_REACH_NLRI carries its own (RFC 4760 §3).
+fn missing_well_known_attributes(
+    path_attributes: &[PathAttribute],
+    announces_ipv4_routes: bool,
+) -> Vec<UpdateError> {
+    let announces_ipv6_routes = path_attributes
+        .iter()
+        .any(|p| matches!(p, PathAttribute::MpReachNlri(m) if !m.nlri.is_empty()));
+    if !announces_ipv4_routes && !announces_ipv6_routes {
+        return vec![];
+    }
+
+    let mut required = vec![(1, "ORIGIN"), (2, "AS_PATH")];
+    if announces_ipv4_routes {
+        required.push((3, "NEXT_HOP"));
+    }
+    required
+        .into_iter()
+        .filter(|(type_code, _)| {
+            !path_attributes.iter().any(|p| {
+                matches!(
+                    (type_code, p),
+                    (1, PathAttribute::Origin(_))
+                        | (2, PathAttribute::AsPath(_))
+                        | (3, PathAttribute::NextHop(_))
+                )
+            })
+        })
+        .map(|(type_code, name)| {
+            UpdateError::new(
+                UpdateErrorAction::TreatAsWithdraw,
+                update_message_error(
+                    UpdateMessageErrorSubcode::MissingWellKnownAttribute,
+                    &[type_code],
+                    anyhow::anyhow!("missing well-known attribute {}", name),
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Builds the error for a malformed UPDATE, carrying the NOTIFICATION of
+/// RFC 4271 §6.3 with `data` as its data field.
+pub fn update_message_error(
+    subcode: UpdateMessageErrorSubcode,
+    data: &[u8],
+    error: anyhow::Error,
+) -> ConvertBytesToBgpMessageError {
+    ConvertBytesToBgpMessageError::from(error).with_notification(NotificationMessage::new(
+        ErrorCode::UpdateMessageError(subcode),
+        BytesMut::from(data),
+    ))
+}
+
+/// The big-endian `u16` at `index`, unless the slice ends before it.
+pub fn read_u16(bytes: &[u8], index: usize) -> Option<u16> {
+    let octets = bytes.get(index..index.checked_add(2)?)?;
+    Some(u16::from_be_bytes(octets.try_into().ok()?))
 }
 
 impl From<UpdateMessage> for BytesMut {
@@ -76,7 +390,7 @@ impl From<UpdateMessage> for BytesMut {
         message
             .path_attributes
             .iter()
-            .for_each(|r| bytes.put::<BytesMut>(r.into()));
+            .for_each(|r| bytes.put(r.to_bytes(message.four_octet_as)));
 
         message
             .network_layer_reachability_information
@@ -91,42 +405,7 @@ impl TryFrom<BytesMut> for UpdateMessage {
     type Error = ConvertBytesToBgpMessageError;
 
     fn try_from(bytes: BytesMut) -> Result<Self, Self::Error> {
-        let header = Header::try_from(BytesMut::from(&bytes[0..19]))?;
-
-        let withdrawn_routes_length: u16 = u16::from_be_bytes(bytes[19..21].try_into().context(
-            format!("cannot convert to withdrawn_routes_length: {:?}", &bytes),
-        )?);
-        let withdrawn_routes_end_index = 21 + withdrawn_routes_length as usize;
-        let withdrawn_routes_bytes = &bytes[21..withdrawn_routes_end_index];
-        let withdrawn_routes = Ipv4Network::from_u8_slice(withdrawn_routes_bytes)?;
-
-        let path_attributes_start_index = withdrawn_routes_end_index + 2;
-        let total_path_attribute_length = u16::from_be_bytes(
-            bytes[withdrawn_routes_end_index..path_attributes_start_index]
-                .try_into()
-                .context(format!(
-                    "cannot convert to total_path_attribute_length: {:?}",
-                    &bytes
-                ))?,
-        );
-
-        let path_attributes_bytes = &bytes[path_attributes_start_index
-            ..path_attributes_start_index + total_path_attribute_length as usize];
-        let path_attributes = Arc::new(PathAttribute::from_u8_slice(path_attributes_bytes)?);
-        let network_layer_reachability_information_start_index =
-            path_attributes_start_index + total_path_attribute_length as usize;
-        let network_layer_reachability_information = Ipv4Network::from_u8_slice(
-            &bytes[network_layer_reachability_information_start_index..],
-        )?;
-
-        Ok(Self {
-            header,
-            withdrawn_routes,
-            withdrawn_routes_length,
-            path_attributes,
-            path_attributes_length: total_path_attribute_length,
-            network_layer_reachability_information,
-        })
+        Self::decode(bytes, true)
     }
 }
 
@@ -144,7 +423,7 @@ mod tests {
 
         let update_message_path_attributes = Arc::new(vec![
             PathAttribute::Origin(Origin::Igp),
-            PathAttribute::AsPath(AsPath::AsSequence(vec![some_as, local_as])),
+            PathAttribute::AsPath(AsPath::sequence(vec![some_as, local_as])),
             PathAttribute::NextHop(local_ip),
         ]);
 
@@ -158,4 +437,206 @@ mod tests {
         let update_message2: UpdateMessage = update_message_bytes.try_into().unwrap();
         assert_eq!(update_message, update_message2);
     }
+
+    #[test]
+    fn convert_ipv6_only_update_message_to_bytes_and_back() {
+        let update_message = UpdateMessage::new(
+            Arc::new(vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+                PathAttribute::MpReachNlri(MpReachNlri {
+                    next_hop: Ipv6NextHop {
+                        global: "2001:db8::1".parse().unwrap(),
+                        link_local: None,
+                    },
+                    nlri: vec!["2001:db8:100::/48".parse().unwrap()],
+                }),
+            ]),
+            vec![],
+            vec![],
+        );
+
+        let update_message_bytes: BytesMut = update_message.clone().into();
+        let update_message2: UpdateMessage = update_message_bytes.try_into().unwrap();
+        assert_eq!(update_message, update_message2);
+    }
+
+    fn update_message_error_subcode(bytes: BytesMut) -> UpdateMessageErrorSubcode {
+        match UpdateMessage::try_from(bytes)
+            .unwrap_err()
+            .notification()
+            .unwrap()
+            .error_code
+        {
+            ErrorCode::UpdateMessageError(subcode) => subcode,
+            error_code => panic!("expected update message error, but got {:?}", error_code),
+        }
+    }
+
+    #[test]
+    fn update_with_lengths_past_the_end_is_a_malformed_attribute_list() {
+        let update_message = UpdateMessage::new(Arc::new(vec![]), vec![], vec![]);
+        let bytes: BytesMut = update_message.into();
+
+        let mut withdrawn_routes_too_long = bytes.clone();
+        withdrawn_routes_too_long[19..21].copy_from_slice(&1u16.to_be_bytes());
+        assert_eq!(
+            update_message_error_subcode(withdrawn_routes_too_long),
+            UpdateMessageErrorSubcode::MalformedAttributeList
+        );
+
+        let mut path_attributes_too_long = bytes;
+        path_attributes_too_long[21..23].copy_from_slice(&1u16.to_be_bytes());
+        assert_eq!(
+            update_message_error_subcode(path_attributes_too_long),
+            UpdateMessageErrorSubcode::MalformedAttributeList
+        );
+    }
+
+    #[test]
+    fn update_with_truncated_nlri_is_an_invalid_network_field() {
+        let update_message = UpdateMessage::new(
+            Arc::new(vec![]),
+            vec!["10.100.220.0/24".parse().unwrap()],
+            vec![],
+        );
+        let bytes: BytesMut = update_message.into();
+
+        assert_eq!(
+            update_message_error_subcode(BytesMut::from(&bytes[..bytes.len() - 1])),
+            UpdateMessageErrorSubcode::InvalidNetworkField
+        );
+    }
+
+    /// An UPDATE carrying `path_attributes` as they are, followed by `nlri`.
+    fn update_message_bytes(path_attributes: &[u8], nlri: &[u8]) -> BytesMut {
+        let length = UPDATE_MESSAGE_MINIMUM_LENGTH + path_attributes.len() + nlri.len();
+        let mut bytes: BytesMut = Header::new(length as u16, MessageType::Update).into();
+        bytes.put_u16(0);
+        bytes.put_u16(path_attributes.len() as u16);
+        bytes.put(path_attributes);
+        bytes.put(nlri);
+        bytes
+    }
+
+    const ORIGIN_IGP: [u8; 4] = [0x40, 1, 1, 0];
+    const AS_PATH: [u8; 9] = [0x40, 2, 6, 2, 1, 0, 0, 0xfc, 0x01];
+    const NEXT_HOP: [u8; 7] = [0x40, 3, 4, 10, 0, 0, 1];
+    const NLRI: [u8; 4] = [24, 10, 100, 220];
+
+    fn actions(update: &UpdateMessage) -> Vec<UpdateErrorAction> {
+        update.errors().iter().map(|e| e.action).collect()
+    }
+
+    #[test]
+    fn malformed_well_known_attribute_is_treated_as_withdraw() {
+        let path_attributes = [&[0x40, 1, 1, 7][..], &AS_PATH, &NEXT_HOP].concat();
+        let update =
+            UpdateMessage::try_from(update_message_bytes(&path_attributes, &NLRI)).unwrap();
+
+        assert_eq!(actions(&update), vec![UpdateErrorAction::TreatAsWithdraw]);
+        assert_eq!(
+            update.errors()[0].notification.error_code,
+            ErrorCode::UpdateMessageError(UpdateMessageErrorSubcode::InvalidOriginAttribute)
+        );
+
+        let withdraw = update.into_withdraw();
+        assert_eq!(
+            withdraw.withdrawn_routes,
+            vec!["10.100.220.0/24".parse().unwrap()]
+        );
+        assert!(withdraw.network_layer_reachability_information.is_empty());
+        assert!(withdraw.path_attributes.is_empty());
+    }
+
+    #[test]
+    fn missing_well_known_attribute_is_treated_as_withdraw() {
+        let path_attributes = [&ORIGIN_IGP[..], &AS_PATH].concat();
+        let update =
+            UpdateMessage::try_from(update_message_bytes(&path_attributes, &NLRI)).unwrap();
+
+        assert_eq!(actions(&update), vec![UpdateErrorAction::TreatAsWithdraw]);
+        let notification = &update.errors()[0].notification;
+        assert_eq!(
+            notification.error_code,
+            ErrorCode::UpdateMessageError(UpdateMessageErrorSubcode::MissingWellKnownAttribute)
+        );
+        assert_eq!(&notification.data[..], &[3]);
+
+        // Without NLRI nothing is announced, so nothing is missing.
+        let update = UpdateMessage::try_from(update_message_bytes(&[], &[])).unwrap();
+        assert!(update.errors().is_empty());
+    }
+
+    #[test]
+    fn malformed_aggregator_and_repeated_attributes_are_discarded() {
+        let path_attributes = [
+            &ORIGIN_IGP[..],
+            &AS_PATH,
+            &NEXT_HOP,
+            &[0xc0, 7, 3, 0, 0, 0],
+            &[0x40, 1, 1, 2],
+        ]
+        .concat();
+        let update =
+            UpdateMessage::try_from(update_message_bytes(&path_attributes, &NLRI)).unwrap();
+
+        assert_eq!(
+            actions(&update),
+            vec![
+                UpdateErrorAction::AttributeDiscard,
+                UpdateErrorAction::AttributeDiscard
+            ]
+        );
+        assert_eq!(
+            *update.path_attributes,
+            vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+                PathAttribute::NextHop("10.0.0.1".parse().unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_mp_reach_nlri_disables_the_address_family() {
+        let path_attributes = [&ORIGIN_IGP[..], &AS_PATH, &[0x80, 14, 5, 0, 2, 1, 16, 0]].concat();
+        let update = UpdateMessage::try_from(update_message_bytes(&path_attributes, &[])).unwrap();
+
+        assert_eq!(
+            actions(&update),
+            vec![UpdateErrorAction::AfiSafiDisable(Afi::Ipv6, Safi::Unicast)]
+        );
+    }
+
+    #[test]
+    fn repeated_mp_reach_nlri_resets_the_session() {
+        let mp_reach_nlri = PathAttribute::MpReachNlri(MpReachNlri {
+            next_hop: Ipv6NextHop {
+                global: "2001:db8::1".parse().unwrap(),
+                link_local: None,
+            },
+            nlri: vec![],
+        })
+        .to_bytes(true);
+        let path_attributes = [&mp_reach_nlri[..], &mp_reach_nlri].concat();
+
+        assert_eq!(
+            update_message_error_subcode(update_message_bytes(&path_attributes, &[])),
+            UpdateMessageErrorSubcode::MalformedAttributeList
+        );
+    }
+
+    #[test]
+    fn truncated_attribute_is_treated_as_withdraw() {
+        let path_attributes = [&ORIGIN_IGP[..], &AS_PATH, &NEXT_HOP, &[0x40, 5, 4, 0]].concat();
+        let update =
+            UpdateMessage::try_from(update_message_bytes(&path_attributes, &NLRI)).unwrap();
+
+        assert_eq!(actions(&update), vec![UpdateErrorAction::TreatAsWithdraw]);
+        assert_eq!(
+            update.errors()[0].notification.error_code,
+            ErrorCode::UpdateMessageError(UpdateMessageErrorSubcode::MalformedAttributeList)
+        );
+    }
 }
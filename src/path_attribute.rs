@@ -1,17 +1,27 @@
 use anyhow::Context;
 use bytes::{BufMut, BytesMut};
 
-use crate::bgp_type::AutonomousSystemNumber;
+use crate::bgp_type::{Afi, AutonomousSystemNumber, Safi};
 use crate::error::ConvertBytesToBgpMessageError;
+use crate::packets::notification::UpdateMessageErrorSubcode;
+use crate::packets::update::{UpdateError, UpdateErrorAction, read_u16, update_message_error};
+use crate::routing::Ipv6Network;
 
 use std::collections::BTreeSet;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum PathAttribute {
     Origin(Origin),
     AsPath(AsPath),
     NextHop(Ipv4Addr),
+    MultiExitDisc(u32),
+    LocalPref(u32),
+    Aggregator(Aggregator),
+    MpReachNlri(MpReachNlri),
+    MpUnreachNlri(MpUnreachNlri),
+    As4Path(AsPath),
+    As4Aggregator(Aggregator),
     DontKnow(Vec<u8>),
 }
 
@@ -22,44 +32,222 @@ pub enum Origin {
     Incomplete,
 }
 
+/// An AS_PATH or AS4_PATH: its segments in the order they were received.
+/// The empty path is the one of routes originated into iBGP.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct AsPath(Vec<AsPathSegment>);
+
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
-pub enum AsPath {
+pub enum AsPathSegment {
     AsSequence(Vec<AutonomousSystemNumber>),
     AsSet(BTreeSet<AutonomousSystemNumber>),
 }
 
-impl From<&AsPath> for BytesMut {
-    fn from(as_path: &AsPath) -> BytesMut {
-        match as_path {
-            AsPath::AsSet(s) => {
-                let mut bytes = BytesMut::new();
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Aggregator {
+    pub as_number: AutonomousSystemNumber,
+    pub address: Ipv4Addr,
+}
 
-                let path_segment_type = 1;
-                let number_of_as = s.len();
-                bytes.put_u8(path_segment_type);
-                bytes.put_u8(number_of_as as u8);
-                bytes.put(
-                    &s.iter()
-                        .flat_map(|a| u16::from(*a).to_be_bytes())
-                        .collect::<Vec<u8>>()[..],
-                );
-                bytes
+/// IPv6 next hop of MP_REACH_NLRI: a global address, followed by a
+/// link-local one when the peers share a link (RFC 2545 §3).
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Ipv6NextHop {
+    pub global: Ipv6Addr,
+    pub link_local: Option<Ipv6Addr>,
+}
+
+/// IPv6 unicast routes carried in MP_REACH_NLRI (RFC 4760 §3). Other
+/// address families are kept as `PathAttribute::DontKnow`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct MpReachNlri {
+    pub next_hop: Ipv6NextHop,
+    pub nlri: Vec<Ipv6Network>,
+}
+
+/// IPv6 unicast routes withdrawn in MP_UNREACH_NLRI (RFC 4760 §4).
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct MpUnreachNlri {
+    pub withdrawn_routes: Vec<Ipv6Network>,
+}
+
+/// Attribute flags (RFC 4271 §4.3).
+const OPTIONAL: u8 = 0b1000_0000;
+const TRANSITIVE: u8 = 0b0100_0000;
+const PARTIAL: u8 = 0b0010_0000;
+
+/// Type code of LOCAL_PREF, which peers discard when received over eBGP.
+pub const LOCAL_PREF_TYPE_CODE: u8 = 5;
+
+fn put_as_number(bytes: &mut BytesMut, as_number: AutonomousSystemNumber, four_octet_as: bool) {
+    if four_octet_as {
+        bytes.put_u32(as_number.into());
+    } else {
+        bytes.put_u16(as_number.as_two_octet());
+    }
+}
+
+fn as_number_length(four_octet_as: bool) -> usize {
+    if four_octet_as { 4 } else { 2 }
+}
+
+fn as_number_from_u8_slice(bytes: &[u8]) -> anyhow::Result<AutonomousSystemNumber> {
+    match *bytes {
+        [a, b] => Ok(u32::from(u16::from_be_bytes([a, b])).into()),
+        [a, b, c, d] => Ok(u32::from_be_bytes([a, b, c, d]).into()),
+        _ => Err(anyhow::anyhow!("cannot convert to AS number: {:?}", bytes)),
+    }
+}
+
+impl AsPath {
+    /// A path made of a single AS_SEQUENCE.
+    pub fn sequence(ases: Vec<AutonomousSystemNumber>) -> Self {
+        Self::from_segments(vec![AsPathSegment::AsSequence(ases)])
+    }
+
+    /// A path of `segments`, leaving out empty ones and joining adjacent
+    /// AS_SEQUENCEs, which only differ from one in their encoding.
+    pub fn from_segments(segments: Vec<AsPathSegment>) -> Self {
+        let mut as_path = Self::default();
+        for segment in segments {
+            as_path.push(segment);
+        }
+        as_path
+    }
+
+    pub fn segments(&self) -> &[AsPathSegment] {
+        &self.0
+    }
+
+    fn push(&mut self, segment: AsPathSegment) {
+        match (self.0.last_mut(), segment) {
+            (_, AsPathSegment::AsSequence(ases)) if ases.is_empty() => {}
+            (_, AsPathSegment::AsSet(ases)) if ases.is_empty() => {}
+            (Some(AsPathSegment::AsSequence(last)), AsPathSegment::AsSequence(ases)) => {
+                last.extend(ases)
             }
-            AsPath::AsSequence(s) => {
-                let mut bytes = BytesMut::new();
+            (_, segment) => self.0.push(segment),
+        }
+    }
+
+    /// The path with `as_number` prepended, as sent to an eBGP peer
+    /// (RFC 4271 §5.1.2). A leading AS_SET is kept as its own segment.
+    pub fn prepend(&self, as_number: AutonomousSystemNumber) -> Self {
+        let mut as_path = Self::sequence(vec![as_number]);
+        for segment in &self.0 {
+            as_path.push(segment.clone());
+        }
+        as_path
+    }
 
-                let path_segment_type = 2;
-                let number_of_as = s.len();
+    /// The AS the path entered ours from, unless it starts with an AS_SET.
+    pub fn neighbor_as(&self) -> Option<AutonomousSystemNumber> {
+        match self.0.first() {
+            Some(AsPathSegment::AsSequence(ases)) => ases.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Encodes the path segments with two- or four-octet AS numbers. The
+    /// empty path has no segment at all.
+    pub fn to_bytes(&self, four_octet_as: bool) -> BytesMut {
+        let mut bytes = BytesMut::new();
+        for segment in &self.0 {
+            let (path_segment_type, ases): (u8, Vec<AutonomousSystemNumber>) = match segment {
+                AsPathSegment::AsSet(s) => (1, s.iter().copied().collect()),
+                AsPathSegment::AsSequence(s) => (2, s.clone()),
+            };
+            // A segment holds at most 255 ASes; longer ones take several.
+            for chunk in ases.chunks(usize::from(u8::MAX)) {
                 bytes.put_u8(path_segment_type);
-                bytes.put_u8(number_of_as as u8);
-                bytes.put(
-                    &s.iter()
-                        .flat_map(|a| u16::from(*a).to_be_bytes())
-                        .collect::<Vec<u8>>()[..],
-                );
-                bytes
+                bytes.put_u8(chunk.len() as u8);
+                for as_number in chunk {
+                    put_as_number(&mut bytes, *as_number, four_octet_as);
+                }
             }
         }
+        bytes
+    }
+
+    /// Decodes the segments of an AS_PATH or AS4_PATH.
+    pub fn from_u8_slice(value: &[u8], four_octet_as: bool) -> anyhow::Result<Self> {
+        let as_number_length = as_number_length(four_octet_as);
+        let mut as_path = Self::default();
+        let mut rest = value;
+        while !rest.is_empty() {
+            let [segment_type, segment_length, ases @ ..] = rest else {
+                anyhow::bail!("truncated AS path segment: {:?}", rest);
+            };
+            if *segment_length == 0 {
+                anyhow::bail!("empty AS path segment: {:?}", rest);
+            }
+            let ases_length = usize::from(*segment_length) * as_number_length;
+            let ases_bytes = ases
+                .get(..ases_length)
+                .context(format!("truncated AS path segment: {:?}", rest))?;
+            let ases = ases_bytes
+                .chunks(as_number_length)
+                .map(as_number_from_u8_slice)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            as_path.push(match segment_type {
+                1 => AsPathSegment::AsSet(ases.into_iter().collect()),
+                2 => AsPathSegment::AsSequence(ases),
+                _ => anyhow::bail!("unknown AS path segment type: {}", segment_type),
+            });
+            rest = &rest[2 + ases_length..];
+        }
+
+        Ok(as_path)
+    }
+
+    /// Number of ASes the path counts as in route selection, where each
+    /// AS_SET counts as one (RFC 4271 §9.1.2.2).
+    pub fn path_length(&self) -> usize {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                AsPathSegment::AsSequence(as_sequence) => as_sequence.len(),
+                AsPathSegment::AsSet(_) => 1,
+            })
+            .sum()
+    }
+
+    fn contains_four_octet_as(&self) -> bool {
+        self.0.iter().any(|segment| match segment {
+            AsPathSegment::AsSequence(as_sequence) => as_sequence.iter().any(|a| a.is_four_octet()),
+            AsPathSegment::AsSet(as_set) => as_set.iter().any(|a| a.is_four_octet()),
+        })
+    }
+
+    /// Rebuilds the path received from a two-octet AS speaker using its
+    /// AS4_PATH, as described in RFC 6793 §4.2.3: the ASes the AS_PATH has
+    /// in excess are taken from its front and the AS4_PATH follows them.
+    fn merge_as4_path(self, as4_path: AsPath) -> AsPath {
+        let Some(mut leading) = self.path_length().checked_sub(as4_path.path_length()) else {
+            return self;
+        };
+
+        let mut as_path = Self::default();
+        for segment in self.0 {
+            if leading == 0 {
+                break;
+            }
+            match segment {
+                AsPathSegment::AsSequence(ases) => {
+                    let taken = leading.min(ases.len());
+                    leading -= taken;
+                    as_path.push(AsPathSegment::AsSequence(ases[..taken].to_vec()));
+                }
+                AsPathSegment::AsSet(ases) => {
+                    leading -= 1;
+                    as_path.push(AsPathSegment::AsSet(ases));
+                }
+            }
+        }
+        for segment in as4_path.0 {
+            as_path.push(segment);
+        }
+        as_path
     }
 }
 
@@ -76,166 +264,811 @@ impl TryFrom<u8> for Origin {
     }
 }
 
-impl TryFrom<&[u8]> for AsPath {
-    type Error = anyhow::Error;
+impl Aggregator {
+    fn to_bytes(self, four_octet_as: bool) -> BytesMut {
+        let mut bytes = BytesMut::new();
+        put_as_number(&mut bytes, self.as_number, four_octet_as);
+        bytes.put(&self.address.octets()[..]);
+        bytes
+    }
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        match value[0] {
-            1 => {
-                let mut ases = BTreeSet::new();
-                let mut i = 2;
-                while i < value.len() {
-                    ases.insert(u16::from_be_bytes(value[i..i + 2].try_into()?).into());
-                    i += 2;
-                }
-                Ok(AsPath::AsSet(ases))
-            }
-            2 => {
-                let mut ases = Vec::new();
-                let mut i = 2;
-                while i < value.len() {
-                    ases.push(u16::from_be_bytes(value[i..i + 2].try_into()?).into());
-                    i += 2;
-                }
-                Ok(AsPath::AsSequence(ases))
-            }
-            _ => Err(anyhow::anyhow!(format!(
-                "cannot convert to AsPath: {:?}",
-                value
-            ))),
+    fn from_u8_slice(value: &[u8], four_octet_as: bool) -> anyhow::Result<Self> {
+        let as_number_length = as_number_length(four_octet_as);
+        if value.len() != as_number_length + 4 {
+            return Err(anyhow::anyhow!("cannot convert to Aggregator: {:?}", value));
         }
+
+        let address: [u8; 4] = value[as_number_length..].try_into()?;
+        Ok(Self {
+            as_number: as_number_from_u8_slice(&value[..as_number_length])?,
+            address: Ipv4Addr::from(address),
+        })
     }
 }
 
-impl PathAttribute {
-    pub fn bytes_len(&self) -> usize {
-        let path_attribute_value_length = match self {
-            PathAttribute::Origin(_) => 1,
-            PathAttribute::AsPath(a) => a.bytes_len(),
-            PathAttribute::NextHop(_) => 4,
-            PathAttribute::DontKnow(v) => v.len(),
-        };
+impl Ipv6NextHop {
+    fn to_bytes(self) -> BytesMut {
+        let mut bytes = BytesMut::new();
+        bytes.put(&self.global.octets()[..]);
+        if let Some(link_local) = self.link_local {
+            bytes.put(&link_local.octets()[..]);
+        }
+        bytes
+    }
 
-        let length = path_attribute_value_length + 2;
+    fn from_u8_slice(value: &[u8]) -> anyhow::Result<Self> {
+        let address = |bytes: &[u8]| -> anyhow::Result<Ipv6Addr> {
+            let octets: [u8; 16] = bytes.try_into()?;
+            Ok(Ipv6Addr::from(octets))
+        };
 
-        if path_attribute_value_length > 255 {
-            length + 2
-        } else {
-            length + 1
+        match value.len() {
+            16 => Ok(Self {
+                global: address(value)?,
+                link_local: None,
+            }),
+            32 => Ok(Self {
+                global: address(&value[..16])?,
+                link_local: Some(address(&value[16..])?),
+            }),
+            _ => Err(anyhow::anyhow!(
+                "cannot convert to IPv6 next hop: {:?}",
+                value
+            )),
         }
     }
+}
+
+fn put_ipv6_unicast(bytes: &mut BytesMut) {
+    bytes.put_u16(Afi::Ipv6.into());
+    bytes.put_u8(Safi::Unicast.into());
+}
+
+fn is_ipv6_unicast(value: &[u8]) -> bool {
+    let [afi_high, afi_low, safi, ..] = *value else {
+        return false;
+    };
+    Afi::try_from(u16::from_be_bytes([afi_high, afi_low])).ok() == Some(Afi::Ipv6)
+        && Safi::try_from(safi).ok() == Some(Safi::Unicast)
+}
 
+impl MpReachNlri {
+    fn to_bytes(&self) -> BytesMut {
+        let next_hop = self.next_hop.to_bytes();
+
+        let mut bytes = BytesMut::new();
+        put_ipv6_unicast(&mut bytes);
+        bytes.put_u8(next_hop.len() as u8);
+        bytes.put(next_hop);
+        bytes.put_u8(0); // reserved
+        self.nlri
+            .iter()
+            .for_each(|r| bytes.put::<BytesMut>(r.into()));
+        bytes
+    }
+
+    fn from_u8_slice(value: &[u8]) -> Result<Self, ConvertBytesToBgpMessageError> {
+        let next_hop_length = *value.get(3).context("truncated MP_REACH_NLRI")? as usize;
+        let next_hop_end_index = 4 + next_hop_length;
+        let next_hop = Ipv6NextHop::from_u8_slice(
+            value
+                .get(4..next_hop_end_index)
+                .context("truncated MP_REACH_NLRI next hop")?,
+        )?;
+        let nlri = Ipv6Network::from_u8_slice(
+            value
+                .get(next_hop_end_index + 1..)
+                .context("truncated MP_REACH_NLRI")?,
+        )?;
+
+        Ok(Self { next_hop, nlri })
+    }
+}
+
+impl MpUnreachNlri {
+    fn to_bytes(&self) -> BytesMut {
+        let mut bytes = BytesMut::new();
+        put_ipv6_unicast(&mut bytes);
+        self.withdrawn_routes
+            .iter()
+            .for_each(|r| bytes.put::<BytesMut>(r.into()));
+        bytes
+    }
+
+    fn from_u8_slice(value: &[u8]) -> Result<Self, ConvertBytesToBgpMessageError> {
+        Ok(Self {
+            withdrawn_routes: Ipv6Network::from_u8_slice(
+                value.get(3..).context("truncated MP_UNREACH_NLRI")?,
+            )?,
+        })
+    }
+}
+
+/// Splits the first attribute off `rest`, returning its type code, the
+/// index its value starts at and the whole attribute, unless it is
+/// truncated.
+fn split_attribute(rest: &[u8]) -> Option<(u8, usize, &[u8])> {
+    let [attribute_flag, attribute_type_code, ..] = *rest else {
+        return None;
+    };
+    let (attribute_length, attribute_start_index) = if attribute_flag & 0b0001_0000 == 0 {
+        (usize::from(*rest.get(2)?), 3)
+    } else {
+        (usize::from(read_u16(rest, 2)?), 4)
+    };
+    let whole_attribute = rest.get(..attribute_start_index + attribute_length)?;
+    Some((attribute_type_code, attribute_start_index, whole_attribute))
+}
+
+fn malformed_attribute_list(rest: &[u8]) -> ConvertBytesToBgpMessageError {
+    update_message_error(
+        UpdateMessageErrorSubcode::MalformedAttributeList,
+        &[],
+        anyhow::anyhow!("truncated path attribute: {:?}", rest),
+    )
+}
+
+impl PathAttribute {
+    pub fn bytes_len(&self, four_octet_as: bool) -> usize {
+        self.to_bytes(four_octet_as).len()
+    }
+
+    /// Decodes the path attributes of an UPDATE. Errors carry the
+    /// NOTIFICATION of RFC 4271 §6.3, whose data field is the offending
+    /// attribute where one is called for.
     pub fn from_u8_slice(
         bytes: &[u8],
+        four_octet_as: bool,
     ) -> Result<Vec<PathAttribute>, ConvertBytesToBgpMessageError> {
+        let (path_attributes, errors) = Self::decode_list(bytes, four_octet_as)?;
+        match errors.into_iter().next() {
+            Some(error) => Err(error.into()),
+            None => Ok(path_attributes),
+        }
+    }
+
+    /// Decodes the path attributes of an UPDATE with the revised error
+    /// handling of RFC 7606: malformed attributes are left out and returned
+    /// alongside the others with the action they call for. Only errors that
+    /// leave no choice but a session reset fail the decoding.
+    pub fn decode_list(
+        bytes: &[u8],
+        four_octet_as: bool,
+    ) -> Result<(Vec<PathAttribute>, Vec<UpdateError>), ConvertBytesToBgpMessageError> {
         let mut path_attributes = vec![];
-        let mut i = 0;
-        while bytes.len() > i {
-            let attribute_flag = bytes[i];
-            let attribute_length_octets = ((attribute_flag & 0b0001_0000) >> 4) + 1;
-            let attribute_type_code = bytes[i + 1];
-            let attribute_length = if attribute_length_octets == 1 {
-                bytes[i + 2] as usize
-            } else {
-                u16::from_be_bytes(
-                    bytes[i + 2..i + 4]
-                        .try_into()
-                        .context("cannot convert to attribute_length")?,
-                ) as usize
+        let mut errors = vec![];
+        let mut attribute_type_codes = BTreeSet::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let Some((attribute_type_code, attribute_start_index, whole_attribute)) =
+                split_attribute(rest)
+            else {
+                // RFC 7606 §4: the NLRI can still be located, as the total
+                // path attribute length is known.
+                errors.push(UpdateError::new(
+                    UpdateErrorAction::TreatAsWithdraw,
+                    malformed_attribute_list(rest),
+                ));
+                break;
             };
+            rest = &rest[whole_attribute.len()..];
 
-            let attribute_start_index = i + 1 + attribute_length_octets as usize + 1;
-            let attribute_end_index = attribute_start_index + attribute_length;
-            let path_attribute = match attribute_type_code {
-                1 => PathAttribute::Origin(Origin::try_from(bytes[attribute_start_index])?),
-                2 => PathAttribute::AsPath(AsPath::try_from(
-                    &bytes[attribute_start_index..attribute_end_index],
-                )?),
-                3 => {
-                    let addr = Ipv4Addr::new(
-                        bytes[attribute_start_index],
-                        bytes[attribute_start_index + 1],
-                        bytes[attribute_start_index + 2],
-                        bytes[attribute_start_index + 3],
-                    );
-
-                    PathAttribute::NextHop(addr)
+            // RFC 7606 §3 (g): repeated MP_REACH_NLRI or MP_UNREACH_NLRI
+            // reset the session, any other repeated attribute is discarded.
+            if !attribute_type_codes.insert(attribute_type_code) {
+                let error = update_message_error(
+                    UpdateMessageErrorSubcode::MalformedAttributeList,
+                    &[],
+                    anyhow::anyhow!("attribute {} appears more than once", attribute_type_code),
+                );
+                if let 14 | 15 = attribute_type_code {
+                    return Err(error);
                 }
-                _ => PathAttribute::DontKnow(bytes[i..attribute_end_index].to_owned()),
+                errors.push(UpdateError::new(UpdateErrorAction::AttributeDiscard, error));
+                continue;
+            }
+
+            match Self::decode(
+                attribute_type_code,
+                &whole_attribute[attribute_start_index..],
+                whole_attribute,
+                four_octet_as,
+            ) {
+                Ok(path_attribute) => path_attributes.push(path_attribute),
+                Err(error) => errors.push(UpdateError {
+                    attribute_type_code: Some(attribute_type_code),
+                    ..UpdateError::new(Self::error_action(attribute_type_code), error)
+                }),
+            }
+        }
+
+        Ok((path_attributes, errors))
+    }
+
+    /// How an UPDATE with a malformed attribute of this type is handled
+    /// (RFC 7606 §7, RFC 6793 §6). Unrecognized well-known attributes are
+    /// treated as withdraw too.
+    fn error_action(attribute_type_code: u8) -> UpdateErrorAction {
+        match attribute_type_code {
+            // Only IPv6 unicast is decoded from MP_REACH_NLRI and
+            // MP_UNREACH_NLRI, other families are kept as they are.
+            14 | 15 => UpdateErrorAction::AfiSafiDisable(Afi::Ipv6, Safi::Unicast),
+            6 | 7 | 17 | 18 => UpdateErrorAction::AttributeDiscard,
+            _ => UpdateErrorAction::TreatAsWithdraw,
+        }
+    }
+
+    /// The Optional and Transitive bits an attribute we recognize is
+    /// defined with.
+    fn expected_flags(attribute_type_code: u8) -> Option<u8> {
+        match attribute_type_code {
+            1 | 2 | 3 | 5 | 6 => Some(TRANSITIVE),
+            4 | 14 | 15 => Some(OPTIONAL),
+            7 | 17 | 18 => Some(OPTIONAL | TRANSITIVE),
+            _ => None,
+        }
+    }
+
+    /// Decodes the value of one attribute; `whole_attribute` also holds its
+    /// flags, type and length, and is the data of the NOTIFICATION if it is
+    /// malformed.
+    fn decode(
+        attribute_type_code: u8,
+        attribute: &[u8],
+        whole_attribute: &[u8],
+        four_octet_as: bool,
+    ) -> Result<PathAttribute, ConvertBytesToBgpMessageError> {
+        let error = |subcode, error: anyhow::Error| {
+            let data = match subcode {
+                UpdateMessageErrorSubcode::MalformedAsPath => &[][..],
+                _ => whole_attribute,
             };
-            path_attributes.push(path_attribute);
-            i = attribute_end_index;
+            update_message_error(subcode, data, error)
+        };
+        let length_error = || {
+            error(
+                UpdateMessageErrorSubcode::AttributeLengthError,
+                anyhow::anyhow!("unexpected attribute length: {:?}", whole_attribute),
+            )
+        };
+
+        // RFC 7606 §3 (c): an attribute we recognize is malformed if its
+        // Optional or Transitive bit conflicts with its definition.
+        let attribute_flag = whole_attribute[0];
+        let recognized = !matches!(attribute_type_code, 14 | 15) || is_ipv6_unicast(attribute);
+        if let Some(expected_flags) = Self::expected_flags(attribute_type_code)
+            && recognized
+            && attribute_flag & (OPTIONAL | TRANSITIVE) != expected_flags
+        {
+            return Err(error(
+                UpdateMessageErrorSubcode::AttributeFlagsError,
+                anyhow::anyhow!(
+                    "unexpected flags {:#04x} of attribute {}",
+                    attribute_flag,
+                    attribute_type_code
+                ),
+            ));
         }
 
-        Ok(path_attributes)
+        let path_attribute = match attribute_type_code {
+            1 => {
+                let [origin] = *attribute else {
+                    return Err(length_error());
+                };
+                PathAttribute::Origin(
+                    Origin::try_from(origin)
+                        .map_err(|e| error(UpdateMessageErrorSubcode::InvalidOriginAttribute, e))?,
+                )
+            }
+            2 => PathAttribute::AsPath(
+                AsPath::from_u8_slice(attribute, four_octet_as)
+                    .map_err(|e| error(UpdateMessageErrorSubcode::MalformedAsPath, e))?,
+            ),
+            3 => {
+                let octets: [u8; 4] = attribute.try_into().map_err(|_| length_error())?;
+                PathAttribute::NextHop(Ipv4Addr::from(octets))
+            }
+            4 => PathAttribute::MultiExitDisc(u32::from_be_bytes(
+                attribute.try_into().map_err(|_| length_error())?,
+            )),
+            5 => PathAttribute::LocalPref(u32::from_be_bytes(
+                attribute.try_into().map_err(|_| length_error())?,
+            )),
+            // ATOMIC_AGGREGATE has no value; it is only passed on.
+            6 if !attribute.is_empty() => return Err(length_error()),
+            6 => PathAttribute::DontKnow(whole_attribute.to_owned()),
+            7 => PathAttribute::Aggregator(
+                Aggregator::from_u8_slice(attribute, four_octet_as).map_err(|_| length_error())?,
+            ),
+            14 if is_ipv6_unicast(attribute) => {
+                PathAttribute::MpReachNlri(MpReachNlri::from_u8_slice(attribute).map_err(|e| {
+                    error(UpdateMessageErrorSubcode::OptionalAttributeError, e.into())
+                })?)
+            }
+            15 if is_ipv6_unicast(attribute) => {
+                PathAttribute::MpUnreachNlri(MpUnreachNlri::from_u8_slice(attribute).map_err(
+                    |e| error(UpdateMessageErrorSubcode::OptionalAttributeError, e.into()),
+                )?)
+            }
+            17 => PathAttribute::As4Path(
+                AsPath::from_u8_slice(attribute, true)
+                    .map_err(|e| error(UpdateMessageErrorSubcode::OptionalAttributeError, e))?,
+            ),
+            18 => PathAttribute::As4Aggregator(
+                Aggregator::from_u8_slice(attribute, true).map_err(|_| length_error())?,
+            ),
+            14 | 15 => PathAttribute::DontKnow(whole_attribute.to_owned()),
+            _ if attribute_flag & OPTIONAL == 0 => {
+                return Err(error(
+                    UpdateMessageErrorSubcode::UnrecognizedWellKnownAttribute,
+                    anyhow::anyhow!("unrecognized well-known attribute {}", attribute_type_code),
+                ));
+            }
+            _ => PathAttribute::DontKnow(whole_attribute.to_owned()),
+        };
+
+        Ok(path_attribute)
     }
-}
 
-impl From<&PathAttribute> for BytesMut {
-    fn from(p: &PathAttribute) -> Self {
-        let mut bytes = BytesMut::new();
+    /// The attribute as passed on to other peers, or `None` if it must not
+    /// be. Unrecognized attributes are only passed on if transitive, with
+    /// the Partial bit set on optional ones (RFC 4271 §5, §9.1.3). The
+    /// MP_REACH_NLRI and MP_UNREACH_NLRI of address families we do not
+    /// decode carry next hops of the peer that sent them, so they never are.
+    pub fn propagated(&self) -> Option<PathAttribute> {
+        let PathAttribute::DontKnow(attribute) = self else {
+            return Some(self.clone());
+        };
+        let [attribute_flag, attribute_type_code, ..] = attribute[..] else {
+            return None;
+        };
+        if attribute_flag & TRANSITIVE == 0 || matches!(attribute_type_code, 14 | 15) {
+            return None;
+        }
+
+        let mut attribute = attribute.clone();
+        if attribute_flag & OPTIONAL != 0 {
+            attribute[0] |= PARTIAL;
+        }
+        Some(PathAttribute::DontKnow(attribute))
+    }
 
-        match p {
+    pub fn to_bytes(&self, four_octet_as: bool) -> BytesMut {
+        let well_known_transitive = 0b0100_0000;
+        let optional_non_transitive = 0b1000_0000;
+        let optional_transitive = 0b1100_0000;
+
+        let (attribute_flag, attribute_type_code, attribute) = match self {
             PathAttribute::Origin(o) => {
-                let attribute_flag = 0b0100_0000;
-                let attribute_type_code = 1;
-                let attribute_length = 1;
                 let attribute = match o {
                     Origin::Igp => 0,
                     Origin::Egp => 1,
                     Origin::Incomplete => 2,
                 };
+                (well_known_transitive, 1, BytesMut::from(&[attribute][..]))
+            }
+            PathAttribute::AsPath(a) => (well_known_transitive, 2, a.to_bytes(four_octet_as)),
+            PathAttribute::NextHop(n) => {
+                (well_known_transitive, 3, BytesMut::from(&n.octets()[..]))
+            }
+            PathAttribute::MultiExitDisc(m) => (
+                optional_non_transitive,
+                4,
+                BytesMut::from(&m.to_be_bytes()[..]),
+            ),
+            PathAttribute::LocalPref(l) => (
+                well_known_transitive,
+                5,
+                BytesMut::from(&l.to_be_bytes()[..]),
+            ),
+            PathAttribute::Aggregator(a) => (optional_transitive, 7, a.to_bytes(four_octet_as)),
+            PathAttribute::MpReachNlri(m) => (optional_non_transitive, 14, m.to_bytes()),
+            PathAttribute::MpUnreachNlri(m) => (optional_non_transitive, 15, m.to_bytes()),
+            PathAttribute::As4Path(a) => (optional_transitive, 17, a.to_bytes(true)),
+            PathAttribute::As4Aggregator(a) => (optional_transitive, 18, a.to_bytes(true)),
+            PathAttribute::DontKnow(v) => return BytesMut::from(&v[..]),
+        };
 
-                bytes.put_u8(attribute_flag);
-                bytes.put_u8(attribute_type_code);
-                bytes.put_u8(attribute_length);
-                bytes.put_u8(attribute);
-            }
-            PathAttribute::AsPath(a) => {
-                let mut attribute_flag = 0b0100_0000;
-                let attribute_type_code = 2;
-
-                let attribute_length = a.bytes_len() as u16;
-                let mut attribute_length_bytes = BytesMut::new();
-                if attribute_length < 256 {
-                    attribute_length_bytes.put_u8(attribute_length as u8);
-                } else {
-                    attribute_flag += 0b0001_0000;
-                    attribute_length_bytes.put_u16(attribute_length);
-                }
+        let mut bytes = BytesMut::new();
+        if attribute.len() < 256 {
+            bytes.put_u8(attribute_flag);
+            bytes.put_u8(attribute_type_code);
+            bytes.put_u8(attribute.len() as u8);
+        } else {
+            bytes.put_u8(attribute_flag | 0b0001_0000);
+            bytes.put_u8(attribute_type_code);
+            bytes.put_u16(attribute.len() as u16);
+        }
+        bytes.put(attribute);
 
-                let attribute = BytesMut::from(a);
+        bytes
+    }
 
-                bytes.put_u8(attribute_flag);
-                bytes.put_u8(attribute_type_code);
-                bytes.put(attribute_length_bytes);
-                bytes.put(attribute);
+    /// Rewrites path attributes for a speaker that does not support
+    /// four-octet AS numbers: AS numbers that do not fit are replaced by
+    /// AS_TRANS and the originals carried in AS4_PATH and AS4_AGGREGATOR
+    /// (RFC 6793 §4.2.2).
+    pub fn to_two_octet_as(path_attributes: &[PathAttribute]) -> Vec<PathAttribute> {
+        let mut converted = vec![];
+        for path_attribute in path_attributes {
+            match path_attribute {
+                PathAttribute::AsPath(a) if a.contains_four_octet_as() => {
+                    converted.push(path_attribute.clone());
+                    converted.push(PathAttribute::As4Path(a.clone()));
+                }
+                PathAttribute::Aggregator(a) if a.as_number.is_four_octet() => {
+                    converted.push(PathAttribute::Aggregator(Aggregator {
+                        as_number: AutonomousSystemNumber::AS_TRANS,
+                        address: a.address,
+                    }));
+                    converted.push(PathAttribute::As4Aggregator(*a));
+                }
+                PathAttribute::As4Path(_) | PathAttribute::As4Aggregator(_) => {}
+                _ => converted.push(path_attribute.clone()),
             }
-            PathAttribute::NextHop(n) => {
-                let mut attribute_flag = 0b0100_0000;
-                let attribute_type_code = 3;
-                let attribute_length = 4;
-                let attribute = n.octets();
+        }
+        converted
+    }
 
-                bytes.put_u8(attribute_flag);
-                bytes.put_u8(attribute_type_code);
-                bytes.put_u8(attribute_length);
-                bytes.put(&attribute[..]);
+    /// Reverses `to_two_octet_as` for attributes received from a two-octet
+    /// AS speaker (RFC 6793 §4.2.3), dropping AS4_PATH and AS4_AGGREGATOR.
+    pub fn from_two_octet_as(path_attributes: Vec<PathAttribute>) -> Vec<PathAttribute> {
+        let mut as4_path = None;
+        let mut as4_aggregator = None;
+        let mut aggregator_is_as_trans = true;
+        for path_attribute in &path_attributes {
+            match path_attribute {
+                PathAttribute::As4Path(a) => as4_path = Some(a.clone()),
+                PathAttribute::As4Aggregator(a) => as4_aggregator = Some(*a),
+                PathAttribute::Aggregator(a) => {
+                    aggregator_is_as_trans = a.as_number == AutonomousSystemNumber::AS_TRANS
+                }
+                _ => {}
             }
-            PathAttribute::DontKnow(v) => bytes.put(&v[..]),
+        }
+        // An aggregator that is not AS_TRANS was set by a two-octet speaker
+        // after the path left four-octet territory, so the AS4 attributes
+        // are stale.
+        if !aggregator_is_as_trans {
+            as4_path = None;
+            as4_aggregator = None;
         }
 
-        bytes
+        path_attributes
+            .into_iter()
+            .filter_map(|path_attribute| match path_attribute {
+                PathAttribute::AsPath(a) => Some(PathAttribute::AsPath(match &as4_path {
+                    Some(as4_path) => a.merge_as4_path(as4_path.clone()),
+                    None => a,
+                })),
+                PathAttribute::Aggregator(a) => {
+                    Some(PathAttribute::Aggregator(as4_aggregator.unwrap_or(a)))
+                }
+                PathAttribute::As4Path(_) | PathAttribute::As4Aggregator(_) => None,
+                path_attribute => Some(path_attribute),
+            })
+            .collect()
     }
 }
 
-impl AsPath {
-    fn bytes_len(&self) -> usize {
-        let as_bytes_length = match self {
-            AsPath::AsSequence(as_sequence) => 2 * as_sequence.len(),
-            AsPath::AsSet(as_set) => 2 * as_set.len(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::notification::ErrorCode;
+
+    fn as_sequence(ases: &[u32]) -> AsPath {
+        AsPath::sequence(ases.iter().map(|a| (*a).into()).collect())
+    }
+
+    #[test]
+    fn as_path_is_encoded_with_two_or_four_octet_as_numbers() {
+        let as_path = PathAttribute::AsPath(as_sequence(&[64512, 4_200_000_000]));
+
+        let two_octet = as_path.to_bytes(false);
+        assert_eq!(&two_octet[..], &[0x40, 2, 6, 2, 2, 0xfc, 0x00, 0x5b, 0xa0]);
+
+        let four_octet = as_path.to_bytes(true);
+        assert_eq!(four_octet.len(), 3 + 2 + 8);
+        assert_eq!(
+            PathAttribute::from_u8_slice(&four_octet, true).unwrap(),
+            vec![as_path]
+        );
+    }
+
+    #[test]
+    fn mp_reach_and_unreach_nlri_round_trip_for_ipv6_unicast() {
+        let path_attributes = vec![
+            PathAttribute::MpReachNlri(MpReachNlri {
+                next_hop: Ipv6NextHop {
+                    global: "2001:db8::1".parse().unwrap(),
+                    link_local: Some("fe80::1".parse().unwrap()),
+                },
+                nlri: vec!["2001:db8:1::/48".parse().unwrap()],
+            }),
+            PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                withdrawn_routes: vec!["2001:db8:2::/48".parse().unwrap()],
+            }),
+        ];
+        let bytes: BytesMut = path_attributes
+            .iter()
+            .flat_map(|p| p.to_bytes(true))
+            .collect();
+
+        assert_eq!(bytes[0], 0b1000_0000);
+        assert_eq!(bytes[6], 32);
+        assert_eq!(
+            PathAttribute::from_u8_slice(&bytes, true).unwrap(),
+            path_attributes
+        );
+    }
+
+    #[test]
+    fn mp_reach_nlri_of_other_address_family_is_kept_raw() {
+        // AFI 1 (IPv4), SAFI 2 (multicast), next hop 10.0.0.1, 10.0.0.0/8
+        let bytes = [0x80, 14, 11, 0, 1, 2, 4, 10, 0, 0, 1, 0, 8, 10];
+
+        assert_eq!(
+            PathAttribute::from_u8_slice(&bytes, true).unwrap(),
+            vec![PathAttribute::DontKnow(bytes.to_vec())]
+        );
+    }
+
+    #[test]
+    fn four_octet_as_path_survives_a_two_octet_speaker() {
+        let aggregator = Aggregator {
+            as_number: 4_200_000_001.into(),
+            address: "10.0.0.1".parse().unwrap(),
         };
+        let path_attributes = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(as_sequence(&[4_200_000_000, 64513])),
+            PathAttribute::Aggregator(aggregator),
+        ];
+
+        let two_octet = PathAttribute::to_two_octet_as(&path_attributes);
+        assert!(two_octet.contains(&PathAttribute::As4Path(as_sequence(&[
+            4_200_000_000,
+            64513
+        ]))));
+        let bytes: BytesMut = two_octet.iter().flat_map(|p| p.to_bytes(false)).collect();
+
+        // A two-octet speaker prepends its own AS before passing the route on.
+        let mut received = PathAttribute::from_u8_slice(&bytes, false).unwrap();
+        for path_attribute in received.iter_mut() {
+            if let PathAttribute::AsPath(a) = path_attribute {
+                assert_eq!(a.neighbor_as(), Some(AutonomousSystemNumber::AS_TRANS));
+                *a = a.prepend(64600.into());
+            }
+        }
+
+        assert_eq!(
+            PathAttribute::from_two_octet_as(received),
+            vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(as_sequence(&[64600, 4_200_000_000, 64513])),
+                PathAttribute::Aggregator(aggregator),
+            ]
+        );
+    }
+
+    fn update_message_error(bytes: &[u8]) -> (UpdateMessageErrorSubcode, BytesMut) {
+        let error = PathAttribute::from_u8_slice(bytes, true).unwrap_err();
+        let notification = error.notification().unwrap();
+        match notification.error_code {
+            ErrorCode::UpdateMessageError(subcode) => (subcode, notification.data.clone()),
+            error_code => panic!("expected update message error, but got {:?}", error_code),
+        }
+    }
+
+    #[test]
+    fn truncated_attributes_are_a_malformed_attribute_list() {
+        let bytes = PathAttribute::NextHop("10.0.0.1".parse().unwrap()).to_bytes(true);
+
+        for length in 1..bytes.len() {
+            assert_eq!(
+                update_message_error(&bytes[..length]).0,
+                UpdateMessageErrorSubcode::MalformedAttributeList
+            );
+        }
+    }
+
+    #[test]
+    fn attribute_of_unexpected_length_carries_the_attribute() {
+        let origin = [0x40, 1, 2, 0, 0];
+        assert_eq!(
+            update_message_error(&origin),
+            (
+                UpdateMessageErrorSubcode::AttributeLengthError,
+                BytesMut::from(&origin[..])
+            )
+        );
+
+        let origin = [0x40, 1, 1, 3];
+        assert_eq!(
+            update_message_error(&origin),
+            (
+                UpdateMessageErrorSubcode::InvalidOriginAttribute,
+                BytesMut::from(&origin[..])
+            )
+        );
+    }
+
+    #[test]
+    fn as_path_segment_longer_than_the_attribute_is_malformed() {
+        // AS_SEQUENCE claiming 3 ASes but holding 1
+        let as_path = [0x40, 2, 6, 2, 3, 0, 0, 0xfc, 0x00];
+
+        assert_eq!(
+            update_message_error(&as_path),
+            (UpdateMessageErrorSubcode::MalformedAsPath, BytesMut::new())
+        );
+    }
+
+    #[test]
+    fn as_path_segments_are_kept_apart() {
+        // AS_SEQUENCE 64512, then AS_SET {64513, 64514}
+        let bytes = [
+            2, 1, 0, 0, 0xfc, 0x00, 1, 2, 0, 0, 0xfc, 0x01, 0, 0, 0xfc, 0x02,
+        ];
+        let as_path = AsPath::from_u8_slice(&bytes, true).unwrap();
+
+        assert_eq!(
+            as_path.segments(),
+            [
+                AsPathSegment::AsSequence(vec![64512.into()]),
+                AsPathSegment::AsSet([64513.into(), 64514.into()].into()),
+            ]
+        );
+        assert_eq!(as_path.path_length(), 2);
+        assert_eq!(&as_path.to_bytes(true)[..], bytes);
+    }
+
+    #[test]
+    fn prepended_as_leads_a_path_starting_with_a_set() {
+        let as_path = AsPath::from_segments(vec![AsPathSegment::AsSet([64513.into()].into())]);
+
+        assert_eq!(
+            as_path.prepend(64512.into()).segments(),
+            [
+                AsPathSegment::AsSequence(vec![64512.into()]),
+                AsPathSegment::AsSet([64513.into()].into()),
+            ]
+        );
+        assert_eq!(as_path.neighbor_as(), None);
+    }
+
+    #[test]
+    fn as4_path_is_merged_behind_the_leading_ases_of_as_path() {
+        let as_trans = u32::from(AutonomousSystemNumber::AS_TRANS);
+        // 64600 prepended by a two-octet speaker to AS_TRANS {AS_TRANS}.
+        let as_path = AsPath::from_segments(vec![
+            AsPathSegment::AsSequence(vec![64600.into(), as_trans.into()]),
+            AsPathSegment::AsSet([as_trans.into()].into()),
+        ]);
+        let as4_path = AsPath::from_segments(vec![
+            AsPathSegment::AsSequence(vec![4_200_000_000.into()]),
+            AsPathSegment::AsSet([4_200_000_001.into()].into()),
+        ]);
+
+        assert_eq!(
+            as_path.merge_as4_path(as4_path).segments(),
+            [
+                AsPathSegment::AsSequence(vec![64600.into(), 4_200_000_000.into()]),
+                AsPathSegment::AsSet([4_200_000_001.into()].into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn as_path_longer_than_a_segment_is_split_and_joined_again() {
+        let as_path = as_sequence(&(64512..64812).collect::<Vec<u32>>());
+        let bytes = as_path.to_bytes(true);
+
+        assert_eq!(bytes.len(), 2 + 255 * 4 + 2 + 45 * 4);
+        assert_eq!(AsPath::from_u8_slice(&bytes, true).unwrap(), as_path);
+    }
+
+    #[test]
+    fn only_transitive_unrecognized_attributes_are_propagated() {
+        let optional_transitive = PathAttribute::DontKnow(vec![0xc0, 32, 1, 0]);
+        let optional_non_transitive = PathAttribute::DontKnow(vec![0x80, 99, 1, 0]);
+        let other_mp_reach_nlri = PathAttribute::DontKnow(vec![0xc0, 14, 3, 0, 1, 2]);
+
+        assert_eq!(
+            optional_transitive.propagated(),
+            Some(PathAttribute::DontKnow(vec![0xe0, 32, 1, 0]))
+        );
+        assert_eq!(optional_non_transitive.propagated(), None);
+        assert_eq!(other_mp_reach_nlri.propagated(), None);
+        assert_eq!(
+            PathAttribute::LocalPref(100).propagated(),
+            Some(PathAttribute::LocalPref(100))
+        );
+    }
+
+    /// The action, subcode and data of each error `bytes` decode with.
+    fn decode_errors(
+        bytes: &[u8],
+    ) -> Vec<(UpdateErrorAction, UpdateMessageErrorSubcode, BytesMut)> {
+        let (_, errors) = PathAttribute::decode_list(bytes, true).unwrap();
+        errors
+            .into_iter()
+            .map(|error| match error.notification.error_code {
+                ErrorCode::UpdateMessageError(subcode) => {
+                    (error.action, subcode, error.notification.data)
+                }
+                error_code => panic!("expected update message error, but got {:?}", error_code),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn attribute_with_conflicting_flags_is_handled_as_its_section_requires() {
+        let optional_origin = [0xc0, 1, 1, 0];
+        let transitive_med = [0xc0, 4, 4, 0, 0, 0, 1];
+        let well_known_aggregator = [0x40, 7, 8, 0, 0, 0xfc, 0x01, 10, 0, 0, 1];
+
+        for (attribute, action) in [
+            (&optional_origin[..], UpdateErrorAction::TreatAsWithdraw),
+            (&transitive_med[..], UpdateErrorAction::TreatAsWithdraw),
+            (
+                &well_known_aggregator[..],
+                UpdateErrorAction::AttributeDiscard,
+            ),
+        ] {
+            assert_eq!(
+                decode_errors(attribute),
+                vec![(
+                    action,
+                    UpdateMessageErrorSubcode::AttributeFlagsError,
+                    BytesMut::from(attribute)
+                )]
+            );
+        }
+
+        // The Partial and Extended Length bits do not matter.
+        let origin = [0x70, 1, 0, 1, 0];
+        assert_eq!(
+            PathAttribute::from_u8_slice(&origin, true).unwrap(),
+            vec![PathAttribute::Origin(Origin::Igp)]
+        );
+    }
+
+    #[test]
+    fn unrecognized_well_known_attribute_is_treated_as_withdraw() {
+        let well_known = [0x40, 99, 1, 0];
+        assert_eq!(
+            decode_errors(&well_known),
+            vec![(
+                UpdateErrorAction::TreatAsWithdraw,
+                UpdateMessageErrorSubcode::UnrecognizedWellKnownAttribute,
+                BytesMut::from(&well_known[..])
+            )]
+        );
+
+        let optional = [0xc0, 99, 1, 0];
+        assert_eq!(
+            PathAttribute::from_u8_slice(&optional, true).unwrap(),
+            vec![PathAttribute::DontKnow(optional.to_vec())]
+        );
+    }
+
+    #[test]
+    fn atomic_aggregate_is_recognized_and_discarded_if_malformed() {
+        let atomic_aggregate = [0x40, 6, 0];
+        assert_eq!(
+            PathAttribute::from_u8_slice(&atomic_aggregate, true).unwrap(),
+            vec![PathAttribute::DontKnow(atomic_aggregate.to_vec())]
+        );
 
-        2 + as_bytes_length
+        let with_value = [0x40, 6, 1, 0];
+        assert_eq!(
+            decode_errors(&with_value),
+            vec![(
+                UpdateErrorAction::AttributeDiscard,
+                UpdateMessageErrorSubcode::AttributeLengthError,
+                BytesMut::from(&with_value[..])
+            )]
+        );
     }
 }
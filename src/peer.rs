@@ -1,140 +1,2607 @@
-use tracing::info;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::Future,
+    net::Ipv4Addr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use futures::FutureExt;
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        watch,
+    },
+    time::Instant,
+};
+use tracing::{info, warn};
+
+use crate::{
+    bgp_type::{Afi, HoldTime, Safi},
+    config::{GlobalConfig, Mode, NeighborConfig},
+    connection::Connection,
+    error::{ConvertBytesToBgpMessageError, SendMessageError},
+    event::Event,
+    event_queue::EventQueue,
+    packets::{
+        capability::Capability,
+        message::Message,
+        notification::{
+            CeaseSubcode, ErrorCode, FiniteStateMachineErrorSubcode, MessageHeaderErrorSubcode,
+            NotificationMessage, OpenMessageErrorSubcode,
+        },
+        open::OpenMessage,
+        route_refresh::RouteRefreshMessage,
+        update::{UpdateErrorAction, UpdateMessage},
+    },
+    path_attribute::{AsPath, Ipv6NextHop, LOCAL_PREF_TYPE_CODE, MpReachNlri, PathAttribute},
+    routing::{
+        AdjRibIn, AdjRibOut, CandidatePath, DEFAULT_LOCAL_PREF, IpNetwork, LocRib, PathSource,
+    },
+    state::State,
+    timer::{Backoff, Timer},
+};
+
+/// Changes to a running peer's configuration.
+#[derive(Debug)]
+pub enum PeerCommand {
+    Reconfigure(GlobalConfig, NeighborConfig),
+    /// The neighbor was removed: the session is closed and the peer stops.
+    Deconfigure,
+    /// The daemon is exiting: the session is closed with an Administrative
+    /// Shutdown Cease and the peer stops.
+    Shutdown,
+}
+
+/// Hold time used while waiting for the remote OPEN (RFC 4271 §8.2.2 suggests 4 minutes).
+const LARGE_HOLD_TIME: Duration = Duration::from_secs(240);
+
+#[derive(Debug)]
+pub struct Peer {
+    state: State,
+    event_queue: EventQueue,
+    tcp_connection: Option<Connection>,
+    collision_connection: Option<Connection>,
+    dial: Option<Dial>,
+    incoming_connections: UnboundedReceiver<Connection>,
+    connection_sender: UnboundedSender<Connection>,
+    commands: UnboundedReceiver<PeerCommand>,
+    command_sender: UnboundedSender<PeerCommand>,
+    deconfigured: bool,
+    global: GlobalConfig,
+    neighbor: NeighborConfig,
+    hold_timer: Timer,
+    keepalive_timer: Timer,
+    connect_retry_timer: Timer,
+    delay_open_timer: Timer,
+    idle_hold_timer: Timer,
+    connect_retry_backoff: Backoff,
+    allow_automatic_start: bool,
+    passive_tcp_establishment: bool,
+    negotiated_capabilities: Vec<Capability>,
+    adj_rib_in: AdjRibIn,
+    loc_rib: Arc<Mutex<LocRib>>,
+    adj_rib_out: AdjRibOut,
+    remote_bgp_identifier: Ipv4Addr,
+    loc_rib_changes: watch::Receiver<u64>,
+    update_errors: BTreeMap<UpdateErrorAction, u64>,
+    ipv6_unicast_disabled: bool,
+}
+
+/// An outgoing connection attempt, awaited alongside everything else the
+/// peer waits for so that dialing never blocks it.
+struct Dial(Pin<Box<dyn Future<Output = anyhow::Result<Connection>> + Send>>);
+
+impl fmt::Debug for Dial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Dial")
+    }
+}
+
+/// Consecutive connection failures back off up to this multiple of ConnectRetryTime.
+const CONNECT_RETRY_BACKOFF_LIMIT: u32 = 16;
+
+impl Peer {
+    pub fn new(
+        global: GlobalConfig,
+        neighbor: NeighborConfig,
+        loc_rib: Arc<Mutex<LocRib>>,
+    ) -> Self {
+        let state = State::Idle;
+        let event_queue = EventQueue::new();
+        let (connection_sender, incoming_connections) = unbounded_channel();
+        let (command_sender, commands) = unbounded_channel();
+        let loc_rib_changes = loc_rib.lock().unwrap().subscribe();
+
+        Self {
+            state,
+            event_queue,
+            tcp_connection: None,
+            collision_connection: None,
+            dial: None,
+            incoming_connections,
+            connection_sender,
+            commands,
+            command_sender,
+            deconfigured: false,
+            hold_timer: Timer::new(LARGE_HOLD_TIME),
+            keepalive_timer: Timer::new(Duration::ZERO),
+            connect_retry_timer: Timer::new(neighbor.connect_retry_time),
+            delay_open_timer: Timer::new(neighbor.delay_open_time),
+            idle_hold_timer: Timer::new(neighbor.connect_retry_time),
+            connect_retry_backoff: Backoff::new(
+                neighbor.connect_retry_time,
+                neighbor.connect_retry_time * CONNECT_RETRY_BACKOFF_LIMIT,
+            ),
+            allow_automatic_start: false,
+            passive_tcp_establishment: neighbor.mode == Mode::Passive,
+            negotiated_capabilities: vec![],
+            adj_rib_in: AdjRibIn::new(),
+            loc_rib,
+            adj_rib_out: AdjRibOut::new(),
+            remote_bgp_identifier: Ipv4Addr::UNSPECIFIED,
+            loc_rib_changes,
+            update_errors: BTreeMap::new(),
+            ipv6_unicast_disabled: false,
+            global,
+            neighbor,
+        }
+    }
+
+    pub fn remote_ip(&self) -> Ipv4Addr {
+        self.neighbor.remote_ip
+    }
+
+    /// Where the listener hands over connections accepted from this peer.
+    pub fn connection_sender(&self) -> UnboundedSender<Connection> {
+        self.connection_sender.clone()
+    }
+
+    /// Where configuration changes for this peer are sent.
+    pub fn command_sender(&self) -> UnboundedSender<PeerCommand> {
+        self.command_sender.clone()
+    }
+
+    /// How many malformed UPDATEs were handled with each action since the
+    /// peer was created.
+    pub fn update_errors(&self) -> &BTreeMap<UpdateErrorAction, u64> {
+        &self.update_errors
+    }
+
+    /// Whether the neighbor was removed from the configuration or the daemon
+    /// is shutting down, after which the peer no longer needs to run.
+    pub fn is_deconfigured(&self) -> bool {
+        self.deconfigured
+    }
+
+    pub fn start(&mut self) {
+        info!("peer started");
+        self.allow_automatic_start = true;
+        let event = match self.neighbor.mode {
+            Mode::Active | Mode::Both => Event::ManualStart,
+            Mode::Passive => Event::ManualStartWithPassiveTcpEstablishment,
+        };
+        self.event_queue.enqueue(event);
+    }
+
+    pub fn stop(&mut self) {
+        info!("peer stopped");
+        self.event_queue.enqueue(Event::ManualStop);
+    }
+
+    /// Runs the peer until it is deconfigured. Between events it sleeps
+    /// until a command, a connection, a message, a timer or a change to
+    /// Loc-RIB wakes it up.
+    pub async fn run(&mut self) {
+        self.start();
+        while !self.deconfigured {
+            while let Some(event) = self.event_queue.dequeue() {
+                info!("event occurred, event={:?}", event);
+                self.handle_event(event).await;
+            }
+            self.advertise_routes_if_changed().await;
+            self.wait().await;
+        }
+    }
+
+    /// Handles whatever is ready without waiting: pending commands, expired
+    /// timers, a finished dial, one incoming connection, one event and one message from each
+    /// connection. Drives the peer step by step where `run` would sleep.
+    pub async fn next(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            self.handle_command(command).await;
+        }
+
+        self.enqueue_expired_timers();
+
+        if let Some(result) = dial(&mut self.dial).now_or_never() {
+            self.finish_dial(result);
+        }
+
+        if let Ok(connection) = self.incoming_connections.try_recv() {
+            self.accept_incoming_connection(connection).await;
+        }
+
+        if let Some(event) = self.event_queue.dequeue() {
+            info!("event occurred, event={:?}", event);
+            self.handle_event(event).await;
+        }
+
+        if let Some(connection) = self.collision_connection.as_mut() {
+            let received = connection.get_message().await;
+            self.receive_from_collision_connection(received).await;
+        }
+
+        self.advertise_routes_if_changed().await;
+
+        if let Some(connection) = self.tcp_connection.as_mut() {
+            let received = connection.get_message().await;
+            self.receive_from_connection(received).await;
+        }
+    }
+
+    /// Waits for the next thing to happen and handles it.
+    async fn wait(&mut self) {
+        let deadline = [
+            &self.hold_timer,
+            &self.keepalive_timer,
+            &self.connect_retry_timer,
+            &self.delay_open_timer,
+            &self.idle_hold_timer,
+        ]
+        .into_iter()
+        .filter_map(Timer::deadline)
+        .min();
+        let established = self.state == State::Established;
+
+        tokio::select! {
+            Some(command) = self.commands.recv() => self.handle_command(command).await,
+            Some(connection) = self.incoming_connections.recv() => {
+                self.accept_incoming_connection(connection).await;
+            }
+            result = dial(&mut self.dial) => self.finish_dial(result),
+            received = receive(&mut self.tcp_connection) => {
+                self.receive_from_connection(received).await;
+            }
+            received = receive(&mut self.collision_connection) => {
+                self.receive_from_collision_connection(received).await;
+            }
+            () = sleep_until(deadline) => self.enqueue_expired_timers(),
+            Ok(()) = self.loc_rib_changes.changed(), if established => {}
+        }
+    }
+
+    fn finish_dial(&mut self, result: anyhow::Result<Connection>) {
+        self.dial = None;
+        match result {
+            Ok(connection) if self.tcp_connection.is_none() => {
+                self.tcp_connection = Some(connection);
+                self.event_queue.enqueue(Event::TcpCrAcked);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("cannot establish TCP connection, error={:?}", e);
+                self.event_queue.enqueue(Event::TcpConnectionFails);
+            }
+        }
+    }
+
+    fn enqueue_expired_timers(&mut self) {
+        if self.hold_timer.is_expired() {
+            self.event_queue.enqueue(Event::HoldTimerExpires);
+        }
+        if self.keepalive_timer.is_expired() {
+            self.event_queue.enqueue(Event::KeepaliveTimerExpires);
+        }
+        if self.connect_retry_timer.is_expired() {
+            self.event_queue.enqueue(Event::ConnectRetryTimerExpires);
+        }
+        if self.delay_open_timer.is_expired() {
+            self.event_queue.enqueue(Event::DelayOpenTimerExpires);
+        }
+        if self.idle_hold_timer.is_expired() {
+            self.event_queue.enqueue(Event::IdleHoldTimerExpires);
+        }
+    }
+
+    async fn receive_from_connection(
+        &mut self,
+        received: Result<Option<Message>, ConvertBytesToBgpMessageError>,
+    ) {
+        match received {
+            Ok(Some(message)) => {
+                info!("message received, message={:?}", message);
+                self.handle_message(message).await;
+            }
+            Ok(None)
+                if self
+                    .tcp_connection
+                    .as_ref()
+                    .is_some_and(Connection::is_closed) =>
+            {
+                info!("connection closed by remote peer");
+                self.tcp_connection = None;
+                self.event_queue.enqueue(Event::TcpConnectionFails);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!("cannot parse received message, error={}", e);
+                self.handle_message_error(e);
+            }
+        }
+    }
+
+    async fn handle_command(&mut self, command: PeerCommand) {
+        match command {
+            PeerCommand::Reconfigure(global, neighbor) => self.reconfigure(global, neighbor).await,
+            PeerCommand::Deconfigure => {
+                info!("peer deconfigured");
+                self.close(CeaseSubcode::PeerDeconfigured).await;
+            }
+            PeerCommand::Shutdown => {
+                info!("peer shutting down");
+                self.close(CeaseSubcode::AdministrativeShutdown).await;
+            }
+        }
+    }
+
+    /// Applies a new configuration. The session is only reset if a parameter
+    /// it was negotiated or is addressed with changed; anything else, like
+    /// the mode or required capabilities, takes effect on the next session.
+    /// Otherwise the routes kept in Adj-RIB-In go through the import policy
+    /// again, and Adj-RIB-Out is re-synced so that export changes reach the
+    /// peer.
+    async fn reconfigure(&mut self, global: GlobalConfig, neighbor: NeighborConfig) {
+        let reset = self.global.local_as != global.local_as
+            || self.global.local_ip != global.local_ip
+            || self.neighbor.remote_as != neighbor.remote_as
+            || self.neighbor.hold_time != neighbor.hold_time
+            || self.neighbor.connect_retry_time != neighbor.connect_retry_time
+            || self.neighbor.delay_open_time != neighbor.delay_open_time
+            || self.neighbor.address_families != neighbor.address_families;
+        info!("peer reconfigured, reset={}", reset);
+        if reset {
+            // Before the configuration changes, so our paths are removed
+            // from Loc-RIB under the source they were learned from.
+            let notification = NotificationMessage::new(
+                ErrorCode::Cease(CeaseSubcode::OtherConfigurationChange),
+                BytesMut::new(),
+            );
+            self.reset_session(notification).await;
+        }
+
+        self.connect_retry_timer
+            .set_duration(neighbor.connect_retry_time);
+        self.delay_open_timer.set_duration(neighbor.delay_open_time);
+        self.connect_retry_backoff = Backoff::new(
+            neighbor.connect_retry_time,
+            neighbor.connect_retry_time * CONNECT_RETRY_BACKOFF_LIMIT,
+        );
+        self.passive_tcp_establishment = neighbor.mode == Mode::Passive;
+        let reimport = self.neighbor.import != neighbor.import;
+        self.global = global;
+        self.neighbor = neighbor;
+        if reimport {
+            self.reimport();
+        }
+        self.adj_rib_out.invalidate();
+    }
+
+    /// Runs every route in Adj-RIB-In through the import policy again.
+    fn reimport(&mut self) {
+        let networks: Vec<IpNetwork> = self.adj_rib_in.iter().map(|(n, _)| *n).collect();
+        self.loc_rib.lock().unwrap().update_from_adj_rib_in(
+            self.path_source(),
+            &self.adj_rib_in,
+            &networks,
+            self.neighbor.import.as_ref(),
+        );
+    }
+
+    /// Closes the session with a Cease for good: the peer then stops.
+    async fn close(&mut self, subcode: CeaseSubcode) {
+        self.manual_stop();
+        self.deconfigured = true;
+        let notification = NotificationMessage::new(ErrorCode::Cease(subcode), BytesMut::new());
+        self.reset_session(notification).await;
+    }
+
+    /// Closes the session with `notification`, unless there is none.
+    async fn reset_session(&mut self, notification: NotificationMessage) {
+        if self.state != State::Idle {
+            self.send_notification_and_release(notification).await;
+        }
+    }
+
+    /// An incoming connection either becomes the session's connection or, if
+    /// one already exists, is tracked for collision detection (RFC 4271 §6.8)
+    /// after sending our OPEN on it.
+    async fn accept_incoming_connection(&mut self, mut connection: Connection) {
+        match self.state {
+            _ if self.neighbor.mode == Mode::Active => {
+                info!("refusing connection from peer in active mode");
+            }
+            State::Idle => {
+                info!("refusing connection in idle state");
+                self.event_queue.enqueue(Event::TcpCrInvalid);
+            }
+            _ if self.tcp_connection.is_none() => {
+                self.tcp_connection = Some(connection);
+                self.event_queue.enqueue(Event::TcpConnectionConfirmed);
+            }
+            State::Established => {
+                info!("closing connection colliding with established session");
+                close_colliding(connection).await;
+            }
+            _ if self.collision_connection.is_none() => {
+                if let Err(e) = connection.send(self.open_message()).await {
+                    warn!("cannot send open on colliding connection, error={:?}", e);
+                    return;
+                }
+                self.collision_connection = Some(connection);
+                self.event_queue.enqueue(Event::TcpConnectionValid);
+            }
+            _ => {
+                self.event_queue.enqueue(Event::TcpCrInvalid);
+            }
+        }
+    }
+
+    async fn receive_from_collision_connection(
+        &mut self,
+        received: Result<Option<Message>, ConvertBytesToBgpMessageError>,
+    ) {
+        match received {
+            Ok(Some(Message::Open(open))) => {
+                info!("open received on colliding connection, open={:?}", open);
+                if self.state == State::Established {
+                    self.close_collision_connection().await;
+                } else if !self.resolve_collision(open.bgp_identifier()).await {
+                    self.state = State::OpenSent;
+                    self.event_queue.enqueue(Event::BgpOpen(open));
+                }
+            }
+            Ok(None)
+                if self
+                    .collision_connection
+                    .as_ref()
+                    .is_some_and(Connection::is_closed) =>
+            {
+                info!("colliding connection closed by remote peer");
+                self.collision_connection = None;
+            }
+            Ok(None) => {}
+            Ok(Some(message)) => {
+                warn!(
+                    "unexpected message on colliding connection, message={:?}",
+                    message
+                );
+                self.collision_connection = None;
+            }
+            Err(e) => {
+                warn!("cannot parse message on colliding connection, error={}", e);
+                self.collision_connection = None;
+            }
+        }
+    }
+
+    /// Keeps the connection initiated by the speaker with the higher BGP
+    /// Identifier and closes the other one with a Cease NOTIFICATION. Returns
+    /// whether the session's current connection survived.
+    async fn resolve_collision(&mut self, remote_bgp_identifier: Ipv4Addr) -> bool {
+        let Some(collision_connection) = self.collision_connection.take() else {
+            return true;
+        };
+        let Some(connection) = self.tcp_connection.take() else {
+            self.tcp_connection = Some(collision_connection);
+            return false;
+        };
+
+        let keep_locally_initiated =
+            u32::from(self.global.local_ip) > u32::from(remote_bgp_identifier);
+        // A second connection in the same direction means the old one is stale.
+        let keep_current = connection.is_initiated_locally()
+            != collision_connection.is_initiated_locally()
+            && connection.is_initiated_locally() == keep_locally_initiated;
+        info!(
+            "connection collision detected, remote-bgp-identifier={:?}, keep-current={}",
+            remote_bgp_identifier, keep_current
+        );
+
+        let (survivor, loser) = if keep_current {
+            (connection, collision_connection)
+        } else {
+            (collision_connection, connection)
+        };
+        close_colliding(loser).await;
+        self.tcp_connection = Some(survivor);
+
+        keep_current
+    }
+
+    async fn close_collision_connection(&mut self) {
+        if let Some(connection) = self.collision_connection.take() {
+            close_colliding(connection).await;
+        }
+    }
+
+    async fn handle_message(&mut self, message: Message) {
+        match message {
+            Message::Open(open) if self.delay_open_timer.is_running() => {
+                self.event_queue
+                    .enqueue(Event::BgpOpenWithDelayOpenTimerRunning(open));
+            }
+            Message::Open(open) => {
+                self.event_queue.enqueue(Event::BgpOpen(open));
+            }
+            Message::Keepalive(keepalive) => {
+                self.event_queue.enqueue(Event::KeepaliveMsg(keepalive));
+            }
+            Message::Update(update) => {
+                self.event_queue.enqueue(Event::UpdateMsg(update));
+            }
+            Message::Notification(notification)
+                if notification.error_code
+                    == ErrorCode::OpenMessageError(
+                        OpenMessageErrorSubcode::UnsupportedVersionNumber,
+                    ) =>
+            {
+                self.event_queue
+                    .enqueue(Event::NotifMsgVerErr(notification));
+            }
+            Message::Notification(notification) => {
+                self.event_queue.enqueue(Event::NotifMsg(notification));
+            }
+            Message::RouteRefresh(route_refresh) => {
+                self.event_queue
+                    .enqueue(Event::RouteRefreshMsg(route_refresh));
+            }
+        }
+    }
+
+    fn handle_message_error(&mut self, error: ConvertBytesToBgpMessageError) {
+        // RFC 4271 §4.5: a NOTIFICATION is never sent in response to one;
+        // the peer is closing the connection anyway.
+        if error.is_in_notification() {
+            self.tcp_connection = None;
+            self.event_queue.enqueue(Event::TcpConnectionFails);
+            return;
+        }
+
+        let notification = error.notification().cloned().unwrap_or_else(|| {
+            NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::Unspecific),
+                BytesMut::new(),
+            )
+        });
+
+        let event = match notification.error_code {
+            ErrorCode::OpenMessageError(_) => Event::BgpOpenMsgErr(notification),
+            ErrorCode::UpdateMessageError(_) => Event::UpdateMsgErr(notification),
+            _ => Event::BgpHeaderErr(notification),
+        };
+        self.event_queue.enqueue(event);
+    }
+
+    /// Transition table of RFC 4271 §8.2.2. The optional session attributes
+    /// DelayOpen, DampPeerOscillations and PassiveTcpEstablishment are
+    /// supported; SendNOTIFICATIONwithoutOPEN and CollisionDetectEstablishedState
+    /// are not.
+    async fn handle_event(&mut self, event: Event) {
+        let current_state = self.state;
+
+        match (self.state, event) {
+            (State::Idle, Event::ManualStart | Event::AutomaticStart) => {
+                self.initiate_tcp_connection(false);
+            }
+            (
+                State::Idle,
+                Event::ManualStartWithPassiveTcpEstablishment
+                | Event::AutomaticStartWithPassiveTcpEstablishment,
+            ) => {
+                self.initiate_tcp_connection(true);
+            }
+            (State::Idle, Event::AutomaticStartWithDampPeerOscillations)
+                if !self.idle_hold_timer.is_running() =>
+            {
+                self.initiate_tcp_connection(false);
+            }
+            (
+                State::Idle,
+                Event::AutomaticStartWithDampPeerOscillationsAndPassiveTcpEstablishment,
+            ) if !self.idle_hold_timer.is_running() => {
+                self.initiate_tcp_connection(true);
+            }
+            (State::Idle, Event::IdleHoldTimerExpires) if self.allow_automatic_start => {
+                info!("restarting session automatically");
+                let event = if self.passive_tcp_establishment {
+                    Event::AutomaticStartWithDampPeerOscillationsAndPassiveTcpEstablishment
+                } else {
+                    Event::AutomaticStartWithDampPeerOscillations
+                };
+                self.event_queue.enqueue(event);
+            }
+            (State::Idle, _) => {}
+
+            (_, event) if event.is_start() => {}
+            (_, Event::TcpCrInvalid) => {}
+            (State::Connect | State::Active, Event::ManualStop) => {
+                self.manual_stop();
+                self.release_connection();
+            }
+            (State::Connect, Event::ConnectRetryTimerExpires) => {
+                self.tcp_connection = None;
+                self.delay_open_timer.stop();
+                self.initiate_tcp_connection(self.passive_tcp_establishment);
+            }
+            (State::Active, Event::ConnectRetryTimerExpires) => {
+                self.initiate_tcp_connection(self.passive_tcp_establishment);
+            }
+            (State::Connect | State::Active, Event::DelayOpenTimerExpires) => {
+                self.send_open().await;
+            }
+            (State::Connect | State::Active, Event::TcpConnectionValid) => {}
+            (State::Connect | State::Active, Event::TcpCrAcked | Event::TcpConnectionConfirmed) => {
+                self.dial = None;
+                self.connect_retry_timer.stop();
+                if self.neighbor.delay_open_time.is_zero() {
+                    self.send_open().await;
+                } else {
+                    self.delay_open_timer.start();
+                }
+            }
+            (State::Connect, Event::TcpConnectionFails) if self.delay_open_timer.is_running() => {
+                self.tcp_connection = None;
+                self.delay_open_timer.stop();
+                self.connect_retry_timer.start();
+                self.state = State::Active;
+            }
+            (State::Connect | State::Active, Event::BgpOpenWithDelayOpenTimerRunning(open)) => {
+                self.connect_retry_timer.stop();
+                self.delay_open_timer.stop();
+                self.send_open().await;
+                self.accept_open(open).await;
+            }
+            (State::Connect | State::Active, _) => {
+                self.release_connection();
+            }
+
+            (_, Event::ManualStop) => {
+                self.manual_stop();
+                let notification = NotificationMessage::new(
+                    ErrorCode::Cease(CeaseSubcode::AdministrativeShutdown),
+                    BytesMut::new(),
+                );
+                self.send_notification_and_release(notification).await;
+            }
+            (_, Event::AutomaticStop) => {
+                let notification = NotificationMessage::new(
+                    ErrorCode::Cease(CeaseSubcode::Unspecific),
+                    BytesMut::new(),
+                );
+                self.send_notification_and_release(notification).await;
+            }
+            (_, Event::HoldTimerExpires) => {
+                let notification =
+                    NotificationMessage::new(ErrorCode::HoldTimerExpired, BytesMut::new());
+                self.send_notification_and_release(notification).await;
+            }
+            (_, Event::TcpConnectionValid | Event::TcpCrAcked | Event::TcpConnectionConfirmed) => {}
+            (_, Event::OpenCollisionDump) => {
+                let notification = NotificationMessage::new(
+                    ErrorCode::Cease(CeaseSubcode::ConnectionCollisionResolution),
+                    BytesMut::new(),
+                );
+                self.send_notification_and_release(notification).await;
+            }
+            (State::OpenSent, Event::TcpConnectionFails) => {
+                self.tcp_connection = None;
+                self.hold_timer.stop();
+                self.connect_retry_timer.start();
+                self.state = State::Active;
+            }
+            // A NOTIFICATION is never answered with another one (RFC 4271 §6.4).
+            (_, Event::TcpConnectionFails | Event::NotifMsg(_) | Event::NotifMsgVerErr(_)) => {
+                self.release_connection();
+            }
+            (State::OpenSent, Event::BgpOpen(open)) => {
+                self.connect_retry_timer.stop();
+                self.delay_open_timer.stop();
+                if self.resolve_collision(open.bgp_identifier()).await {
+                    self.accept_open(open).await;
+                } else {
+                    self.hold_timer.set_duration(LARGE_HOLD_TIME);
+                    self.hold_timer.start();
+                }
+            }
+            (State::OpenSent | State::OpenConfirm, Event::BgpOpenMsgErr(notification))
+            | (_, Event::BgpHeaderErr(notification))
+            | (State::Established, Event::UpdateMsgErr(notification)) => {
+                self.send_notification_and_release(notification).await;
+            }
+            (State::OpenConfirm | State::Established, Event::KeepaliveTimerExpires) => {
+                self.send(Message::new_keepalive()).await;
+                self.keepalive_timer.start();
+            }
+            (State::OpenConfirm, Event::KeepaliveMsg(_)) => {
+                self.close_collision_connection().await;
+                self.hold_timer.start();
+                self.connect_retry_backoff.reset();
+                self.state = State::Established;
+            }
+            (State::Established, Event::KeepaliveMsg(_)) => {
+                self.hold_timer.start();
+            }
+            (State::Established, Event::RouteRefreshMsg(route_refresh)) => {
+                self.refresh_routes(route_refresh);
+            }
+            (State::Established, Event::UpdateMsg(update)) => {
+                self.hold_timer.start();
+                let update = self.handle_update_errors(update);
+                let networks = self.adj_rib_in.update(&update);
+                let mut loc_rib = self.loc_rib.lock().unwrap();
+                loc_rib.update_from_adj_rib_in(
+                    self.path_source(),
+                    &self.adj_rib_in,
+                    &networks,
+                    self.neighbor.import.as_ref(),
+                );
+                for network in &networks {
+                    if let Some(decision) = loc_rib.decision(network) {
+                        info!(
+                            "best path selected, network={:?}, peer={:?}, reason={}",
+                            network, decision.best.source.peer_address, decision.reason
+                        );
+                    }
+                }
+            }
+            (state, event) => {
+                let notification = Self::unexpected_event_notification(state, &event);
+                self.send_notification_and_release(notification).await;
+            }
+        }
+
+        if self.state != current_state {
+            info!(
+                "state transitioned, from={:?}, to={:?}",
+                current_state, self.state
+            );
+        }
+    }
+
+    /// Starts connecting to the remote peer, or with PassiveTcpEstablishment
+    /// only waits for the listener to hand over its connection. The dial runs
+    /// while `wait` waits for everything else, and is given up for a new one
+    /// when ConnectRetryTimer expires first.
+    fn initiate_tcp_connection(&mut self, passive: bool) {
+        self.passive_tcp_establishment = passive;
+        self.state = if passive {
+            State::Active
+        } else {
+            State::Connect
+        };
+        self.connect_retry_timer
+            .set_duration(self.neighbor.connect_retry_time);
+        self.connect_retry_timer.start();
+
+        self.dial = (!passive).then(|| {
+            Dial(Box::pin(Connection::connect(
+                self.global.local_ip,
+                self.neighbor.remote_ip,
+            )))
+        });
+    }
+
+    fn local_capabilities(&self) -> Vec<Capability> {
+        let mut capabilities = self.neighbor.address_families.clone();
+        capabilities.push(Capability::RouteRefresh);
+        capabilities.push(Capability::FourOctetAsNumber(self.global.local_as));
+        capabilities
+    }
+
+    fn open_message(&self) -> Message {
+        Message::new_open(
+            self.global.local_as,
+            self.global.local_ip,
+            self.neighbor.hold_time,
+            self.local_capabilities(),
+        )
+    }
+
+    async fn send_open(&mut self) {
+        self.send(self.open_message()).await;
+        self.hold_timer.set_duration(LARGE_HOLD_TIME);
+        self.hold_timer.start();
+        self.state = State::OpenSent;
+    }
+
+    async fn accept_open(&mut self, open: OpenMessage) {
+        if let Some(notification) = self.validate_open(&open) {
+            warn!("unacceptable open, open={:?}", open);
+            self.send_notification_and_release(notification).await;
+            return;
+        }
+
+        self.remote_bgp_identifier = open.bgp_identifier();
+        self.negotiated_capabilities =
+            Capability::negotiate(&self.local_capabilities(), open.capabilities());
+        info!(
+            "capabilities negotiated, capabilities={:?}",
+            self.negotiated_capabilities
+        );
+        let four_octet_as = self
+            .negotiated_capabilities
+            .iter()
+            .any(|capability| matches!(capability, Capability::FourOctetAsNumber(_)));
+        if let Some(connection) = self.tcp_connection.as_mut() {
+            connection.set_four_octet_as(four_octet_as);
+        }
+
+        let hold_time = self.neighbor.hold_time.negotiate(open.hold_time());
+        info!("hold time negotiated, hold-time={:?}", hold_time);
+        self.hold_timer.set_duration(hold_time.into());
+        self.keepalive_timer
+            .set_duration(hold_time.keepalive_interval());
+
+        self.send(Message::new_keepalive()).await;
+        self.hold_timer.start();
+        self.keepalive_timer.start();
+        self.state = State::OpenConfirm;
+    }
+
+    /// The checks of RFC 4271 §6.2 that depend on our configuration; the
+    /// message format itself was already validated when it was decoded.
+    fn validate_open(&self, open: &OpenMessage) -> Option<NotificationMessage> {
+        let bgp_identifier = open.bgp_identifier();
+        let missing_capabilities: Vec<&Capability> = self
+            .neighbor
+            .required_capabilities
+            .iter()
+            .filter(|capability| !open.capabilities().iter().any(|c| capability.matches(c)))
+            .collect();
+
+        let (subcode, data) = if open.as_number() != self.neighbor.remote_as {
+            (OpenMessageErrorSubcode::BadPeerAs, BytesMut::new())
+        } else if bgp_identifier.is_unspecified()
+            || bgp_identifier.is_broadcast()
+            || bgp_identifier.is_multicast()
+            || bgp_identifier == self.global.local_ip
+        {
+            (OpenMessageErrorSubcode::BadBgpIdentifier, BytesMut::new())
+        } else if !open.hold_time().is_acceptable() {
+            (
+                OpenMessageErrorSubcode::UnacceptableHoldTime,
+                BytesMut::new(),
+            )
+        } else if !missing_capabilities.is_empty() {
+            // RFC 5492 §5: the data field lists the capabilities we lack.
+            let data = missing_capabilities
+                .into_iter()
+                .flat_map(BytesMut::from)
+                .collect();
+            (OpenMessageErrorSubcode::UnsupportedCapability, data)
+        } else {
+            return None;
+        };
+
+        Some(NotificationMessage::new(
+            ErrorCode::OpenMessageError(subcode),
+            data,
+        ))
+    }
+
+    /// Sends the peer what changed in Loc-RIB since the last call; on a new
+    /// session this is the whole table.
+    async fn advertise_routes_if_changed(&mut self) {
+        let synced = self
+            .adj_rib_out
+            .is_synced_with(&self.loc_rib.lock().unwrap());
+        if self.state != State::Established || synced {
+            return;
+        }
+
+        let ipv6_unicast = self.ipv6_unicast();
+        let updates = {
+            let loc_rib = self.loc_rib.lock().unwrap();
+            self.adj_rib_out.sync(&loc_rib, |network, path| {
+                if !network.is_ipv4() && !ipv6_unicast {
+                    return None;
+                }
+                Self::export(&self.global, &self.neighbor, network, path)
+            })
+        };
+        for update in updates {
+            info!("sending update, update={:?}", update);
+            self.send(Message::Update(update)).await;
+        }
+    }
+
+    /// Whether IPv6 unicast routes are exchanged on the session.
+    fn ipv6_unicast(&self) -> bool {
+        !self.ipv6_unicast_disabled
+            && self
+                .negotiated_capabilities
+                .contains(&Capability::MultiprotocolExtensions {
+                    afi: Afi::Ipv6,
+                    safi: Safi::Unicast,
+                })
+    }
+
+    /// Advertises the routes of the requested address family again
+    /// (RFC 2918 §4); a request for a family not exchanged is ignored.
+    fn refresh_routes(&mut self, route_refresh: RouteRefreshMessage) {
+        let afi = match route_refresh.address_family() {
+            Some((Afi::Ipv4, Safi::Unicast)) => Afi::Ipv4,
+            Some((Afi::Ipv6, Safi::Unicast)) if self.ipv6_unicast() => Afi::Ipv6,
+            _ => {
+                warn!(
+                    "ignoring route refresh for a family not exchanged, message={:?}",
+                    route_refresh
+                );
+                return;
+            }
+        };
+        info!("route refresh requested, afi={:?}", afi);
+        self.adj_rib_out.clear_family(afi);
+    }
+
+    /// Attributes to advertise `path` to `network` with to the configured
+    /// peer, or `None` if it must not be advertised (RFC 4271 §9.2). IPv6
+    /// routes carry their next hop in an MP_REACH_NLRI without prefixes;
+    /// eBGP peers only get them if `local-ipv6` is configured.
+    fn export(
+        global: &GlobalConfig,
+        neighbor: &NeighborConfig,
+        network: &IpNetwork,
+        path: &CandidatePath,
+    ) -> Option<Vec<PathAttribute>> {
+        let ibgp = global.local_as == neighbor.remote_as;
+        if path.source.peer_address == neighbor.remote_ip || (ibgp && path.source.ibgp) {
+            return None;
+        }
+        let ipv6_next_hop = match network {
+            IpNetwork::V4(_) => None,
+            IpNetwork::V6(_) if ibgp => {
+                Some(path.path_attributes.iter().find_map(|p| match p {
+                    PathAttribute::MpReachNlri(m) => Some(m.next_hop),
+                    _ => None,
+                })?)
+            }
+            IpNetwork::V6(_) => Some(Ipv6NextHop {
+                global: global.local_ipv6?,
+                link_local: None,
+            }),
+        };
+
+        let mut path_attributes = vec![];
+        let mut has_as_path = false;
+        for path_attribute in path.path_attributes.iter() {
+            match path_attribute {
+                PathAttribute::AsPath(as_path) if !ibgp => {
+                    has_as_path = true;
+                    path_attributes.push(PathAttribute::AsPath(as_path.prepend(global.local_as)));
+                }
+                PathAttribute::AsPath(_) => {
+                    has_as_path = true;
+                    path_attributes.push(path_attribute.clone());
+                }
+                PathAttribute::NextHop(_) if ipv6_next_hop.is_some() => {}
+                PathAttribute::NextHop(_) if !ibgp => {
+                    path_attributes.push(PathAttribute::NextHop(global.local_ip));
+                }
+                // LOCAL_PREF stays inside our AS and MULTI_EXIT_DISC must not
+                // reach ASes beyond the one that sent it.
+                PathAttribute::LocalPref(_) | PathAttribute::MultiExitDisc(_) if !ibgp => {}
+                PathAttribute::MpReachNlri(_) | PathAttribute::MpUnreachNlri(_) => {}
+                _ => path_attributes.extend(path_attribute.propagated()),
+            }
+        }
+        if !has_as_path {
+            let ases = if ibgp { vec![] } else { vec![global.local_as] };
+            path_attributes.push(PathAttribute::AsPath(AsPath::sequence(ases)));
+        }
+        if let Some(next_hop) = ipv6_next_hop {
+            path_attributes.push(PathAttribute::MpReachNlri(MpReachNlri {
+                next_hop,
+                nlri: vec![],
+            }));
+        }
+        if ibgp
+            && !path_attributes
+                .iter()
+                .any(|p| matches!(p, PathAttribute::LocalPref(_)))
+        {
+            path_attributes.push(PathAttribute::LocalPref(DEFAULT_LOCAL_PREF));
+        }
+
+        Some(path_attributes)
+    }
+
+    /// Applies the actions RFC 7606 calls for on the errors found in
+    /// `update`, returning what is left of it to process. None of them
+    /// resets the session: those errors fail the decoding instead. LOCAL_PREF
+    /// from an external peer is discarded, even a malformed one (§7.5).
+    fn handle_update_errors(&mut self, update: UpdateMessage) -> UpdateMessage {
+        let ebgp = !self.path_source().ibgp;
+        let mut treat_as_withdraw = false;
+        for error in update.errors() {
+            let action = if ebgp && error.attribute_type_code == Some(LOCAL_PREF_TYPE_CODE) {
+                UpdateErrorAction::AttributeDiscard
+            } else {
+                error.action
+            };
+            let count = self.update_errors.entry(action).or_default();
+            *count += 1;
+            warn!(
+                "malformed update handled, action={:?}, count={}, error={}",
+                action, count, error.reason
+            );
+            match action {
+                UpdateErrorAction::AttributeDiscard => {}
+                UpdateErrorAction::TreatAsWithdraw => treat_as_withdraw = true,
+                UpdateErrorAction::AfiSafiDisable(Afi::Ipv6, Safi::Unicast) => {
+                    self.disable_ipv6_unicast()
+                }
+                UpdateErrorAction::AfiSafiDisable(..) => {}
+            }
+        }
+
+        let update = if ebgp {
+            update.without_local_pref()
+        } else {
+            update
+        };
+        let update = if self.ipv6_unicast_disabled {
+            update.without_ipv6_unicast()
+        } else {
+            update
+        };
+        if treat_as_withdraw {
+            update.into_withdraw()
+        } else {
+            update
+        }
+    }
+
+    /// Forgets the IPv6 routes learned from the peer and ignores further
+    /// ones until the session is released.
+    fn disable_ipv6_unicast(&mut self) {
+        if self.ipv6_unicast_disabled {
+            return;
+        }
+        warn!("ipv6 unicast disabled for the session");
+        self.ipv6_unicast_disabled = true;
+        let networks = self.adj_rib_in.remove_ipv6();
+        self.loc_rib.lock().unwrap().update_from_adj_rib_in(
+            self.path_source(),
+            &self.adj_rib_in,
+            &networks,
+            self.neighbor.import.as_ref(),
+        );
+    }
+
+    fn path_source(&self) -> PathSource {
+        PathSource {
+            peer_address: self.neighbor.remote_ip,
+            router_id: self.remote_bgp_identifier,
+            peer_as: self.neighbor.remote_as,
+            ibgp: self.global.local_as == self.neighbor.remote_as,
+            local: false,
+        }
+    }
+
+    /// Sends `message` on the session's connection. One that cannot be
+    /// written to fails like one closed by the remote peer.
+    async fn send(&mut self, message: Message) {
+        let Some(connection) = self.tcp_connection.as_mut() else {
+            return;
+        };
+        match connection.send(message).await {
+            Ok(()) => {}
+            Err(SendMessageError::Io(e)) => {
+                warn!("cannot send message, error={:?}", e);
+                self.tcp_connection = None;
+                self.event_queue.enqueue(Event::TcpConnectionFails);
+            }
+            Err(e) => warn!("cannot send message, error={:?}", e),
+        }
+    }
+
+    /// Sends `notification` and drops the session, whether or not it could
+    /// be sent.
+    async fn send_notification_and_release(&mut self, notification: NotificationMessage) {
+        warn!("sending notification, notification={:?}", notification);
+        if let Some(connection) = self.tcp_connection.as_mut()
+            && let Err(e) = connection.send(Message::Notification(notification)).await
+        {
+            warn!("cannot send notification, error={:?}", e);
+        }
+        self.release_connection();
+    }
+
+    /// RFC 6608: an unexpected message is reported with a subcode naming the
+    /// state it arrived in and the message type as data.
+    fn unexpected_event_notification(state: State, event: &Event) -> NotificationMessage {
+        let subcode = match (state, event.message_type()) {
+            (_, None) => FiniteStateMachineErrorSubcode::Unspecified,
+            (State::OpenSent, Some(_)) => {
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenSentState
+            }
+            (State::OpenConfirm, Some(_)) => {
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenConfirmState
+            }
+            (State::Established, Some(_)) => {
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInEstablishedState
+            }
+            (_, Some(_)) => FiniteStateMachineErrorSubcode::Unspecified,
+        };
+        let data = event
+            .message_type()
+            .map(|message_type| BytesMut::from(&[u8::from(message_type)][..]))
+            .unwrap_or_default();
+
+        NotificationMessage::new(ErrorCode::FiniteStateMachineError(subcode), data)
+    }
+
+    fn manual_stop(&mut self) {
+        self.allow_automatic_start = false;
+        self.connect_retry_backoff.reset();
+    }
+
+    /// Drops the session and, unless the peer was stopped, schedules an
+    /// automatic restart once IdleHoldTimer (backed off on every consecutive
+    /// failure, as DampPeerOscillations requires) expires.
+    fn release_connection(&mut self) {
+        self.tcp_connection = None;
+        self.dial = None;
+        self.collision_connection = None;
+        self.negotiated_capabilities.clear();
+        self.ipv6_unicast_disabled = false;
+        self.adj_rib_in.clear();
+        self.loc_rib
+            .lock()
+            .unwrap()
+            .remove_source(&self.path_source());
+        self.adj_rib_out.clear();
+        self.hold_timer.stop();
+        self.keepalive_timer.stop();
+        self.connect_retry_timer.stop();
+        self.delay_open_timer.stop();
+        self.idle_hold_timer.stop();
+        if self.allow_automatic_start {
+            self.idle_hold_timer
+                .set_duration(self.connect_retry_backoff.next_interval());
+            self.idle_hold_timer.start();
+        }
+        self.state = State::Idle;
+    }
+}
+
+/// Waits for the connection attempt `dial` to finish; forever if there is
+/// none.
+async fn dial(dial: &mut Option<Dial>) -> anyhow::Result<Connection> {
+    match dial {
+        Some(Dial(dial)) => dial.await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Closes a connection that lost a collision with a Cease NOTIFICATION
+/// (RFC 4271 §6.8).
+async fn close_colliding(mut connection: Connection) {
+    let notification = Message::new_notification(
+        ErrorCode::Cease(CeaseSubcode::ConnectionCollisionResolution),
+        BytesMut::new(),
+    );
+    if let Err(e) = connection.send(notification).await {
+        warn!(
+            "cannot send notification on colliding connection, error={:?}",
+            e
+        );
+    }
+}
+
+/// Waits for a message on `connection`; forever if there is none.
+async fn receive(
+    connection: &mut Option<Connection>,
+) -> Result<Option<Message>, ConvertBytesToBgpMessageError> {
+    match connection {
+        Some(connection) if !connection.is_closed() => connection.receive().await,
+        _ => std::future::pending().await,
+    }
+}
+
+/// Waits until `deadline`; forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use crate::bgp_type::{Afi, HoldTime, Safi};
+    use crate::config::{Config, Mode};
+    use crate::connection::{Connection, Listener};
+    use crate::event::Event;
+    use crate::packets::capability::Capability;
+    use crate::packets::message::Message;
+    use crate::packets::notification::{
+        CeaseSubcode, ErrorCode, FiniteStateMachineErrorSubcode, MessageHeaderErrorSubcode,
+        NotificationMessage, OpenMessageErrorSubcode, UpdateMessageErrorSubcode,
+    };
+    use crate::packets::open::OpenMessage;
+    use crate::packets::route_refresh::RouteRefreshMessage;
+    use crate::packets::update::{UpdateErrorAction, UpdateMessage};
+    use crate::path_attribute::{
+        AsPath, AsPathSegment, Ipv6NextHop, MpReachNlri, MpUnreachNlri, Origin, PathAttribute,
+    };
+    use crate::peer::{Peer, PeerCommand};
+    use crate::routing::{
+        CandidatePath, DEFAULT_LOCAL_PREF, Ipv4Network, Ipv6Network, LocRib, PathSource,
+    };
+    use crate::state::State;
+
+    fn new_peer(config: Config) -> Peer {
+        let neighbor = config.neighbors.into_iter().next().unwrap();
+        Peer::new(
+            config.global,
+            neighbor,
+            Arc::new(Mutex::new(LocRib::default())),
+        )
+    }
+
+    /// Hands connections accepted on the peer's local address over to it.
+    fn listen_for(peer: &Peer) {
+        let (local_ip, remote_ip) = (peer.global.local_ip, peer.remote_ip());
+        let connection_sender = peer.connection_sender();
+        tokio::spawn(async move {
+            let listener = Listener::bind(local_ip).await.unwrap();
+            listener.register(remote_ip, connection_sender);
+            listener.run().await;
+        });
+    }
+
+    fn peer_with_connection(config: &str, state: State) -> (Peer, DuplexStream) {
+        let config: Config = config.parse().unwrap();
+        let mut peer = new_peer(config);
+        let (local, remote) = tokio::io::duplex(4096);
+        peer.tcp_connection = Some(Connection::from_stream(local, true));
+        peer.allow_automatic_start = true;
+        peer.state = state;
+        (peer, remote)
+    }
+
+    async fn receive_message(remote: &mut DuplexStream) -> Message {
+        let mut bytes = vec![0u8; 19];
+        remote.read_exact(&mut bytes).await.unwrap();
+        let length = u16::from_be_bytes([bytes[16], bytes[17]]) as usize;
+        bytes.resize(length, 0);
+        remote.read_exact(&mut bytes[19..]).await.unwrap();
+        Message::try_from(BytesMut::from(&bytes[..])).unwrap()
+    }
+
+    async fn receive_notification(remote: &mut DuplexStream) -> NotificationMessage {
+        match receive_message(remote).await {
+            Message::Notification(notification) => notification,
+            message => panic!("expected notification, but got {:?}", message),
+        }
+    }
+
+    async fn assert_connection_closed_silently(remote: &mut DuplexStream) {
+        let mut buf = [0u8; 1];
+        assert_eq!(remote.read(&mut buf).await.unwrap(), 0);
+    }
+
+    fn remote_open(hold_time: u16) -> OpenMessage {
+        OpenMessage::new(
+            64513.into(),
+            "127.0.0.2".parse().unwrap(),
+            HoldTime::from(hold_time),
+            vec!["ipv4-unicast".parse().unwrap()],
+        )
+    }
+
+    const CONFIG: &str = "64512 127.0.0.1 64513 127.0.0.2 active";
+
+    #[tokio::test]
+    async fn idle_ignores_events_other_than_start() {
+        let config: Config = CONFIG.parse().unwrap();
+        let mut peer = new_peer(config);
+
+        for event in [
+            Event::ManualStop,
+            Event::AutomaticStop,
+            Event::TcpConnectionFails,
+            Event::BgpOpen(remote_open(90)),
+            Event::HoldTimerExpires,
+        ] {
+            peer.handle_event(event).await;
+            assert_eq!(peer.state, State::Idle);
+        }
+        assert_eq!(peer.event_queue.dequeue(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_hold_timer_expiry_triggers_damped_automatic_start() {
+        let config: Config = CONFIG.parse().unwrap();
+        let mut peer = new_peer(config);
+        peer.allow_automatic_start = true;
+        peer.idle_hold_timer.start();
+
+        peer.handle_event(Event::AutomaticStartWithDampPeerOscillations)
+            .await;
+        assert_eq!(peer.state, State::Idle);
+
+        tokio::time::advance(peer.idle_hold_timer.duration()).await;
+        peer.next().await;
+        assert_eq!(peer.state, State::Idle);
+        assert_eq!(
+            peer.event_queue.dequeue(),
+            Some(Event::AutomaticStartWithDampPeerOscillations)
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_sends_open_when_tcp_connection_is_acked() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Connect);
+        peer.connect_retry_timer.start();
+
+        peer.handle_event(Event::TcpCrAcked).await;
+
+        assert_eq!(peer.state, State::OpenSent);
+        assert!(!peer.connect_retry_timer.is_running());
+        assert!(peer.hold_timer.is_running());
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Open(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn connect_handles_incoming_connection_while_dialing() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 both".parse().unwrap();
+        let mut peer = new_peer(config);
+
+        peer.handle_event(Event::ManualStart).await;
+        assert_eq!(peer.state, State::Connect);
+        assert!(peer.dial.is_some());
+
+        let (local, mut remote) = tokio::io::duplex(4096);
+        peer.accept_incoming_connection(Connection::from_stream(local, false))
+            .await;
+        peer.next().await;
+
+        assert_eq!(peer.state, State::OpenSent);
+        assert!(peer.dial.is_none());
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Open(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn active_sends_open_when_tcp_connection_is_confirmed() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Active);
+
+        peer.handle_event(Event::TcpConnectionConfirmed).await;
+
+        assert_eq!(peer.state, State::OpenSent);
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Open(_)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_with_delay_open_waits_for_delay_open_timer() {
+        let (mut peer, mut remote) =
+            peer_with_connection(&format!("{CONFIG} delay-open 5"), State::Connect);
+
+        peer.handle_event(Event::TcpCrAcked).await;
+        assert_eq!(peer.state, State::Connect);
+        assert!(peer.delay_open_timer.is_running());
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        peer.next().await;
+        assert_eq!(peer.state, State::OpenSent);
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Open(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn connect_answers_open_received_while_delay_open_timer_is_running() {
+        let (mut peer, mut remote) =
+            peer_with_connection(&format!("{CONFIG} delay-open 5"), State::Connect);
+        peer.delay_open_timer.start();
+
+        peer.handle_event(Event::BgpOpenWithDelayOpenTimerRunning(remote_open(90)))
+            .await;
+
+        assert_eq!(peer.state, State::OpenConfirm);
+        assert!(!peer.delay_open_timer.is_running());
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Open(_)
+        ));
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Keepalive(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn connect_returns_to_active_when_tcp_fails_while_delay_open_timer_is_running() {
+        let (mut peer, _remote) =
+            peer_with_connection(&format!("{CONFIG} delay-open 5"), State::Connect);
+        peer.delay_open_timer.start();
+
+        peer.handle_event(Event::TcpConnectionFails).await;
+
+        assert_eq!(peer.state, State::Active);
+        assert!(peer.connect_retry_timer.is_running());
+        assert!(!peer.delay_open_timer.is_running());
+    }
+
+    #[tokio::test]
+    async fn connect_and_active_release_the_session_on_tcp_connection_failure() {
+        for state in [State::Connect, State::Active] {
+            let (mut peer, mut remote) = peer_with_connection(CONFIG, state);
+
+            peer.handle_event(Event::TcpConnectionFails).await;
+
+            assert_eq!(peer.state, State::Idle);
+            assert!(peer.idle_hold_timer.is_running());
+            assert_connection_closed_silently(&mut remote).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_and_active_release_the_session_on_unexpected_events() {
+        for event in [
+            Event::KeepaliveMsg(Default::default()),
+            Event::BgpOpen(remote_open(90)),
+            Event::AutomaticStop,
+            Event::BgpHeaderErr(NotificationMessage::new(
+                ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageType),
+                BytesMut::new(),
+            )),
+        ] {
+            let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Connect);
+
+            peer.handle_event(event).await;
+
+            assert_eq!(peer.state, State::Idle);
+            assert_connection_closed_silently(&mut remote).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn manual_stop_in_connect_disables_automatic_start() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Connect);
+
+        peer.handle_event(Event::ManualStop).await;
+
+        assert_eq!(peer.state, State::Idle);
+        assert!(!peer.idle_hold_timer.is_running());
+        assert_connection_closed_silently(&mut remote).await;
+    }
+
+    #[tokio::test]
+    async fn start_events_are_ignored_outside_idle() {
+        for state in [
+            State::Connect,
+            State::Active,
+            State::OpenSent,
+            State::OpenConfirm,
+            State::Established,
+        ] {
+            let (mut peer, _remote) = peer_with_connection(CONFIG, state);
+
+            peer.handle_event(Event::ManualStart).await;
+            peer.handle_event(Event::AutomaticStartWithPassiveTcpEstablishment)
+                .await;
+
+            assert_eq!(peer.state, state);
+        }
+    }
+
+    #[tokio::test]
+    async fn open_sent_answers_open_with_keepalive_and_negotiates_hold_time() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::OpenSent);
+
+        peer.handle_event(Event::BgpOpen(remote_open(30))).await;
+
+        assert_eq!(peer.state, State::OpenConfirm);
+        assert_eq!(peer.hold_timer.duration(), Duration::from_secs(30));
+        assert_eq!(peer.keepalive_timer.duration(), Duration::from_secs(10));
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Keepalive(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn open_sent_rejects_unacceptable_hold_time() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::OpenSent);
+
+        peer.handle_event(Event::BgpOpen(remote_open(2))).await;
+
+        assert_eq!(peer.state, State::Idle);
+        assert_eq!(
+            receive_notification(&mut remote).await.error_code,
+            ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnacceptableHoldTime)
+        );
+    }
+
+    #[tokio::test]
+    async fn open_sent_rejects_unexpected_peer_as() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::OpenSent);
+        let open = OpenMessage::new(
+            64514.into(),
+            "127.0.0.2".parse().unwrap(),
+            HoldTime::new(),
+            vec![],
+        );
+
+        peer.handle_event(Event::BgpOpen(open)).await;
+
+        assert_eq!(peer.state, State::Idle);
+        assert_eq!(
+            receive_notification(&mut remote).await.error_code,
+            ErrorCode::OpenMessageError(OpenMessageErrorSubcode::BadPeerAs)
+        );
+    }
+
+    #[tokio::test]
+    async fn open_sent_rejects_bad_bgp_identifier() {
+        for bgp_identifier in ["0.0.0.0", "127.0.0.1"] {
+            let (mut peer, mut remote) = peer_with_connection(CONFIG, State::OpenSent);
+            let open = OpenMessage::new(
+                64513.into(),
+                bgp_identifier.parse().unwrap(),
+                HoldTime::new(),
+                vec![],
+            );
+
+            peer.handle_event(Event::BgpOpen(open)).await;
+
+            assert_eq!(peer.state, State::Idle);
+            assert_eq!(
+                receive_notification(&mut remote).await.error_code,
+                ErrorCode::OpenMessageError(OpenMessageErrorSubcode::BadBgpIdentifier)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn open_sent_stores_negotiated_capabilities() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::OpenSent);
+        let open = OpenMessage::new(
+            64513.into(),
+            "127.0.0.2".parse().unwrap(),
+            HoldTime::new(),
+            vec![Capability::RouteRefresh, "ipv4-unicast".parse().unwrap()],
+        );
+
+        peer.handle_event(Event::BgpOpen(open)).await;
+
+        assert_eq!(peer.state, State::OpenConfirm);
+        assert_eq!(
+            peer.negotiated_capabilities,
+            vec!["ipv4-unicast".parse().unwrap(), Capability::RouteRefresh]
+        );
+    }
+
+    #[tokio::test]
+    async fn open_sent_accepts_four_octet_peer_as_from_capability() {
+        let (mut peer, _remote) = peer_with_connection(
+            "64512 127.0.0.1 4200000000 127.0.0.2 active",
+            State::OpenSent,
+        );
+        let remote_as = 4_200_000_000.into();
+        let open = OpenMessage::new(
+            remote_as,
+            "127.0.0.2".parse().unwrap(),
+            HoldTime::new(),
+            vec![Capability::FourOctetAsNumber(remote_as)],
+        );
+
+        peer.handle_event(Event::BgpOpen(open)).await;
+
+        assert_eq!(peer.state, State::OpenConfirm);
+        assert_eq!(
+            peer.negotiated_capabilities,
+            vec![Capability::FourOctetAsNumber(64512.into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn open_sent_rejects_peer_without_required_capability() {
+        let (mut peer, mut remote) = peer_with_connection(
+            "64512 127.0.0.1 64513 127.0.0.2 active require-capability ipv4-unicast",
+            State::OpenSent,
+        );
+        let open = OpenMessage::new(
+            64513.into(),
+            "127.0.0.2".parse().unwrap(),
+            HoldTime::new(),
+            vec![],
+        );
+
+        peer.handle_event(Event::BgpOpen(open)).await;
+
+        assert_eq!(peer.state, State::Idle);
+        let notification = receive_notification(&mut remote).await;
+        assert_eq!(
+            notification.error_code,
+            ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnsupportedCapability)
+        );
+        assert_eq!(&notification.data[..], &[1, 4, 0, 1, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn open_sent_moves_to_active_when_tcp_connection_fails() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::OpenSent);
+        peer.hold_timer.start();
+
+        peer.handle_event(Event::TcpConnectionFails).await;
+
+        assert_eq!(peer.state, State::Active);
+        assert!(peer.tcp_connection.is_none());
+        assert!(!peer.hold_timer.is_running());
+        assert!(peer.connect_retry_timer.is_running());
+    }
+
+    #[tokio::test]
+    async fn open_sent_and_open_confirm_send_notification_for_message_errors() {
+        for state in [State::OpenSent, State::OpenConfirm] {
+            for notification in [
+                NotificationMessage::new(
+                    ErrorCode::MessageHeaderError(MessageHeaderErrorSubcode::BadMessageLength),
+                    BytesMut::new(),
+                ),
+                NotificationMessage::new(
+                    ErrorCode::OpenMessageError(OpenMessageErrorSubcode::BadPeerAs),
+                    BytesMut::new(),
+                ),
+            ] {
+                let (mut peer, mut remote) = peer_with_connection(CONFIG, state);
+                let event = match notification.error_code {
+                    ErrorCode::OpenMessageError(_) => Event::BgpOpenMsgErr(notification.clone()),
+                    _ => Event::BgpHeaderErr(notification.clone()),
+                };
+
+                peer.handle_event(event).await;
+
+                assert_eq!(peer.state, State::Idle);
+                assert_eq!(receive_notification(&mut remote).await, notification);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn manual_and_automatic_stop_send_cease() {
+        for state in [State::OpenSent, State::OpenConfirm, State::Established] {
+            for (event, subcode) in [
+                (Event::ManualStop, CeaseSubcode::AdministrativeShutdown),
+                (Event::AutomaticStop, CeaseSubcode::Unspecific),
+                (
+                    Event::OpenCollisionDump,
+                    CeaseSubcode::ConnectionCollisionResolution,
+                ),
+            ] {
+                let (mut peer, mut remote) = peer_with_connection(CONFIG, state);
+
+                peer.handle_event(event).await;
+
+                assert_eq!(peer.state, State::Idle);
+                assert_eq!(
+                    receive_notification(&mut remote).await.error_code,
+                    ErrorCode::Cease(subcode)
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn reconfiguration_resets_session_only_when_session_parameters_change() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
+
+        let config: Config =
+            "64512 127.0.0.1 64513 127.0.0.2 both require-capability route-refresh"
+                .parse()
+                .unwrap();
+        peer.handle_command(PeerCommand::Reconfigure(
+            config.global,
+            config.neighbors[0].clone(),
+        ))
+        .await;
+        assert_eq!(peer.state, State::Established);
+        assert_eq!(peer.neighbor.mode, Mode::Both);
+
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 both hold-time 30"
+            .parse()
+            .unwrap();
+        peer.handle_command(PeerCommand::Reconfigure(
+            config.global,
+            config.neighbors[0].clone(),
+        ))
+        .await;
+        assert_eq!(peer.state, State::Idle);
+        assert_eq!(peer.neighbor.hold_time, HoldTime::from(30));
+        assert_eq!(
+            receive_notification(&mut remote).await.error_code,
+            ErrorCode::Cease(CeaseSubcode::OtherConfigurationChange)
+        );
+        assert!(peer.idle_hold_timer.is_running());
+    }
+
+    #[tokio::test]
+    async fn reconfiguration_applies_new_import_policy_to_stored_routes() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::Established);
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        peer.handle_event(Event::UpdateMsg(UpdateMessage::new(
+            Arc::new(vec![PathAttribute::NextHop("127.0.0.2".parse().unwrap())]),
+            vec![network],
+            vec![],
+        )))
+        .await;
+
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active import-set-local-pref 200"
+            .parse()
+            .unwrap();
+        peer.handle_command(PeerCommand::Reconfigure(
+            config.global,
+            config.neighbors[0].clone(),
+        ))
+        .await;
+
+        assert_eq!(peer.state, State::Established);
+        let path = peer
+            .loc_rib
+            .lock()
+            .unwrap()
+            .best_path(&network.into())
+            .cloned()
+            .unwrap();
+        assert!(
+            path.path_attributes
+                .contains(&PathAttribute::LocalPref(200))
+        );
+        assert!(
+            !peer
+                .adj_rib_out
+                .is_synced_with(&peer.loc_rib.lock().unwrap())
+        );
+    }
 
-use crate::{
-    config::Config, connection::Connection, event::Event, event_queue::EventQueue,
-    packets::message::Message, state::State,
-};
+    #[tokio::test]
+    async fn deconfiguration_closes_session_and_stops_peer() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
 
-#[derive(Debug)]
-pub struct Peer {
-    state: State,
-    event_queue: EventQueue,
-    tcp_connection: Option<Connection>,
-    config: Config,
-}
+        peer.handle_command(PeerCommand::Deconfigure).await;
 
-impl Peer {
-    pub fn new(config: Config) -> Self {
-        let state = State::Idle;
-        let event_queue = EventQueue::new();
+        assert_eq!(peer.state, State::Idle);
+        assert!(peer.is_deconfigured());
+        assert!(!peer.idle_hold_timer.is_running());
+        assert_eq!(
+            receive_notification(&mut remote).await.error_code,
+            ErrorCode::Cease(CeaseSubcode::PeerDeconfigured)
+        );
+    }
 
-        Self {
-            state,
-            event_queue,
-            tcp_connection: None,
-            config,
-        }
+    #[tokio::test]
+    async fn shutdown_sends_administrative_shutdown_and_stops_peer() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
+
+        peer.handle_command(PeerCommand::Shutdown).await;
+
+        assert_eq!(peer.state, State::Idle);
+        assert!(peer.is_deconfigured());
+        assert_eq!(
+            receive_notification(&mut remote).await.error_code,
+            ErrorCode::Cease(CeaseSubcode::AdministrativeShutdown)
+        );
     }
 
-    pub fn start(&mut self) {
-        info!("peer started");
-        self.event_queue.enqueue(Event::ManualStart);
+    #[tokio::test]
+    async fn hold_timer_expiry_sends_hold_timer_expired() {
+        for state in [State::OpenSent, State::OpenConfirm, State::Established] {
+            let (mut peer, mut remote) = peer_with_connection(CONFIG, state);
+
+            peer.handle_event(Event::HoldTimerExpires).await;
+
+            assert_eq!(peer.state, State::Idle);
+            assert_eq!(
+                receive_notification(&mut remote).await.error_code,
+                ErrorCode::HoldTimerExpired
+            );
+        }
     }
 
-    pub async fn next(&mut self) {
-        if let Some(event) = self.event_queue.dequeue() {
-            info!("event occurred, event={:?}", event);
-            self.handle_event(event).await;
+    #[tokio::test]
+    async fn unexpected_events_send_finite_state_machine_error() {
+        let update = UpdateMessage::new(Default::default(), vec![], vec![]);
+        let cases = [
+            (
+                State::OpenSent,
+                Event::KeepaliveMsg(Default::default()),
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenSentState,
+                vec![4],
+            ),
+            (
+                State::OpenSent,
+                Event::UpdateMsg(update.clone()),
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenSentState,
+                vec![2],
+            ),
+            (
+                State::OpenSent,
+                Event::KeepaliveTimerExpires,
+                FiniteStateMachineErrorSubcode::Unspecified,
+                vec![],
+            ),
+            (
+                State::OpenConfirm,
+                Event::BgpOpen(remote_open(90)),
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenConfirmState,
+                vec![1],
+            ),
+            (
+                State::OpenConfirm,
+                Event::UpdateMsg(update),
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInOpenConfirmState,
+                vec![2],
+            ),
+            (
+                State::Established,
+                Event::BgpOpen(remote_open(90)),
+                FiniteStateMachineErrorSubcode::ReceiveUnexpectedMessageInEstablishedState,
+                vec![1],
+            ),
+            (
+                State::Established,
+                Event::ConnectRetryTimerExpires,
+                FiniteStateMachineErrorSubcode::Unspecified,
+                vec![],
+            ),
+        ];
+
+        for (state, event, subcode, data) in cases {
+            let (mut peer, mut remote) = peer_with_connection(CONFIG, state);
+
+            peer.handle_event(event).await;
+
+            assert_eq!(peer.state, State::Idle);
+            let notification = receive_notification(&mut remote).await;
+            assert_eq!(
+                notification.error_code,
+                ErrorCode::FiniteStateMachineError(subcode)
+            );
+            assert_eq!(&notification.data[..], &data[..]);
         }
+    }
 
-        if let Some(connection) = &mut self.tcp_connection {
-            if let Some(message) = connection.get_message().await {
-                info!("message received, message={:?}", message);
-                self.handle_message(message).await;
+    #[tokio::test]
+    async fn received_notification_releases_the_session_without_reply() {
+        for state in [State::OpenSent, State::OpenConfirm, State::Established] {
+            for event in [
+                Event::NotifMsg(NotificationMessage::new(
+                    ErrorCode::Cease(CeaseSubcode::AdministrativeReset),
+                    BytesMut::new(),
+                )),
+                Event::NotifMsgVerErr(NotificationMessage::new(
+                    ErrorCode::OpenMessageError(OpenMessageErrorSubcode::UnsupportedVersionNumber),
+                    BytesMut::new(),
+                )),
+                Event::TcpConnectionFails,
+            ] {
+                if state == State::OpenSent && event == Event::TcpConnectionFails {
+                    continue;
+                }
+                let (mut peer, mut remote) = peer_with_connection(CONFIG, state);
+
+                peer.handle_event(event).await;
+
+                assert_eq!(peer.state, State::Idle);
+                assert_connection_closed_silently(&mut remote).await;
             }
         }
     }
 
-    async fn handle_message(&mut self, message: Message) {
-        match message {
-            Message::Open(open) => {
-                self.event_queue.enqueue(Event::BgpOpen(open));
-            }
-            Message::Keepalive(keepalive) => {
-                self.event_queue.enqueue(Event::KeepaliveMsg(keepalive));
-            }
-            Message::Update(update) => {
-                self.event_queue.enqueue(Event::UpdateMsg(update));
-            }
+    #[tokio::test]
+    async fn open_confirm_transitions_to_established_on_keepalive() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::OpenConfirm);
+
+        peer.handle_event(Event::KeepaliveMsg(Default::default()))
+            .await;
+
+        assert_eq!(peer.state, State::Established);
+        assert!(peer.hold_timer.is_running());
+    }
+
+    #[tokio::test]
+    async fn keepalive_timer_expiry_sends_keepalive() {
+        for state in [State::OpenConfirm, State::Established] {
+            let (mut peer, mut remote) = peer_with_connection(CONFIG, state);
+            peer.keepalive_timer.set_duration(Duration::from_secs(30));
+
+            peer.handle_event(Event::KeepaliveTimerExpires).await;
+
+            assert_eq!(peer.state, state);
+            assert!(peer.keepalive_timer.is_running());
+            assert!(matches!(
+                receive_message(&mut remote).await,
+                Message::Keepalive(_)
+            ));
         }
     }
 
-    async fn handle_event(&mut self, event: Event) {
-        let current_state = self.state.clone();
-
-        match &self.state {
-            State::Idle => match event {
-                Event::ManualStart => {
-                    self.tcp_connection = Connection::connect(&self.config).await.ok();
-                    if self.tcp_connection.is_some() {
-                        self.event_queue.enqueue(Event::TcpConnectionConfirmed);
-                    } else {
-                        panic!("cannot establish TCP connection: {:?}", self.config);
-                    }
-                    self.state = State::Connect;
-                }
-                _ => {}
-            },
-            State::Connect => match event {
-                Event::TcpConnectionConfirmed => {
-                    self.tcp_connection
-                        .as_mut()
-                        .expect("tcp-connection is None")
-                        .send(Message::new_open(
-                            self.config.local_as,
-                            self.config.local_ip,
-                        ))
-                        .await;
-                    self.state = State::OpenSent;
-                }
-                _ => {}
-            },
-            State::OpenSent => match event {
-                Event::BgpOpen(open) => {
-                    self.tcp_connection
-                        .as_mut()
-                        .expect("tcp-connection is None")
-                        .send(Message::new_keepalive())
-                        .await;
-                    self.state = State::OpenConfirm;
-                }
-                _ => {}
-            },
-            State::OpenConfirm => match event {
-                Event::KeepaliveMsg(keepalive) => {
-                    self.state = State::Established;
-                }
-                _ => {}
+    #[tokio::test]
+    async fn established_restarts_hold_timer_on_keepalive_and_update() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::Established);
+
+        peer.handle_event(Event::KeepaliveMsg(Default::default()))
+            .await;
+        assert!(peer.hold_timer.is_running());
+
+        peer.hold_timer.stop();
+        peer.handle_event(Event::UpdateMsg(UpdateMessage::new(
+            Default::default(),
+            vec![],
+            vec![],
+        )))
+        .await;
+        assert!(peer.hold_timer.is_running());
+        assert_eq!(peer.state, State::Established);
+    }
+
+    #[tokio::test]
+    async fn established_stores_received_routes_until_session_drops() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::Established);
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+
+        peer.handle_event(Event::UpdateMsg(UpdateMessage::new(
+            Arc::new(vec![PathAttribute::NextHop("127.0.0.2".parse().unwrap())]),
+            vec![network],
+            vec![],
+        )))
+        .await;
+        assert!(peer.adj_rib_in.get(&network.into()).is_some());
+        assert!(
+            peer.loc_rib
+                .lock()
+                .unwrap()
+                .best_path(&network.into())
+                .is_some()
+        );
+
+        peer.handle_event(Event::HoldTimerExpires).await;
+        assert_eq!(peer.state, State::Idle);
+        assert!(peer.adj_rib_in.is_empty());
+        assert!(
+            peer.loc_rib
+                .lock()
+                .unwrap()
+                .best_path(&network.into())
+                .is_none()
+        );
+    }
+
+    /// Decodes `update` after `corrupt` altered its encoding.
+    fn corrupted_update(update: UpdateMessage, corrupt: impl FnOnce(&mut BytesMut)) -> Event {
+        let mut bytes: BytesMut = update.into();
+        corrupt(&mut bytes);
+        Event::UpdateMsg(UpdateMessage::try_from(bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn malformed_update_withdraws_its_routes_without_resetting_the_session() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let update = UpdateMessage::new(
+            Arc::new(vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+                PathAttribute::NextHop("127.0.0.2".parse().unwrap()),
+            ]),
+            vec![network],
+            vec![],
+        );
+        peer.handle_event(Event::UpdateMsg(update.clone())).await;
+        assert!(peer.adj_rib_in.get(&network.into()).is_some());
+
+        // ORIGIN is the first attribute; its value follows flags, type and length.
+        peer.handle_event(corrupted_update(update, |bytes| bytes[26] = 7))
+            .await;
+
+        assert_eq!(peer.state, State::Established);
+        assert!(peer.adj_rib_in.get(&network.into()).is_none());
+        assert!(
+            peer.loc_rib
+                .lock()
+                .unwrap()
+                .best_path(&network.into())
+                .is_none()
+        );
+        assert_eq!(
+            peer.update_errors()
+                .get(&UpdateErrorAction::TreatAsWithdraw),
+            Some(&1)
+        );
+        drop(peer);
+        assert_connection_closed_silently(&mut remote).await;
+    }
+
+    #[tokio::test]
+    async fn local_pref_from_ebgp_peer_is_discarded_even_if_malformed() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::Established);
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let path_attributes = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+            PathAttribute::NextHop("127.0.0.2".parse().unwrap()),
+        ];
+        let mut with_local_pref = path_attributes.clone();
+        with_local_pref.push(PathAttribute::LocalPref(200));
+        let update = UpdateMessage::new(Arc::new(with_local_pref), vec![network], vec![]);
+
+        peer.handle_event(Event::UpdateMsg(update.clone())).await;
+        assert_eq!(
+            peer.adj_rib_in.get(&network.into()),
+            Some(&Arc::new(path_attributes.clone()))
+        );
+
+        // LOCAL_PREF sent as optional transitive.
+        peer.handle_event(corrupted_update(update, |bytes| {
+            let i = bytes.windows(3).position(|w| w == [0x40, 5, 4]).unwrap();
+            bytes[i] = 0xc0;
+        }))
+        .await;
+        assert_eq!(peer.state, State::Established);
+        assert_eq!(
+            peer.adj_rib_in.get(&network.into()),
+            Some(&Arc::new(path_attributes))
+        );
+        assert_eq!(
+            peer.update_errors()
+                .get(&UpdateErrorAction::AttributeDiscard),
+            Some(&1)
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_mp_unreach_nlri_disables_ipv6_unicast_until_session_drops() {
+        let (mut peer, _remote) = peer_with_connection(CONFIG, State::Established);
+        let network: Ipv6Network = "2001:db8:100::/48".parse().unwrap();
+        let announce = UpdateMessage::new(
+            Arc::new(vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+                PathAttribute::MpReachNlri(MpReachNlri {
+                    next_hop: Ipv6NextHop {
+                        global: "2001:db8::1".parse().unwrap(),
+                        link_local: None,
+                    },
+                    nlri: vec![network],
+                }),
+            ]),
+            vec![],
+            vec![],
+        );
+        let withdraw = UpdateMessage::new(
+            Arc::new(vec![PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                withdrawn_routes: vec![network],
+            })]),
+            vec![],
+            vec![],
+        );
+        peer.handle_event(Event::UpdateMsg(announce.clone())).await;
+        assert!(peer.adj_rib_in.get(&network.into()).is_some());
+
+        // The prefix length is 7 octets from the end.
+        peer.handle_event(corrupted_update(withdraw, |bytes| {
+            let index = bytes.len() - 7;
+            bytes[index] = 200;
+        }))
+        .await;
+        assert_eq!(peer.state, State::Established);
+        assert!(peer.adj_rib_in.is_empty());
+        assert!(
+            peer.loc_rib
+                .lock()
+                .unwrap()
+                .best_path(&network.into())
+                .is_none()
+        );
+        assert_eq!(
+            peer.update_errors()
+                .get(&UpdateErrorAction::AfiSafiDisable(Afi::Ipv6, Safi::Unicast)),
+            Some(&1)
+        );
+
+        peer.handle_event(Event::UpdateMsg(announce)).await;
+        assert!(peer.adj_rib_in.is_empty());
+
+        peer.handle_event(Event::HoldTimerExpires).await;
+        assert!(!peer.ipv6_unicast_disabled);
+    }
+
+    fn learned_path(peer_address: &str, peer_as: u32, ibgp: bool) -> CandidatePath {
+        let peer_address: Ipv4Addr = peer_address.parse().unwrap();
+        CandidatePath {
+            source: PathSource {
+                peer_address,
+                router_id: peer_address,
+                peer_as: peer_as.into(),
+                ibgp,
+                local: false,
             },
-            _ => {}
+            path_attributes: Arc::new(vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![peer_as.into()])),
+                PathAttribute::NextHop(peer_address),
+                PathAttribute::MultiExitDisc(10),
+            ]),
+            igp_metric: Some(0),
+            immediate_next_hop: None,
         }
+    }
 
-        if self.state != current_state {
-            info!(
-                "state transitioned, from={:?}, to={:?}",
-                current_state, self.state
-            );
+    async fn receive_update(remote: &mut DuplexStream) -> UpdateMessage {
+        match receive_message(remote).await {
+            Message::Update(update) => update,
+            message => panic!("expected update, but got {:?}", message),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+    #[tokio::test]
+    async fn established_advertises_and_withdraws_loc_rib_routes_to_ebgp_peer() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
+        peer.tcp_connection
+            .as_mut()
+            .unwrap()
+            .set_four_octet_as(true);
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let path = learned_path("10.0.0.9", 64600, false);
 
-    use crate::config::Config;
-    use crate::peer::Peer;
-    use crate::state::State;
+        peer.loc_rib
+            .lock()
+            .unwrap()
+            .insert(network.into(), path.clone());
+        peer.next().await;
+
+        let update = receive_update(&mut remote).await;
+        assert_eq!(update.network_layer_reachability_information, vec![network]);
+        assert_eq!(
+            *update.path_attributes,
+            vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64512.into(), 64600.into()])),
+                PathAttribute::NextHop("127.0.0.1".parse().unwrap()),
+            ]
+        );
+
+        peer.loc_rib
+            .lock()
+            .unwrap()
+            .remove(&network.into(), &path.source);
+        peer.next().await;
+
+        let update = receive_update(&mut remote).await;
+        assert_eq!(update.withdrawn_routes, vec![network]);
+        assert!(update.network_layer_reachability_information.is_empty());
+    }
+
+    #[tokio::test]
+    async fn established_advertises_only_ebgp_learned_routes_to_ibgp_peer() {
+        let (mut peer, mut remote) =
+            peer_with_connection("64512 127.0.0.1 64512 127.0.0.2 active", State::Established);
+        peer.tcp_connection
+            .as_mut()
+            .unwrap()
+            .set_four_octet_as(true);
+        let ibgp_network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let ebgp_network: Ipv4Network = "10.100.230.0/24".parse().unwrap();
+
+        peer.loc_rib
+            .lock()
+            .unwrap()
+            .insert(ibgp_network.into(), learned_path("10.0.0.8", 64512, true));
+        peer.loc_rib
+            .lock()
+            .unwrap()
+            .insert(ebgp_network.into(), learned_path("10.0.0.9", 64600, false));
+        peer.next().await;
+
+        let update = receive_update(&mut remote).await;
+        assert_eq!(
+            update.network_layer_reachability_information,
+            vec![ebgp_network]
+        );
+        assert_eq!(
+            *update.path_attributes,
+            vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64600.into()])),
+                PathAttribute::NextHop("10.0.0.9".parse().unwrap()),
+                PathAttribute::MultiExitDisc(10),
+                PathAttribute::LocalPref(DEFAULT_LOCAL_PREF),
+            ]
+        );
+        assert!(peer.adj_rib_out.get(&ibgp_network.into()).is_none());
+    }
+
+    #[test]
+    fn export_keeps_as_sets_and_drops_non_transitive_unrecognized_attributes() {
+        let config: Config = CONFIG.parse().unwrap();
+        let mut path = learned_path("10.0.0.9", 64600, false);
+        path.path_attributes = Arc::new(vec![
+            PathAttribute::Origin(Origin::Incomplete),
+            PathAttribute::AsPath(AsPath::from_segments(vec![AsPathSegment::AsSet(
+                [64600.into(), 64601.into()].into(),
+            )])),
+            PathAttribute::NextHop("10.0.0.9".parse().unwrap()),
+            PathAttribute::DontKnow(vec![0xc0, 32, 1, 0]),
+            PathAttribute::DontKnow(vec![0x80, 99, 1, 0]),
+            PathAttribute::DontKnow(vec![0x90, 14, 0, 3, 0, 1, 2]),
+        ]);
+
+        assert_eq!(
+            Peer::export(
+                &config.global,
+                &config.neighbors[0],
+                &"10.100.220.0/24".parse::<Ipv4Network>().unwrap().into(),
+                &path
+            ),
+            Some(vec![
+                PathAttribute::Origin(Origin::Incomplete),
+                PathAttribute::AsPath(AsPath::from_segments(vec![
+                    AsPathSegment::AsSequence(vec![64512.into()]),
+                    AsPathSegment::AsSet([64600.into(), 64601.into()].into()),
+                ])),
+                PathAttribute::NextHop("127.0.0.1".parse().unwrap()),
+                PathAttribute::DontKnow(vec![0xe0, 32, 1, 0]),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn established_advertises_routes_again_on_route_refresh() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
+        peer.tcp_connection
+            .as_mut()
+            .unwrap()
+            .set_four_octet_as(true);
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        peer.loc_rib
+            .lock()
+            .unwrap()
+            .insert(network.into(), learned_path("10.0.0.9", 64600, false));
+        peer.next().await;
+        let advertised = receive_update(&mut remote).await;
+
+        let refresh = |afi| Message::RouteRefresh(RouteRefreshMessage::new(afi, Safi::Unicast));
+        for message in [refresh(Afi::Ipv6), refresh(Afi::Ipv4)] {
+            remote.write_all(&BytesMut::from(message)).await.unwrap();
+            peer.next().await;
+        }
+        peer.next().await;
+
+        assert_eq!(peer.state, State::Established);
+        assert_eq!(receive_update(&mut remote).await, advertised);
+    }
+
+    #[tokio::test]
+    async fn established_advertises_ipv6_routes_with_local_ipv6_next_hop() {
+        let (mut peer, mut remote) = peer_with_connection(
+            &format!("{CONFIG} local-ipv6 2001:db8::2"),
+            State::Established,
+        );
+        peer.tcp_connection
+            .as_mut()
+            .unwrap()
+            .set_four_octet_as(true);
+        peer.negotiated_capabilities = vec!["ipv6-unicast".parse().unwrap()];
+        let network: Ipv6Network = "2001:db8:100::/48".parse().unwrap();
+        let mut path = learned_path("10.0.0.9", 64600, false);
+        path.path_attributes = Arc::new(vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(AsPath::sequence(vec![64600.into()])),
+            PathAttribute::MpReachNlri(MpReachNlri {
+                next_hop: Ipv6NextHop {
+                    global: "2001:db8::9".parse().unwrap(),
+                    link_local: None,
+                },
+                nlri: vec![network],
+            }),
+        ]);
+
+        peer.loc_rib
+            .lock()
+            .unwrap()
+            .insert(network.into(), path.clone());
+        peer.next().await;
+
+        let update = receive_update(&mut remote).await;
+        assert!(update.network_layer_reachability_information.is_empty());
+        assert_eq!(
+            *update.path_attributes,
+            vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::sequence(vec![64512.into(), 64600.into()])),
+                PathAttribute::MpReachNlri(MpReachNlri {
+                    next_hop: Ipv6NextHop {
+                        global: "2001:db8::2".parse().unwrap(),
+                        link_local: None,
+                    },
+                    nlri: vec![network],
+                }),
+            ]
+        );
+
+        peer.loc_rib
+            .lock()
+            .unwrap()
+            .remove(&network.into(), &path.source);
+        peer.next().await;
+
+        let update = receive_update(&mut remote).await;
+        assert_eq!(
+            *update.path_attributes,
+            vec![PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                withdrawn_routes: vec![network],
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_to_write_to_connection_releases_session() {
+        let (mut peer, remote) = peer_with_connection(CONFIG, State::Established);
+        drop(remote);
+
+        peer.send(Message::new_keepalive()).await;
+        assert!(peer.tcp_connection.is_none());
+
+        peer.next().await;
+        assert_eq!(peer.state, State::Idle);
+    }
+
+    #[test]
+    fn ipv6_routes_are_not_exported_to_ebgp_peers_without_local_ipv6() {
+        let config: Config = CONFIG.parse().unwrap();
+        let network: Ipv6Network = "2001:db8:100::/48".parse().unwrap();
+
+        assert_eq!(
+            Peer::export(
+                &config.global,
+                &config.neighbors[0],
+                &network.into(),
+                &learned_path("10.0.0.9", 64600, false)
+            ),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn established_sends_notification_for_update_message_error() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
+        let notification = NotificationMessage::new(
+            ErrorCode::UpdateMessageError(UpdateMessageErrorSubcode::MalformedAsPath),
+            BytesMut::new(),
+        );
+
+        peer.handle_event(Event::UpdateMsgErr(notification.clone()))
+            .await;
+
+        assert_eq!(peer.state, State::Idle);
+        assert_eq!(receive_notification(&mut remote).await, notification);
+    }
+
+    #[tokio::test]
+    async fn received_messages_are_turned_into_events() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::OpenSent);
+        let open: BytesMut = Message::Open(remote_open(90)).into();
+        remote.write_all(&open).await.unwrap();
+
+        peer.next().await;
+
+        assert_eq!(
+            peer.event_queue.dequeue(),
+            Some(Event::BgpOpen(remote_open(90)))
+        );
+    }
+
+    #[tokio::test]
+    async fn received_notifications_are_never_answered() {
+        // Cease Hard Reset (RFC 8538), then a NOTIFICATION too short to
+        // hold an error code.
+        let hard_reset: BytesMut = Message::Notification(NotificationMessage::new(
+            ErrorCode::Cease(CeaseSubcode::Unknown(9)),
+            BytesMut::new(),
+        ))
+        .into();
+        let mut truncated = BytesMut::from(&hard_reset[..20]);
+        truncated[16..18].copy_from_slice(&20u16.to_be_bytes());
+
+        for bytes in [hard_reset, truncated] {
+            let (mut peer, mut remote) = peer_with_connection(CONFIG, State::Established);
+            remote.write_all(&bytes).await.unwrap();
+
+            while peer.state == State::Established {
+                peer.next().await;
+            }
+
+            assert_eq!(peer.state, State::Idle);
+            assert_connection_closed_silently(&mut remote).await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn running_peer_wakes_up_for_messages_and_timers() {
+        let (mut peer, mut remote) = peer_with_connection(CONFIG, State::OpenSent);
+        tokio::spawn(async move { peer.run().await });
+        let open: BytesMut = Message::Open(remote_open(90)).into();
+        remote.write_all(&open).await.unwrap();
+
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Keepalive(_)
+        ));
+        let answered = tokio::time::Instant::now();
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Keepalive(_)
+        ));
+        assert_eq!(answered.elapsed(), Duration::from_secs(30));
+    }
+
+    fn peer_with_colliding_connections(config: &str) -> (Peer, DuplexStream, DuplexStream) {
+        let (mut peer, remote) = peer_with_connection(config, State::OpenSent);
+        let (local, colliding_remote) = tokio::io::duplex(4096);
+        peer.collision_connection = Some(Connection::from_stream(local, false));
+        (peer, remote, colliding_remote)
+    }
+
+    #[tokio::test]
+    async fn collision_keeps_remotely_initiated_connection_when_remote_identifier_is_higher() {
+        let (mut peer, mut remote, mut colliding_remote) = peer_with_colliding_connections(CONFIG);
+        let open: BytesMut = Message::Open(remote_open(90)).into();
+        colliding_remote.write_all(&open).await.unwrap();
+
+        peer.next().await;
+        peer.next().await;
+
+        assert_eq!(
+            receive_notification(&mut remote).await.error_code,
+            ErrorCode::Cease(CeaseSubcode::ConnectionCollisionResolution)
+        );
+        assert_eq!(peer.state, State::OpenConfirm);
+        assert!(!peer.tcp_connection.as_ref().unwrap().is_initiated_locally());
+        assert!(peer.collision_connection.is_none());
+        assert!(matches!(
+            receive_message(&mut colliding_remote).await,
+            Message::Keepalive(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn collision_keeps_locally_initiated_connection_when_local_identifier_is_higher() {
+        let (mut peer, mut remote, mut colliding_remote) =
+            peer_with_colliding_connections("64512 127.0.0.3 64513 127.0.0.2 both");
+
+        peer.handle_event(Event::BgpOpen(remote_open(90))).await;
+
+        assert_eq!(
+            receive_notification(&mut colliding_remote).await.error_code,
+            ErrorCode::Cease(CeaseSubcode::ConnectionCollisionResolution)
+        );
+        assert_eq!(peer.state, State::OpenConfirm);
+        assert!(peer.tcp_connection.as_ref().unwrap().is_initiated_locally());
+        assert!(peer.collision_connection.is_none());
+        assert!(matches!(
+            receive_message(&mut remote).await,
+            Message::Keepalive(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn collision_connection_is_closed_once_established() {
+        let (mut peer, _remote, mut colliding_remote) = peer_with_colliding_connections(CONFIG);
+        peer.state = State::OpenConfirm;
+
+        peer.handle_event(Event::KeepaliveMsg(Default::default()))
+            .await;
+
+        assert_eq!(peer.state, State::Established);
+        assert_eq!(
+            receive_notification(&mut colliding_remote).await.error_code,
+            ErrorCode::Cease(CeaseSubcode::ConnectionCollisionResolution)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn peer_transitions_to_idle_when_hold_timer_expires() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active hold-time 3"
+            .parse()
+            .unwrap();
+        let mut peer = new_peer(config);
+        peer.state = State::Established;
+        peer.hold_timer.set_duration(Duration::from_secs(3));
+        peer.hold_timer.start();
+
+        peer.next().await;
+        assert_eq!(peer.state, State::Established);
+
+        tokio::time::advance(Duration::from_secs(3)).await;
+        peer.next().await;
+        assert_eq!(peer.state, State::Idle);
+        assert!(!peer.keepalive_timer.is_running());
+    }
+
+    #[tokio::test]
+    async fn peer_retries_when_tcp_connection_fails() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.3 active connect-retry 1"
+            .parse()
+            .unwrap();
+        let mut peer = new_peer(config);
+        peer.start();
+
+        peer.next().await;
+        assert_eq!(peer.state, State::Connect);
+        peer.wait().await;
+        peer.next().await;
+        assert_eq!(peer.state, State::Idle);
+        assert!(peer.idle_hold_timer.is_running());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        peer.next().await;
+        peer.next().await;
+        assert_eq!(peer.state, State::Connect);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn peer_restarts_automatically_after_session_drops() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active connect-retry 5"
+            .parse()
+            .unwrap();
+        let mut peer = new_peer(config);
+        peer.allow_automatic_start = true;
+        peer.state = State::Established;
+        peer.hold_timer.set_duration(Duration::from_secs(3));
+        peer.hold_timer.start();
+
+        tokio::time::advance(Duration::from_secs(3)).await;
+        peer.next().await;
+        assert_eq!(peer.state, State::Idle);
+        assert!(peer.idle_hold_timer.is_running());
+        assert!(peer.idle_hold_timer.duration() <= Duration::from_secs(5));
+    }
 
     #[tokio::test]
     async fn peer_can_transition_to_open_established_state() {
-        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active".parse().unwrap();
-        let mut peer = Peer::new(config);
+        let config: Config = "64512 127.0.1.1 64513 127.0.1.2 active".parse().unwrap();
+        let mut peer = new_peer(config);
         peer.start();
 
         tokio::spawn(async move {
-            let remote_config: Config = "64513 127.0.0.2 64512 127.0.0.1 passive".parse().unwrap();
-            let mut remote_peer = Peer::new(remote_config);
+            let remote_config: Config = "64513 127.0.1.2 64512 127.0.1.1 passive".parse().unwrap();
+            let mut remote_peer = new_peer(remote_config);
+            listen_for(&remote_peer);
             remote_peer.start();
 
             let max_step = 50;
@@ -162,13 +2629,14 @@ mod tests {
 
     #[tokio::test]
     async fn peer_can_transition_to_open_confirm_state() {
-        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active".parse().unwrap();
-        let mut peer = Peer::new(config);
+        let config: Config = "64512 127.0.2.1 64513 127.0.2.2 active".parse().unwrap();
+        let mut peer = new_peer(config);
         peer.start();
 
         tokio::spawn(async move {
-            let remote_config: Config = "64513 127.0.0.2 64512 127.0.0.1 passive".parse().unwrap();
-            let mut remote_peer = Peer::new(remote_config);
+            let remote_config: Config = "64513 127.0.2.2 64512 127.0.2.1 passive".parse().unwrap();
+            let mut remote_peer = new_peer(remote_config);
+            listen_for(&remote_peer);
             remote_peer.start();
 
             let max_step = 50;
@@ -196,15 +2664,19 @@ mod tests {
 
     #[tokio::test]
     async fn peer_can_transition_to_connect_state() {
-        let config: Config = "64512 127.0.0.1 65413 127.0.0.2 active".parse().unwrap();
-        let mut peer = Peer::new(config);
+        let config: Config = "64512 127.0.3.1 65413 127.0.3.2 active".parse().unwrap();
+        let mut peer = new_peer(config);
         peer.start();
 
         tokio::spawn(async move {
-            let remote_config = "64513 127.0.0.2 64512 127.0.0.1 passive".parse().unwrap();
-            let mut remote_peer = Peer::new(remote_config);
+            let remote_config = "64513 127.0.3.2 64512 127.0.3.1 passive".parse().unwrap();
+            let mut remote_peer = new_peer(remote_config);
+            listen_for(&remote_peer);
             remote_peer.start();
-            remote_peer.next().await;
+            for _ in 0..20 {
+                remote_peer.next().await;
+                tokio::time::sleep(Duration::from_secs_f32(0.1)).await;
+            }
         });
 
         tokio::time::sleep(Duration::from_secs(1)).await;
@@ -214,21 +2686,67 @@ mod tests {
 
     #[tokio::test]
     async fn peer_can_transition_to_open_sent_state() {
-        let config: Config = "64512 127.0.0.1 65413 127.0.0.2 active".parse().unwrap();
-        let mut peer = Peer::new(config);
+        let config: Config = "64512 127.0.4.1 65413 127.0.4.2 active".parse().unwrap();
+        let mut peer = new_peer(config);
         peer.start();
 
         tokio::spawn(async move {
-            let remote_config = "64513 127.0.0.2 65412 127.0.0.1 passive".parse().unwrap();
-            let mut remote_peer = Peer::new(remote_config);
+            let remote_config = "64513 127.0.4.2 65412 127.0.4.1 passive".parse().unwrap();
+            let mut remote_peer = new_peer(remote_config);
+            listen_for(&remote_peer);
             remote_peer.start();
-            remote_peer.next().await;
-            remote_peer.next().await;
+            for _ in 0..20 {
+                remote_peer.next().await;
+                tokio::time::sleep(Duration::from_secs_f32(0.1)).await;
+            }
         });
 
         tokio::time::sleep(Duration::from_secs(1)).await;
         peer.next().await;
+        peer.wait().await;
         peer.next().await;
         assert_eq!(peer.state, State::OpenSent);
     }
+
+    #[tokio::test]
+    async fn peers_in_both_mode_resolve_collision_and_establish_one_session() {
+        let config: Config = "64512 127.0.5.1 64513 127.0.5.2 both connect-retry 1"
+            .parse()
+            .unwrap();
+        let mut peer = new_peer(config);
+        listen_for(&peer);
+        peer.start();
+
+        let remote = tokio::spawn(async move {
+            let remote_config: Config = "64513 127.0.5.2 64512 127.0.5.1 both connect-retry 1"
+                .parse()
+                .unwrap();
+            let mut remote_peer = new_peer(remote_config);
+            listen_for(&remote_peer);
+            remote_peer.start();
+
+            for _ in 0..100 {
+                remote_peer.next().await;
+                if remote_peer.state == State::Established {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs_f32(0.05)).await;
+            }
+            remote_peer
+        });
+
+        for _ in 0..100 {
+            peer.next().await;
+            if peer.state == State::Established {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs_f32(0.05)).await;
+        }
+
+        let remote_peer = remote.await.unwrap();
+        assert_eq!(peer.state, State::Established);
+        assert_eq!(remote_peer.state, State::Established);
+        assert!(peer.collision_connection.is_none());
+        assert!(remote_peer.collision_connection.is_none());
+    }
 }
@@ -1,22 +1,136 @@
 use std::{
-    net::{IpAddr, Ipv4Addr},
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, btree_map::Entry},
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     ops::{Deref, DerefMut},
     str::FromStr,
+    sync::Arc,
 };
 
 use anyhow::{Context, Result};
 use bytes::{BufMut, BytesMut};
-use futures::TryStreamExt;
-use rtnetlink::{new_connection, IpVersion};
+use tokio::sync::watch;
 use tracing::info;
 
 use crate::{
-    config::Config,
-    error::{ConfigParseError, ConstructIpv4NetworkError, ConvertBytesToBgpMessageError},
+    bgp_type::{Afi, AutonomousSystemNumber},
+    config::GlobalConfig,
+    error::{
+        ConfigParseError, ConstructIpv4NetworkError, ConstructIpv6NetworkError,
+        ConvertBytesToBgpMessageError,
+    },
+    kernel::KernelRoutingTable,
+    packets::{
+        codec::MAX_MESSAGE_LENGTH,
+        notification::UpdateMessageErrorSubcode,
+        update::{UPDATE_MESSAGE_MINIMUM_LENGTH, UpdateMessage, update_message_error},
+    },
+    path_attribute::{AsPath, Ipv6NextHop, MpReachNlri, MpUnreachNlri, Origin, PathAttribute},
 };
 
+/// Routes selected by the decision process, keeping every candidate path so
+/// the selection can be explained and redone when a path goes away.
+#[derive(Debug, Default)]
+pub struct LocRib {
+    paths: BTreeMap<IpNetwork, Vec<CandidatePath>>,
+    /// Bumped on every change so Adj-RIB-Outs know when to resynchronize.
+    version: u64,
+    /// Wakes up the peers waiting for `version` to change.
+    changes: watch::Sender<u64>,
+    /// Whether paths are unusable until `resolve_next_hops` reaches their
+    /// next hop, rather than assumed reachable.
+    track_next_hops: bool,
+    /// The prefixes having a learned path towards each next hop, so that a
+    /// route change only re-resolves the paths it can affect.
+    next_hops: BTreeMap<IpAddr, BTreeSet<IpNetwork>>,
+    /// Prefixes whose paths changed since the last `resolve_next_hops`.
+    unresolved: BTreeSet<IpNetwork>,
+}
+
+/// The peer a candidate path was learned from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct PathSource {
+    pub peer_address: Ipv4Addr,
+    pub router_id: Ipv4Addr,
+    pub peer_as: AutonomousSystemNumber,
+    pub ibgp: bool,
+    /// Whether the path is originated by this router rather than learned.
+    pub local: bool,
+}
+
+impl PathSource {
+    /// The source of networks this router originates itself. It counts as
+    /// eBGP so that iBGP peers are still sent the routes.
+    pub fn local(config: &GlobalConfig) -> Self {
+        Self {
+            peer_address: config.local_ip,
+            router_id: config.local_ip,
+            peer_as: config.local_as,
+            ibgp: false,
+            local: true,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct CandidatePath {
+    pub source: PathSource,
+    pub path_attributes: Arc<Vec<PathAttribute>>,
+    /// Cost of the IGP route to the next hop, or `None` if the next hop is
+    /// unreachable and the path cannot be used.
+    pub igp_metric: Option<u32>,
+    /// The neighbor packets are handed to, as found by `resolve_next_hops`:
+    /// the gateway of the kernel route the next hop was reached through, or
+    /// the next hop itself when it is directly connected.
+    pub immediate_next_hop: Option<IpAddr>,
+}
+
+/// The step of the decision process (RFC 4271 §9.1.2.2) at which one path
+/// was preferred over another.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum SelectionReason {
+    OnlyPath,
+    ReachableNextHop,
+    HigherLocalPref,
+    ShorterAsPath,
+    LowerOrigin,
+    LowerMultiExitDisc,
+    EbgpOverIbgp,
+    LowerIgpMetric,
+    LowerRouterId,
+    LowerPeerAddress,
+}
+
+impl fmt::Display for SelectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            SelectionReason::OnlyPath => "only path",
+            SelectionReason::ReachableNextHop => "reachable next hop",
+            SelectionReason::HigherLocalPref => "higher LOCAL_PREF",
+            SelectionReason::ShorterAsPath => "shorter AS_PATH",
+            SelectionReason::LowerOrigin => "lower ORIGIN",
+            SelectionReason::LowerMultiExitDisc => {
+                "lower MULTI_EXIT_DISC from the same neighbor AS"
+            }
+            SelectionReason::EbgpOverIbgp => "learned via eBGP rather than iBGP",
+            SelectionReason::LowerIgpMetric => "lower IGP metric to the next hop",
+            SelectionReason::LowerRouterId => "lower router ID",
+            SelectionReason::LowerPeerAddress => "lower peer address",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// Outcome of the decision process for one prefix: the best path, the step
+/// that made it beat the runner-up, and the step at which every other
+/// candidate lost to it.
 #[derive(Debug, PartialEq, Eq, Clone)]
-struct LocRib;
+pub struct Decision<'a> {
+    pub best: &'a CandidatePath,
+    pub reason: SelectionReason,
+    pub rejected: Vec<(&'a CandidatePath, SelectionReason)>,
+}
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
 pub struct Ipv4Network(ipnetwork::Ipv4Network);
@@ -57,20 +171,10 @@ impl From<&Ipv4Network> for BytesMut {
     fn from(network: &Ipv4Network) -> Self {
         let prefix = network.prefix();
 
-        let n = network.network().octets();
-        let network_bytes = match prefix {
-            0 => vec![],
-            1..=8 => n[0..1].into(),
-            9..=16 => n[0..2].into(),
-            17..=24 => n[0..3].into(),
-            25..=32 => n[0..4].into(),
-            _ => panic!("Invalid prefix length: {:?}", prefix),
-        };
-
         let mut bytes = BytesMut::new();
 
         bytes.put_u8(prefix);
-        bytes.put(&network_bytes[..]);
+        bytes.put(&network.network().octets()[..network.bytes_len() - 1]);
 
         bytes
     }
@@ -88,113 +192,1944 @@ impl Ipv4Network {
 
     pub fn from_u8_slice(bytes: &[u8]) -> Result<Vec<Self>, ConvertBytesToBgpMessageError> {
         let mut networks = vec![];
-        let mut i = 0;
-        while bytes.len() > i {
-            let prefix = bytes[i];
-            i += 1;
-            match prefix {
-                0 => {
-                    networks.push(Ipv4Network::new(Ipv4Addr::new(0, 0, 0, 0), prefix).context("")?);
-                }
-                1..=8 => {
-                    networks.push(
-                        Ipv4Network::new(Ipv4Addr::new(bytes[i], 0, 0, 0), prefix).context("")?,
-                    );
-                    i += 1;
-                }
-                9..=16 => {
-                    networks.push(
-                        Ipv4Network::new(Ipv4Addr::new(bytes[i], bytes[i + 1], 0, 0), prefix)
-                            .context("")?,
-                    );
-                    i += 2;
-                }
-                17..=24 => {
-                    networks.push(
-                        Ipv4Network::new(
-                            Ipv4Addr::new(bytes[i], bytes[i + 1], bytes[i + 2], 0),
-                            prefix,
-                        )
-                        .context("")?,
-                    );
-                    i += 3;
-                }
-                25..=32 => {
-                    networks.push(
-                        Ipv4Network::new(
-                            Ipv4Addr::new(bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]),
-                            prefix,
-                        )
-                        .context("")?,
-                    );
-                    i += 4;
-                }
-                _ => {
-                    return Err(ConvertBytesToBgpMessageError::from(anyhow::anyhow!(
-                        "Invalid prefix length: {:?}",
-                        prefix
-                    )));
-                }
+        let mut rest = bytes;
+        while let [prefix, prefix_bytes @ ..] = rest {
+            let prefix = *prefix;
+            if prefix > 32 {
+                return Err(invalid_network_field(anyhow::anyhow!(
+                    "Invalid prefix length: {:?}",
+                    prefix
+                )));
             }
+
+            let prefix_octets = (prefix as usize).div_ceil(8);
+            let mut octets = [0u8; 4];
+            octets[..prefix_octets].copy_from_slice(
+                prefix_bytes
+                    .get(..prefix_octets)
+                    .context(format!("truncated IPv4 prefix: {:?}", rest))
+                    .map_err(invalid_network_field)?,
+            );
+            // Trailing bits past the prefix length are irrelevant (RFC 4271 §4.3).
+            if let Some(last) = octets[..prefix_octets].last_mut() {
+                *last &= 0xff << ((8 - prefix % 8) % 8);
+            }
+            networks.push(
+                Ipv4Network::new(Ipv4Addr::from(octets), prefix)
+                    .map_err(|e| invalid_network_field(e.into()))?,
+            );
+            rest = &prefix_bytes[prefix_octets..];
         }
 
         Ok(networks)
     }
 
     pub fn bytes_len(&self) -> usize {
-        match self.prefix() {
-            0 => 1,
-            1..=8 => 2,
-            9..=16 => 3,
-            17..=24 => 4,
-            25..=32 => 5,
-            _ => panic!("Invalid prefix length: {:?}", self.prefix()),
+        1 + (self.prefix() as usize).div_ceil(8)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct Ipv6Network(ipnetwork::Ipv6Network);
+
+impl Deref for Ipv6Network {
+    type Target = ipnetwork::Ipv6Network;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<ipnetwork::Ipv6Network> for Ipv6Network {
+    fn from(ip_network: ipnetwork::Ipv6Network) -> Self {
+        Self(ip_network)
+    }
+}
+
+impl FromStr for Ipv6Network {
+    type Err = ConfigParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let network = s
+            .parse::<ipnetwork::Ipv6Network>()
+            .context(format!("cannot parse Ipv6Network: {:?}", s))?;
+
+        Ok(Self(network))
+    }
+}
+
+impl From<&Ipv6Network> for BytesMut {
+    fn from(network: &Ipv6Network) -> Self {
+        let mut bytes = BytesMut::new();
+
+        bytes.put_u8(network.prefix());
+        bytes.put(&network.network().octets()[..network.bytes_len() - 1]);
+
+        bytes
+    }
+}
+
+impl Ipv6Network {
+    pub fn new(addr: Ipv6Addr, prefix: u8) -> Result<Self, ConstructIpv6NetworkError> {
+        let network = ipnetwork::Ipv6Network::new(addr, prefix).context(format!(
+            "cannot create Ipv6Network: {:?}, {:?}",
+            addr, prefix
+        ))?;
+
+        Ok(Self(network))
+    }
+
+    pub fn from_u8_slice(bytes: &[u8]) -> Result<Vec<Self>, ConvertBytesToBgpMessageError> {
+        let mut networks = vec![];
+        let mut rest = bytes;
+        while let [prefix, prefix_bytes @ ..] = rest {
+            let prefix = *prefix;
+            if prefix > 128 {
+                return Err(invalid_network_field(anyhow::anyhow!(
+                    "Invalid prefix length: {:?}",
+                    prefix
+                )));
+            }
+
+            let prefix_octets = (prefix as usize).div_ceil(8);
+            let mut octets = [0u8; 16];
+            octets[..prefix_octets].copy_from_slice(
+                prefix_bytes
+                    .get(..prefix_octets)
+                    .context(format!("truncated IPv6 prefix: {:?}", rest))
+                    .map_err(invalid_network_field)?,
+            );
+            // Trailing bits past the prefix length are irrelevant (RFC 4271 §4.3).
+            if let Some(last) = octets[..prefix_octets].last_mut() {
+                *last &= 0xff << ((8 - prefix % 8) % 8);
+            }
+            networks.push(
+                Ipv6Network::new(Ipv6Addr::from(octets), prefix)
+                    .map_err(|e| invalid_network_field(e.into()))?,
+            );
+            rest = &prefix_bytes[prefix_octets..];
         }
+
+        Ok(networks)
+    }
+
+    pub fn bytes_len(&self) -> usize {
+        1 + (self.prefix() as usize).div_ceil(8)
+    }
+}
+
+/// An error in an encoded prefix, answered with an Invalid Network Field
+/// NOTIFICATION unless the attribute carrying it calls for another one.
+fn invalid_network_field(error: anyhow::Error) -> ConvertBytesToBgpMessageError {
+    update_message_error(UpdateMessageErrorSubcode::InvalidNetworkField, &[], error)
+}
+
+/// A prefix of either address family, as stored in the RIBs.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum IpNetwork {
+    V4(Ipv4Network),
+    V6(Ipv6Network),
+}
+
+impl IpNetwork {
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, IpNetwork::V4(_))
+    }
+
+    pub fn afi(&self) -> Afi {
+        match self {
+            IpNetwork::V4(_) => Afi::Ipv4,
+            IpNetwork::V6(_) => Afi::Ipv6,
+        }
+    }
+
+    pub fn prefix(&self) -> u8 {
+        match self {
+            IpNetwork::V4(network) => network.prefix(),
+            IpNetwork::V6(network) => network.prefix(),
+        }
+    }
+
+    /// The first address of the network.
+    pub fn network_address(&self) -> IpAddr {
+        match self {
+            IpNetwork::V4(network) => IpAddr::V4(network.network()),
+            IpNetwork::V6(network) => IpAddr::V6(network.network()),
+        }
+    }
+
+    /// The network of `prefix` bits that contains `addr`.
+    fn covering(addr: IpAddr, prefix: u8) -> Option<Self> {
+        match addr {
+            IpAddr::V4(addr) => {
+                let network = ipnetwork::Ipv4Network::new(addr, prefix).ok()?;
+                Some(Ipv4Network::new(network.network(), prefix).ok()?.into())
+            }
+            IpAddr::V6(addr) => {
+                let network = ipnetwork::Ipv6Network::new(addr, prefix).ok()?;
+                Some(Ipv6Network::new(network.network(), prefix).ok()?.into())
+            }
+        }
+    }
+
+    /// The number of leading bits the network address shares with `addr`,
+    /// unless they are of different families.
+    fn common_prefix(&self, addr: IpAddr) -> Option<u8> {
+        let differing = match (self, addr) {
+            (IpNetwork::V4(network), IpAddr::V4(addr)) => {
+                (u32::from(network.network()) ^ u32::from(addr)).leading_zeros()
+            }
+            (IpNetwork::V6(network), IpAddr::V6(addr)) => {
+                (u128::from(network.network()) ^ u128::from(addr)).leading_zeros()
+            }
+            _ => return None,
+        };
+        Some(differing as u8)
+    }
+
+    /// Whether the network lies within `other`, which is of the same family.
+    pub fn is_subnet_of(&self, other: &IpNetwork) -> bool {
+        match (self, other) {
+            (IpNetwork::V4(network), IpNetwork::V4(other)) => network.is_subnet_of(**other),
+            (IpNetwork::V6(network), IpNetwork::V6(other)) => network.is_subnet_of(**other),
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self, addr) {
+            (IpNetwork::V4(network), IpAddr::V4(addr)) => network.contains(addr),
+            (IpNetwork::V6(network), IpAddr::V6(addr)) => network.contains(addr),
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = ConfigParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(':') {
+            Ok(s.parse::<Ipv6Network>()?.into())
+        } else {
+            Ok(s.parse::<Ipv4Network>()?.into())
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpNetwork::V4(network) => write!(f, "{}", **network),
+            IpNetwork::V6(network) => write!(f, "{}", **network),
+        }
+    }
+}
+
+impl From<Ipv4Network> for IpNetwork {
+    fn from(network: Ipv4Network) -> Self {
+        Self::V4(network)
+    }
+}
+
+impl From<Ipv6Network> for IpNetwork {
+    fn from(network: Ipv6Network) -> Self {
+        Self::V6(network)
+    }
+}
+
+/// Routes received from one peer, before any policy or best-path selection
+/// (RFC 4271 §3.2). Each prefix shares the attributes of the UPDATE that
+/// carried it.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct AdjRibIn(BTreeMap<IpNetwork, Arc<Vec<PathAttribute>>>);
+
+impl AdjRibIn {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies an UPDATE: withdrawn prefixes are removed first, then the
+    /// advertised ones replace any previous route for the same prefix.
+    /// Returns the prefixes whose route changed.
+    pub fn update(&mut self, update: &UpdateMessage) -> Vec<IpNetwork> {
+        let mut withdrawn_routes: Vec<IpNetwork> = update
+            .withdrawn_routes
+            .iter()
+            .map(|network| (*network).into())
+            .collect();
+        let mut nlri: Vec<IpNetwork> = update
+            .network_layer_reachability_information
+            .iter()
+            .map(|network| (*network).into())
+            .collect();
+        for path_attribute in update.path_attributes.iter() {
+            match path_attribute {
+                PathAttribute::MpReachNlri(m) => {
+                    nlri.extend(m.nlri.iter().map(|network| IpNetwork::from(*network)))
+                }
+                PathAttribute::MpUnreachNlri(m) => withdrawn_routes.extend(
+                    m.withdrawn_routes
+                        .iter()
+                        .map(|network| IpNetwork::from(*network)),
+                ),
+                _ => {}
+            }
+        }
+
+        for network in &withdrawn_routes {
+            self.0.remove(network);
+        }
+        for network in &nlri {
+            self.0.insert(*network, Arc::clone(&update.path_attributes));
+        }
+
+        withdrawn_routes.into_iter().chain(nlri).collect()
+    }
+
+    pub fn get(&self, network: &IpNetwork) -> Option<&Arc<Vec<PathAttribute>>> {
+        self.0.get(network)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&IpNetwork, &Arc<Vec<PathAttribute>>)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Removes every IPv6 route, returning their prefixes.
+    pub fn remove_ipv6(&mut self) -> Vec<IpNetwork> {
+        let networks: Vec<IpNetwork> = self
+            .0
+            .keys()
+            .filter(|network| matches!(network, IpNetwork::V6(_)))
+            .copied()
+            .collect();
+        for network in &networks {
+            self.0.remove(network);
+        }
+        networks
+    }
+}
+
+/// How many BGP routes a next hop may be resolved through.
+const MAX_NEXT_HOP_RECURSION: u8 = 4;
+
+/// How a next hop is reached: the IGP metric and the immediate next hop.
+type Resolution = (u32, IpAddr);
+
+/// Routes advertised to one peer after export rules were applied
+/// (RFC 4271 §3.2). IPv6 routes are advertised in MP_REACH_NLRI and
+/// withdrawn in MP_UNREACH_NLRI (RFC 4760).
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct AdjRibOut {
+    routes: BTreeMap<IpNetwork, Arc<Vec<PathAttribute>>>,
+    loc_rib_version: Option<u64>,
+}
+
+impl AdjRibOut {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_synced_with(&self, loc_rib: &LocRib) -> bool {
+        self.loc_rib_version == Some(loc_rib.version())
+    }
+
+    /// Brings the Adj-RIB-Out in line with the best paths of `loc_rib`,
+    /// returning the UPDATEs that tell the peer about the difference.
+    /// `export` returns the attributes to advertise a route with, or `None`
+    /// if the peer must not receive it. Those of an IPv6 route hold an
+    /// MP_REACH_NLRI with its next hop; the prefixes are filled in here.
+    pub fn sync(
+        &mut self,
+        loc_rib: &LocRib,
+        export: impl Fn(&IpNetwork, &CandidatePath) -> Option<Vec<PathAttribute>>,
+    ) -> Vec<UpdateMessage> {
+        let routes: BTreeMap<IpNetwork, Vec<PathAttribute>> = loc_rib
+            .best_paths()
+            .filter_map(|(network, path)| Some((*network, export(network, path)?)))
+            .collect();
+
+        let withdrawn_routes: Vec<IpNetwork> = self
+            .routes
+            .keys()
+            .filter(|network| !routes.contains_key(network))
+            .copied()
+            .collect();
+
+        // Prefixes sharing the same attributes go into the same UPDATEs,
+        // one family at a time. The groups are looked up by their
+        // attributes and kept in the order they were first seen.
+        let mut advertisements: Vec<(Arc<Vec<PathAttribute>>, Vec<IpNetwork>)> = vec![];
+        let mut groups: HashMap<Arc<Vec<PathAttribute>>, usize> = HashMap::new();
+        for (network, path_attributes) in routes {
+            if self.routes.get(&network).map(|p| p.as_slice()) == Some(&path_attributes[..]) {
+                continue;
+            }
+            let group = match groups.get(&path_attributes) {
+                Some(group) => *group,
+                None => {
+                    let path_attributes = Arc::new(path_attributes);
+                    groups.insert(Arc::clone(&path_attributes), advertisements.len());
+                    advertisements.push((path_attributes, vec![]));
+                    advertisements.len() - 1
+                }
+            };
+            let (path_attributes, networks) = &mut advertisements[group];
+            networks.push(network);
+            self.routes.insert(network, Arc::clone(path_attributes));
+        }
+        for network in &withdrawn_routes {
+            self.routes.remove(network);
+        }
+        self.loc_rib_version = Some(loc_rib.version());
+
+        let (ipv4_withdrawn_routes, ipv6_withdrawn_routes) = split_families(withdrawn_routes);
+        let empty_mp_unreach_nlri = PathAttribute::MpUnreachNlri(MpUnreachNlri {
+            withdrawn_routes: vec![],
+        });
+        let withdrawals = packed(
+            &ipv4_withdrawn_routes,
+            prefix_space(&[]),
+            Ipv4Network::bytes_len,
+        )
+        .into_iter()
+        .map(|networks| UpdateMessage::new(Arc::new(vec![]), vec![], networks.to_vec()))
+        .chain(
+            packed(
+                &ipv6_withdrawn_routes,
+                prefix_space(&[empty_mp_unreach_nlri]),
+                Ipv6Network::bytes_len,
+            )
+            .into_iter()
+            .map(|networks| {
+                let mp_unreach_nlri = PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                    withdrawn_routes: networks.to_vec(),
+                });
+                UpdateMessage::new(Arc::new(vec![mp_unreach_nlri]), vec![], vec![])
+            }),
+        );
+        let announcements = advertisements.into_iter().flat_map(|(p, networks)| {
+            let (ipv4_networks, ipv6_networks) = split_families(networks);
+            let space = prefix_space(&p);
+            packed(&ipv4_networks, space, Ipv4Network::bytes_len)
+                .into_iter()
+                .map(|networks| UpdateMessage::new(Arc::clone(&p), networks.to_vec(), vec![]))
+                .chain(
+                    packed(&ipv6_networks, space, Ipv6Network::bytes_len)
+                        .into_iter()
+                        .map(|networks| ipv6_announcement(&p, networks)),
+                )
+                .collect::<Vec<_>>()
+        });
+        withdrawals.chain(announcements).collect()
+    }
+
+    pub fn get(&self, network: &IpNetwork) -> Option<&Arc<Vec<PathAttribute>>> {
+        self.routes.get(network)
+    }
+
+    pub fn clear(&mut self) {
+        self.routes.clear();
+        self.loc_rib_version = None;
+    }
+
+    /// Makes the next `sync` run every best path through export again, e.g.
+    /// after a reload changed how routes are exported.
+    pub fn invalidate(&mut self) {
+        self.loc_rib_version = None;
+    }
+
+    /// Forgets what was advertised for `afi`, so that the next `sync`
+    /// advertises all of it again, e.g. on a ROUTE-REFRESH (RFC 2918).
+    pub fn clear_family(&mut self, afi: Afi) {
+        self.routes
+            .retain(|network, _| network.is_ipv4() != (afi == Afi::Ipv4));
+        self.loc_rib_version = None;
+    }
+}
+
+fn split_families(networks: Vec<IpNetwork>) -> (Vec<Ipv4Network>, Vec<Ipv6Network>) {
+    let mut ipv4_networks = vec![];
+    let mut ipv6_networks = vec![];
+    for network in networks {
+        match network {
+            IpNetwork::V4(network) => ipv4_networks.push(network),
+            IpNetwork::V6(network) => ipv6_networks.push(network),
+        }
+    }
+    (ipv4_networks, ipv6_networks)
+}
+
+/// Octets left for prefixes in an UPDATE with `path_attributes`, in either
+/// AS number width it may be sent in. One more is kept for the attribute
+/// carrying IPv6 prefixes, which may need an extended length once they are
+/// added.
+fn prefix_space(path_attributes: &[PathAttribute]) -> usize {
+    let four_octet_as: usize = path_attributes.iter().map(|p| p.bytes_len(true)).sum();
+    let two_octet_as: usize = PathAttribute::to_two_octet_as(path_attributes)
+        .iter()
+        .map(|p| p.bytes_len(false))
+        .sum();
+    (MAX_MESSAGE_LENGTH - UPDATE_MESSAGE_MINIMUM_LENGTH - 1)
+        .saturating_sub(four_octet_as.max(two_octet_as))
+}
+
+/// Splits `networks` into runs taking at most `space` octets once encoded,
+/// one per UPDATE. A prefix that does not fit even on its own still gets a
+/// run, which is then refused when encoded.
+fn packed<N>(networks: &[N], space: usize, bytes_len: impl Fn(&N) -> usize) -> Vec<&[N]> {
+    let mut runs = vec![];
+    let mut start = 0;
+    let mut used = 0;
+    for (i, network) in networks.iter().enumerate() {
+        let len = bytes_len(network);
+        if used + len > space && i > start {
+            runs.push(&networks[start..i]);
+            start = i;
+            used = 0;
+        }
+        used += len;
+    }
+    if start < networks.len() {
+        runs.push(&networks[start..]);
+    }
+    runs
+}
+
+/// An UPDATE advertising `networks` in the MP_REACH_NLRI of `path_attributes`.
+fn ipv6_announcement(path_attributes: &[PathAttribute], networks: &[Ipv6Network]) -> UpdateMessage {
+    let path_attributes = path_attributes
+        .iter()
+        .map(|p| match p {
+            PathAttribute::MpReachNlri(m) => PathAttribute::MpReachNlri(MpReachNlri {
+                next_hop: m.next_hop,
+                nlri: networks.to_vec(),
+            }),
+            p => p.clone(),
+        })
+        .collect();
+    UpdateMessage::new(Arc::new(path_attributes), vec![], vec![])
+}
+
+/// Attributes set on routes as they are originated into Loc-RIB.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord, Default)]
+pub struct RouteMap {
+    pub multi_exit_disc: Option<u32>,
+    pub local_pref: Option<u32>,
+}
+
+impl RouteMap {
+    pub fn apply(&self, path_attributes: &mut Vec<PathAttribute>) {
+        if let Some(multi_exit_disc) = self.multi_exit_disc {
+            path_attributes.retain(|p| !matches!(p, PathAttribute::MultiExitDisc(_)));
+            path_attributes.push(PathAttribute::MultiExitDisc(multi_exit_disc));
+        }
+        if let Some(local_pref) = self.local_pref {
+            path_attributes.retain(|p| !matches!(p, PathAttribute::LocalPref(_)));
+            path_attributes.push(PathAttribute::LocalPref(local_pref));
+        }
+    }
+}
+
+/// Default LOCAL_PREF for paths that do not carry one.
+pub const DEFAULT_LOCAL_PREF: u32 = 100;
+
+impl CandidatePath {
+    /// Paths learned over eBGP only carry a LOCAL_PREF an import route map
+    /// set, as peers discard the one received with them.
+    fn local_pref(&self) -> u32 {
+        self.path_attributes
+            .iter()
+            .find_map(|p| match p {
+                PathAttribute::LocalPref(l) => Some(*l),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_LOCAL_PREF)
+    }
+
+    fn as_path_length(&self) -> usize {
+        self.path_attributes
+            .iter()
+            .map(|p| match p {
+                PathAttribute::AsPath(a) => a.path_length(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn origin(&self) -> u8 {
+        self.path_attributes
+            .iter()
+            .find_map(|p| match p {
+                PathAttribute::Origin(Origin::Igp) => Some(0),
+                PathAttribute::Origin(Origin::Egp) => Some(1),
+                PathAttribute::Origin(Origin::Incomplete) => Some(2),
+                _ => None,
+            })
+            .unwrap_or(2)
+    }
+
+    /// A missing MULTI_EXIT_DISC counts as the lowest value (RFC 4271 §9.1.2.2).
+    fn multi_exit_disc(&self) -> u32 {
+        self.path_attributes
+            .iter()
+            .find_map(|p| match p {
+                PathAttribute::MultiExitDisc(m) => Some(*m),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// NEXT_HOP of a path to the IPv4 `network`, or the global next hop of
+    /// MP_REACH_NLRI for an IPv6 one. An UPDATE may carry both, and its
+    /// prefixes of both families then share the attributes.
+    pub fn next_hop(&self, network: &IpNetwork) -> Option<IpAddr> {
+        self.path_attributes
+            .iter()
+            .find_map(|p| match (p, network) {
+                (PathAttribute::NextHop(next_hop), IpNetwork::V4(_)) => Some(IpAddr::V4(*next_hop)),
+                (PathAttribute::MpReachNlri(mp_reach_nlri), IpNetwork::V6(_)) => {
+                    Some(IpAddr::V6(mp_reach_nlri.next_hop.global))
+                }
+                _ => None,
+            })
+    }
+
+    /// Where packets for `network` are forwarded to: the resolved immediate
+    /// next hop or, if next hops are not tracked, the next hop itself.
+    pub fn forwarding_next_hop(&self, network: &IpNetwork) -> Option<IpAddr> {
+        self.immediate_next_hop.or_else(|| self.next_hop(network))
+    }
+
+    /// The AS the path entered ours from: the first AS of its AS_PATH.
+    fn neighbor_as(&self) -> Option<AutonomousSystemNumber> {
+        self.path_attributes.iter().find_map(|p| match p {
+            PathAttribute::AsPath(as_path) => as_path.neighbor_as(),
+            _ => None,
+        })
     }
 }
 
 impl LocRib {
-    pub async fn new(config: &Config) -> Result<Self> {
-        todo!();
+    /// Creates a Loc-RIB holding the configured networks found in the
+    /// kernel routing table.
+    pub fn new(config: &GlobalConfig, kernel_routing_table: &KernelRoutingTable) -> Self {
+        let mut loc_rib = Self {
+            track_next_hops: true,
+            ..Self::default()
+        };
+        loc_rib.originate_networks(config, kernel_routing_table);
+        loc_rib
     }
 
-    async fn lookup_kernel_routing_table(
-        network_address: Ipv4Network,
-    ) -> Result<(Vec<Ipv4Network>)> {
-        let (connection, handle, _) = new_connection()?;
-        tokio::spawn(connection);
-        let mut routes = handle.route().get(IpVersion::V4).execute();
+    /// Injects each configured network present in the kernel routing table
+    /// and the kernel routes selected for redistribution, withdrawing those
+    /// that are gone from the kernel.
+    pub fn originate_networks(
+        &mut self,
+        config: &GlobalConfig,
+        kernel_routing_table: &KernelRoutingTable,
+    ) {
+        let mut originated: BTreeMap<IpNetwork, Vec<PathAttribute>> = BTreeMap::new();
+        for redistribute in &config.redistribute {
+            for route in kernel_routing_table
+                .routes()
+                .filter(|route| redistribute.matches(route))
+            {
+                let Some(mut path_attributes) =
+                    Self::originated_attributes(config, &route.network, Origin::Incomplete)
+                else {
+                    continue;
+                };
+                if let Some(route_map) = &redistribute.route_map {
+                    route_map.apply(&mut path_attributes);
+                }
+                originated.entry(route.network).or_insert(path_attributes);
+            }
+        }
+        // A network statement takes precedence over redistribution.
+        for network in &config.networks {
+            if kernel_routing_table.contains(network)
+                && let Some(path_attributes) =
+                    Self::originated_attributes(config, network, Origin::Igp)
+            {
+                originated.insert(*network, path_attributes);
+            }
+        }
 
-        let mut results = vec![];
-        while let Some(route) = routes.try_next().await? {
-            let destination = if let Some((IpAddr::V4(addr), prefix)) = route.destination_prefix() {
-                ipnetwork::Ipv4Network::new(addr, prefix)?.into()
-            } else {
+        let source = PathSource::local(config);
+        let withdrawn: Vec<IpNetwork> = self
+            .paths
+            .iter()
+            .filter(|(network, paths)| {
+                !originated.contains_key(network) && paths.iter().any(|p| p.source == source)
+            })
+            .map(|(network, _)| *network)
+            .collect();
+        for network in withdrawn {
+            info!(
+                "withdrawing network missing from kernel, network={:?}",
+                network
+            );
+            self.remove(&network, &source);
+        }
+        for (network, path_attributes) in originated {
+            let current = self
+                .paths
+                .get(&network)
+                .and_then(|paths| paths.iter().find(|p| p.source == source));
+            if current.is_some_and(|p| *p.path_attributes == path_attributes) {
+                continue;
+            }
+            info!("originating network, network={:?}", network);
+            self.insert(
+                network,
+                CandidatePath {
+                    source,
+                    path_attributes: Arc::new(path_attributes),
+                    igp_metric: Some(0),
+                    immediate_next_hop: None,
+                },
+            );
+        }
+    }
+
+    /// Attributes of a route to `network` originated by this router, with
+    /// itself as next hop. IPv6 ones carry it in an MP_REACH_NLRI, so they
+    /// can only be originated if `local-ipv6` is configured.
+    fn originated_attributes(
+        config: &GlobalConfig,
+        network: &IpNetwork,
+        origin: Origin,
+    ) -> Option<Vec<PathAttribute>> {
+        let next_hop = match network {
+            IpNetwork::V4(_) => PathAttribute::NextHop(config.local_ip),
+            IpNetwork::V6(_) => PathAttribute::MpReachNlri(MpReachNlri {
+                next_hop: Ipv6NextHop {
+                    global: config.local_ipv6?,
+                    link_local: None,
+                },
+                nlri: vec![],
+            }),
+        };
+        Some(vec![
+            PathAttribute::Origin(origin),
+            PathAttribute::AsPath(AsPath::default()),
+            next_hop,
+        ])
+    }
+
+    /// Resolves the next hop of the learned paths that may have changed
+    /// against the kernel routing table and, if `recursive-next-hop` is
+    /// configured, through other BGP routes, taking the IGP metric from the
+    /// resolving route. Only the paths of prefixes changed since the last
+    /// call and those whose next hop lies in one of the `kernel_changes`
+    /// networks or, when recursive, in a changed prefix are looked at.
+    /// Paths whose next hop cannot be resolved are not used (RFC 4271
+    /// §9.1.2.1).
+    pub fn resolve_next_hops(
+        &mut self,
+        config: &GlobalConfig,
+        kernel_routing_table: &KernelRoutingTable,
+        kernel_changes: &[IpNetwork],
+    ) {
+        let mut changed = std::mem::take(&mut self.unresolved);
+        let mut affected = changed.clone();
+        affected.extend(self.networks_via(kernel_changes));
+        let mut resolved_any = false;
+        for _ in 0..=MAX_NEXT_HOP_RECURSION {
+            if config.recursive_next_hop {
+                affected.extend(self.networks_via(&changed));
+            }
+            changed = self.resolve_networks(&affected, config, kernel_routing_table);
+            resolved_any |= !changed.is_empty();
+            if !config.recursive_next_hop || changed.is_empty() {
+                break;
+            }
+            affected = BTreeSet::new();
+        }
+        if resolved_any {
+            self.bump_version();
+        }
+    }
+
+    /// Makes the next `resolve_next_hops` look at every path again, e.g.
+    /// because the configuration changed how next hops are resolved.
+    pub fn invalidate_next_hops(&mut self) {
+        self.unresolved = self.paths.keys().copied().collect();
+    }
+
+    /// The prefixes with a path whose next hop lies in one of `networks`.
+    fn networks_via<'a>(
+        &self,
+        networks: impl IntoIterator<Item = &'a IpNetwork>,
+    ) -> BTreeSet<IpNetwork> {
+        networks
+            .into_iter()
+            .flat_map(|network| {
+                self.next_hops
+                    .range(network.network_address()..)
+                    .take_while(|(next_hop, _)| network.contains(**next_hop))
+                    .flat_map(|(_, networks)| networks.iter().copied())
+            })
+            .collect()
+    }
+
+    /// Resolves the next hop of the learned paths of `networks`, returning
+    /// the prefixes where an IGP metric or immediate next hop changed. Each
+    /// next hop is resolved once, however many paths share it.
+    fn resolve_networks(
+        &mut self,
+        networks: &BTreeSet<IpNetwork>,
+        config: &GlobalConfig,
+        kernel_routing_table: &KernelRoutingTable,
+    ) -> BTreeSet<IpNetwork> {
+        let mut resolutions: BTreeMap<IpAddr, Option<Resolution>> = BTreeMap::new();
+        let mut resolved: Vec<(IpNetwork, usize, Option<Resolution>)> = vec![];
+        for network in networks {
+            let Some(paths) = self.paths.get(network) else {
                 continue;
             };
+            for (i, path) in paths.iter().enumerate() {
+                if path.source.local {
+                    continue;
+                }
+                let resolution = path.next_hop(network).and_then(|next_hop| {
+                    *resolutions.entry(next_hop).or_insert_with(|| {
+                        self.resolve(
+                            next_hop,
+                            kernel_routing_table,
+                            config.recursive_next_hop,
+                            MAX_NEXT_HOP_RECURSION,
+                        )
+                    })
+                });
+                resolved.push((*network, i, resolution));
+            }
+        }
 
-            if destination != network_address {
+        let mut changed = BTreeSet::new();
+        for (network, i, resolution) in resolved {
+            let Some(path) = self.paths.get_mut(&network).and_then(|p| p.get_mut(i)) else {
                 continue;
+            };
+            let igp_metric = resolution.map(|(igp_metric, _)| igp_metric);
+            let immediate_next_hop = resolution.map(|(_, immediate_next_hop)| immediate_next_hop);
+            if path.igp_metric != igp_metric || path.immediate_next_hop != immediate_next_hop {
+                info!(
+                    "next hop resolved, network={:?}, next-hop={:?}, immediate-next-hop={:?}, igp-metric={:?}",
+                    network,
+                    path.next_hop(&network),
+                    immediate_next_hop,
+                    igp_metric
+                );
+                path.igp_metric = igp_metric;
+                path.immediate_next_hop = immediate_next_hop;
+                changed.insert(network);
             }
+        }
+        changed
+    }
 
-            results.push(destination);
+    /// The IGP metric to reach `next_hop` and the immediate next hop to
+    /// forward to, following the most specific of the kernel route and, when
+    /// `recursive`, the best BGP route covering it.
+    fn resolve(
+        &self,
+        next_hop: IpAddr,
+        kernel_routing_table: &KernelRoutingTable,
+        recursive: bool,
+        depth: u8,
+    ) -> Option<Resolution> {
+        let kernel_route = kernel_routing_table.lookup(next_hop);
+        if recursive
+            && depth > 0
+            && let Some((network, path)) = self.longest_match(next_hop)
+            && kernel_route.is_none_or(|route| network.prefix() > route.network.prefix())
+        {
+            return self.resolve(
+                path.next_hop(&network)?,
+                kernel_routing_table,
+                recursive,
+                depth - 1,
+            );
         }
+        kernel_route.map(|route| (route.metric, route.gateway.unwrap_or(next_hop)))
+    }
 
-        Ok(results)
+    /// The most specific prefix with a usable best path that contains
+    /// `addr`. The prefixes containing an address sort from the shortest to
+    /// the longest, so the last one up to `addr` is looked at first. When
+    /// it does not contain `addr`, no prefix longer than the bits they
+    /// share can, and the search goes on below that length.
+    fn longest_match(&self, addr: IpAddr) -> Option<(IpNetwork, &CandidatePath)> {
+        let mut prefix = if addr.is_ipv4() { 32 } else { 128 };
+        loop {
+            let bound = IpNetwork::covering(addr, prefix)?;
+            let (network, _) = self.paths.range(..=bound).next_back()?;
+            if network.contains(addr) {
+                if let Some(path) = self.best_path(network) {
+                    return Some((*network, path));
+                }
+                prefix = network.prefix().checked_sub(1)?;
+            } else {
+                let shared = network.common_prefix(addr)?;
+                prefix = shared.min(prefix.checked_sub(1)?);
+            }
+        }
+    }
+
+    /// Replaces the paths `source` contributes for `networks` with those now
+    /// in its Adj-RIB-In, passed through `import`.
+    pub fn update_from_adj_rib_in(
+        &mut self,
+        source: PathSource,
+        adj_rib_in: &AdjRibIn,
+        networks: &[IpNetwork],
+        import: Option<&RouteMap>,
+    ) {
+        for network in networks {
+            self.remove(network, &source);
+            if let Some(path_attributes) = adj_rib_in.get(network) {
+                let path_attributes = match import {
+                    Some(import) => {
+                        let mut path_attributes = path_attributes.to_vec();
+                        import.apply(&mut path_attributes);
+                        Arc::new(path_attributes)
+                    }
+                    None => Arc::clone(path_attributes),
+                };
+                self.insert(
+                    *network,
+                    CandidatePath {
+                        source,
+                        path_attributes,
+                        igp_metric: (!self.track_next_hops).then_some(0),
+                        immediate_next_hop: None,
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn insert(&mut self, network: IpNetwork, path: CandidatePath) {
+        let before = self.next_hops_of(&network);
+        let paths = self.paths.entry(network).or_default();
+        paths.retain(|p| p.source != path.source);
+        paths.push(path);
+        self.reindex(network, before);
+        self.bump_version();
+    }
+
+    pub fn remove(&mut self, network: &IpNetwork, source: &PathSource) {
+        if self.remove_path(network, source) {
+            self.bump_version();
+        }
+    }
+
+    /// Drops every path learned from `source`, e.g. when its session goes down.
+    pub fn remove_source(&mut self, source: &PathSource) {
+        let networks: Vec<IpNetwork> = self
+            .paths
+            .iter()
+            .filter(|(_, paths)| paths.iter().any(|p| p.source == *source))
+            .map(|(network, _)| *network)
+            .collect();
+        let mut removed = false;
+        for network in networks {
+            removed |= self.remove_path(&network, source);
+        }
+        if removed {
+            self.bump_version();
+        }
+    }
+
+    fn remove_path(&mut self, network: &IpNetwork, source: &PathSource) -> bool {
+        let before = self.next_hops_of(network);
+        let Some(paths) = self.paths.get_mut(network) else {
+            return false;
+        };
+        paths.retain(|p| p.source != *source);
+        if paths.is_empty() {
+            self.paths.remove(network);
+        }
+        self.reindex(*network, before);
+        true
+    }
+
+    fn next_hops_of(&self, network: &IpNetwork) -> BTreeSet<IpAddr> {
+        self.paths
+            .get(network)
+            .into_iter()
+            .flatten()
+            .filter(|path| !path.source.local)
+            .filter_map(|path| path.next_hop(network))
+            .collect()
+    }
+
+    /// Moves `network` in the next hop index from the next hops its paths
+    /// had, `before`, to those they have now, and marks it for resolution.
+    fn reindex(&mut self, network: IpNetwork, before: BTreeSet<IpAddr>) {
+        let after = self.next_hops_of(&network);
+        for next_hop in before.difference(&after) {
+            if let Entry::Occupied(mut networks) = self.next_hops.entry(*next_hop) {
+                networks.get_mut().remove(&network);
+                if networks.get().is_empty() {
+                    networks.remove();
+                }
+            }
+        }
+        for next_hop in after.difference(&before) {
+            self.next_hops.entry(*next_hop).or_default().insert(network);
+        }
+        self.unresolved.insert(network);
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// A receiver notified of every change to Loc-RIB.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    fn bump_version(&mut self) {
+        self.version += 1;
+        self.changes.send_replace(self.version);
+    }
+
+    /// The best path of every prefix.
+    pub fn best_paths(&self) -> impl Iterator<Item = (&IpNetwork, &CandidatePath)> {
+        self.paths
+            .keys()
+            .filter_map(|network| Some((network, self.best_path(network)?)))
+    }
+
+    pub fn best_path(&self, network: &IpNetwork) -> Option<&CandidatePath> {
+        self.decision(network).map(|decision| decision.best)
+    }
+
+    /// Runs the decision process for `network`, explaining the result.
+    pub fn decision(&self, network: &IpNetwork) -> Option<Decision<'_>> {
+        let paths = self.paths.get(network)?;
+        let best = paths
+            .iter()
+            .reduce(|best, path| match Self::compare(path, best) {
+                (Ordering::Less, _) => path,
+                _ => best,
+            })
+            .filter(|best| best.igp_metric.is_some())?;
+
+        let rejected: Vec<(&CandidatePath, SelectionReason)> = paths
+            .iter()
+            .filter(|path| !std::ptr::eq(*path, best))
+            .map(|path| (path, Self::compare(best, path).1))
+            .collect();
+        let reason = rejected
+            .iter()
+            .map(|(_, reason)| *reason)
+            .max()
+            .unwrap_or(SelectionReason::OnlyPath);
+
+        Some(Decision {
+            best,
+            reason,
+            rejected,
+        })
+    }
+
+    /// Orders two paths by preference, `Less` meaning `a` is preferred, and
+    /// names the step of RFC 4271 §9.1.2.2 that decided.
+    fn compare(a: &CandidatePath, b: &CandidatePath) -> (Ordering, SelectionReason) {
+        let steps = [
+            (
+                b.igp_metric.is_some().cmp(&a.igp_metric.is_some()),
+                SelectionReason::ReachableNextHop,
+            ),
+            (
+                b.local_pref().cmp(&a.local_pref()),
+                SelectionReason::HigherLocalPref,
+            ),
+            (
+                a.as_path_length().cmp(&b.as_path_length()),
+                SelectionReason::ShorterAsPath,
+            ),
+            (a.origin().cmp(&b.origin()), SelectionReason::LowerOrigin),
+            (
+                if a.neighbor_as() == b.neighbor_as() {
+                    a.multi_exit_disc().cmp(&b.multi_exit_disc())
+                } else {
+                    Ordering::Equal
+                },
+                SelectionReason::LowerMultiExitDisc,
+            ),
+            (
+                a.source.ibgp.cmp(&b.source.ibgp),
+                SelectionReason::EbgpOverIbgp,
+            ),
+            (
+                a.igp_metric.cmp(&b.igp_metric),
+                SelectionReason::LowerIgpMetric,
+            ),
+            (
+                a.source.router_id.cmp(&b.source.router_id),
+                SelectionReason::LowerRouterId,
+            ),
+            (
+                a.source.peer_address.cmp(&b.source.peer_address),
+                SelectionReason::LowerPeerAddress,
+            ),
+        ];
+
+        steps
+            .into_iter()
+            .find(|(ordering, _)| ordering.is_ne())
+            .unwrap_or((Ordering::Equal, SelectionReason::LowerPeerAddress))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::routing::LocRib;
-
-    #[tokio::test]
-    async fn locrib_can_lookup_kernel_routing_table() {
-        let network = ipnetwork::Ipv4Network::new("192.168.0.0".parse().unwrap(), 16)
-            .unwrap()
-            .into();
-        let routes = LocRib::lookup_kernel_routing_table(network).await.unwrap();
-        let expected = vec![network];
-        assert_eq!(routes, expected);
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    use bytes::BytesMut;
+
+    use rtnetlink::packet::{RT_TABLE_MAIN, RTPROT_BOOT, RTPROT_KERNEL, RTPROT_STATIC};
+
+    use crate::config::Config;
+    use crate::kernel::{KernelRoute, KernelRoutingTable};
+    use crate::packets::notification::{ErrorCode, UpdateMessageErrorSubcode};
+    use crate::packets::update::UpdateMessage;
+    use crate::path_attribute::{
+        AsPath, Ipv6NextHop, MpReachNlri, MpUnreachNlri, Origin, PathAttribute,
+    };
+    use crate::routing::{
+        AdjRibIn, AdjRibOut, CandidatePath, IpNetwork, Ipv4Network, Ipv6Network, LocRib,
+        PathSource, RouteMap, SelectionReason,
+    };
+
+    fn candidate(peer: u8, ibgp: bool, path_attributes: Vec<PathAttribute>) -> CandidatePath {
+        CandidatePath {
+            source: PathSource {
+                peer_address: [10, 0, 0, peer].into(),
+                router_id: [192, 168, 0, peer].into(),
+                peer_as: if ibgp { 64512 } else { 64513 }.into(),
+                ibgp,
+                local: false,
+            },
+            path_attributes: Arc::new(path_attributes),
+            igp_metric: Some(0),
+            immediate_next_hop: None,
+        }
+    }
+
+    fn as_path(ases: &[u32]) -> PathAttribute {
+        PathAttribute::AsPath(AsPath::sequence(ases.iter().map(|a| (*a).into()).collect()))
+    }
+
+    fn decide(paths: Vec<CandidatePath>) -> (CandidatePath, SelectionReason) {
+        let network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let mut loc_rib = LocRib::default();
+        for path in paths {
+            loc_rib.insert(network, path);
+        }
+        let decision = loc_rib.decision(&network).unwrap();
+        (decision.best.clone(), decision.reason)
+    }
+
+    #[test]
+    fn loc_rib_prefers_higher_local_pref_over_shorter_as_path() {
+        let preferred = candidate(
+            2,
+            true,
+            vec![as_path(&[1, 2, 3]), PathAttribute::LocalPref(200)],
+        );
+        let paths = vec![candidate(1, true, vec![as_path(&[1])]), preferred.clone()];
+
+        assert_eq!(decide(paths), (preferred, SelectionReason::HigherLocalPref));
+    }
+
+    /// Imports `path_attributes` for `network` from an eBGP `peer` through
+    /// its Adj-RIB-In.
+    fn import_from_ebgp(
+        loc_rib: &mut LocRib,
+        peer: u8,
+        network: Ipv4Network,
+        path_attributes: Vec<PathAttribute>,
+        import: Option<&RouteMap>,
+    ) {
+        let mut adj_rib_in = AdjRibIn::new();
+        let networks = adj_rib_in.update(&UpdateMessage::new(
+            Arc::new(path_attributes),
+            vec![network],
+            vec![],
+        ));
+        loc_rib.update_from_adj_rib_in(
+            candidate(peer, false, vec![]).source,
+            &adj_rib_in,
+            &networks,
+            import,
+        );
+    }
+
+    #[test]
+    fn loc_rib_honours_local_pref_set_by_import_route_map() {
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let import = RouteMap {
+            multi_exit_disc: None,
+            local_pref: Some(200),
+        };
+        let mut loc_rib = LocRib::default();
+        import_from_ebgp(&mut loc_rib, 1, network, vec![as_path(&[1])], None);
+        import_from_ebgp(
+            &mut loc_rib,
+            2,
+            network,
+            vec![as_path(&[1, 2])],
+            Some(&import),
+        );
+
+        let decision = loc_rib.decision(&network.into()).unwrap();
+        assert_eq!(
+            decision.best.source.peer_address,
+            Ipv4Addr::new(10, 0, 0, 2)
+        );
+        assert_eq!(decision.reason, SelectionReason::HigherLocalPref);
+    }
+
+    #[test]
+    fn loc_rib_compares_med_only_within_the_same_neighbor_as() {
+        let same_neighbor = vec![
+            candidate(
+                1,
+                false,
+                vec![as_path(&[1]), PathAttribute::MultiExitDisc(20)],
+            ),
+            candidate(
+                2,
+                false,
+                vec![as_path(&[1]), PathAttribute::MultiExitDisc(10)],
+            ),
+        ];
+        let (best, reason) = decide(same_neighbor);
+        assert_eq!(best.source.peer_address, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(reason, SelectionReason::LowerMultiExitDisc);
+
+        let other_neighbor = vec![
+            candidate(
+                1,
+                false,
+                vec![as_path(&[1]), PathAttribute::MultiExitDisc(20)],
+            ),
+            candidate(
+                2,
+                false,
+                vec![as_path(&[2]), PathAttribute::MultiExitDisc(10)],
+            ),
+        ];
+        let (best, reason) = decide(other_neighbor);
+        assert_eq!(best.source.peer_address, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(reason, SelectionReason::LowerRouterId);
+    }
+
+    #[test]
+    fn loc_rib_prefers_ebgp_then_igp_metric() {
+        let ebgp = candidate(2, false, vec![as_path(&[1])]);
+        let paths = vec![candidate(1, true, vec![as_path(&[1])]), ebgp.clone()];
+        assert_eq!(decide(paths), (ebgp, SelectionReason::EbgpOverIbgp));
+
+        let mut near = candidate(2, true, vec![as_path(&[1])]);
+        near.igp_metric = Some(5);
+        let mut far = candidate(1, true, vec![as_path(&[1])]);
+        far.igp_metric = Some(10);
+        assert_eq!(
+            decide(vec![far, near.clone()]),
+            (near, SelectionReason::LowerIgpMetric)
+        );
+    }
+
+    #[test]
+    fn loc_rib_uses_only_paths_with_reachable_next_hop() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active".parse().unwrap();
+        let network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let preferred = candidate(
+            1,
+            false,
+            vec![as_path(&[1]), PathAttribute::NextHop([10, 0, 0, 1].into())],
+        );
+        let fallback = candidate(
+            2,
+            false,
+            vec![
+                as_path(&[1, 2]),
+                PathAttribute::NextHop([10, 0, 1, 2].into()),
+            ],
+        );
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        kernel_routing_table.insert_route(KernelRoute {
+            metric: 20,
+            ..kernel_route("10.0.1.0/24", RT_TABLE_MAIN, RTPROT_KERNEL)
+        });
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(network, preferred.clone());
+        loc_rib.insert(network, fallback);
+
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &[]);
+        let decision = loc_rib.decision(&network).unwrap();
+        assert_eq!(decision.best.source, candidate(2, false, vec![]).source);
+        assert_eq!(decision.best.igp_metric, Some(20));
+        assert_eq!(decision.reason, SelectionReason::ReachableNextHop);
+
+        let added = kernel_routing_table.insert_route(kernel_route(
+            "10.0.0.0/24",
+            RT_TABLE_MAIN,
+            RTPROT_KERNEL,
+        ));
+        let kernel_changes: Vec<IpNetwork> =
+            added.iter().filter_map(|e| e.route_network()).collect();
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &kernel_changes);
+        assert_eq!(
+            loc_rib.best_path(&network).unwrap().source,
+            preferred.source
+        );
+
+        let removed = [
+            kernel_routing_table.remove_route(kernel_route(
+                "10.0.0.0/24",
+                RT_TABLE_MAIN,
+                RTPROT_KERNEL,
+            )),
+            kernel_routing_table.remove_route(KernelRoute {
+                metric: 20,
+                ..kernel_route("10.0.1.0/24", RT_TABLE_MAIN, RTPROT_KERNEL)
+            }),
+        ];
+        let kernel_changes: Vec<IpNetwork> = removed
+            .iter()
+            .flatten()
+            .filter_map(|e| e.route_network())
+            .collect();
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &kernel_changes);
+        assert!(loc_rib.best_path(&network).is_none());
+    }
+
+    #[test]
+    fn loc_rib_resolves_next_hops_recursively_when_configured() {
+        let network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let next_hop_network: IpNetwork = "10.9.0.0/16".parse::<Ipv4Network>().unwrap().into();
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        kernel_routing_table.insert_route(KernelRoute {
+            metric: 7,
+            ..kernel_route("10.0.0.0/24", RT_TABLE_MAIN, RTPROT_KERNEL)
+        });
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(
+            network,
+            candidate(
+                1,
+                true,
+                vec![as_path(&[1]), PathAttribute::NextHop([10, 9, 0, 1].into())],
+            ),
+        );
+        loc_rib.insert(
+            next_hop_network,
+            candidate(
+                2,
+                true,
+                vec![as_path(&[]), PathAttribute::NextHop([10, 0, 0, 2].into())],
+            ),
+        );
+
+        let config: Config = "64512 127.0.0.1 64512 127.0.0.2 active".parse().unwrap();
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &[]);
+        assert!(loc_rib.best_path(&network).is_none());
+
+        let config: Config = "64512 127.0.0.1 64512 127.0.0.2 active recursive-next-hop"
+            .parse()
+            .unwrap();
+        loc_rib.invalidate_next_hops();
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &[]);
+        let best = loc_rib.best_path(&network).unwrap();
+        assert_eq!(best.igp_metric, Some(7));
+        assert_eq!(best.immediate_next_hop, Some([10, 0, 0, 2].into()));
+
+        loc_rib.insert(
+            "10.9.0.0/24".parse::<Ipv4Network>().unwrap().into(),
+            candidate(
+                3,
+                true,
+                vec![as_path(&[]), PathAttribute::NextHop([10, 0, 1, 3].into())],
+            ),
+        );
+        kernel_routing_table.insert_route(KernelRoute {
+            metric: 9,
+            ..kernel_route("10.0.1.0/24", RT_TABLE_MAIN, RTPROT_KERNEL)
+        });
+        loc_rib.resolve_next_hops(
+            &config.global,
+            &kernel_routing_table,
+            &["10.0.1.0/24".parse::<Ipv4Network>().unwrap().into()],
+        );
+        let best = loc_rib.best_path(&network).unwrap();
+        assert_eq!(best.igp_metric, Some(9));
+        assert_eq!(best.immediate_next_hop, Some([10, 0, 1, 3].into()));
+    }
+
+    #[test]
+    fn loc_rib_forwards_to_gateway_of_route_resolving_next_hop() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active".parse().unwrap();
+        let network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        kernel_routing_table.insert_route(KernelRoute {
+            gateway: Some([10, 0, 0, 254].into()),
+            ..kernel_route("10.9.0.0/16", RT_TABLE_MAIN, RTPROT_STATIC)
+        });
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(
+            network,
+            candidate(1, false, vec![PathAttribute::NextHop([10, 9, 0, 1].into())]),
+        );
+
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &[]);
+
+        let best = loc_rib.best_path(&network).unwrap();
+        assert_eq!(best.next_hop(&network), Some([10, 9, 0, 1].into()));
+        assert_eq!(
+            best.forwarding_next_hop(&network),
+            Some([10, 0, 0, 254].into())
+        );
+    }
+
+    #[test]
+    fn loc_rib_longest_match_skips_unrelated_and_unusable_prefixes() {
+        let mut loc_rib = LocRib::default();
+        let mut insert = |network: &str, igp_metric| {
+            let network: IpNetwork = network.parse::<Ipv4Network>().unwrap().into();
+            let path = CandidatePath {
+                igp_metric,
+                ..candidate(1, false, vec![PathAttribute::NextHop([10, 0, 0, 1].into())])
+            };
+            loc_rib.insert(network, path);
+            network
+        };
+        let eight = insert("10.0.0.0/8", Some(0));
+        let sixteen = insert("10.1.0.0/16", Some(0));
+        insert("10.1.2.0/24", None);
+        for third in [0, 1, 3, 200] {
+            insert(&format!("10.1.{third}.0/24"), Some(0));
+        }
+        insert("10.1.2.128/25", Some(0));
+
+        let longest_match = |addr: [u8; 4]| {
+            loc_rib
+                .longest_match(IpAddr::V4(addr.into()))
+                .map(|(network, _)| network)
+        };
+        assert_eq!(longest_match([10, 1, 2, 1]), Some(sixteen));
+        assert_eq!(longest_match([10, 1, 100, 1]), Some(sixteen));
+        assert_eq!(longest_match([10, 2, 0, 1]), Some(eight));
+        assert_eq!(longest_match([11, 0, 0, 1]), None);
+        assert_eq!(loc_rib.longest_match("2001:db8::1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn loc_rib_re_resolves_only_paths_via_changed_routes() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active".parse().unwrap();
+        let first: IpNetwork = "10.100.1.0/24".parse::<Ipv4Network>().unwrap().into();
+        let second: IpNetwork = "10.100.2.0/24".parse::<Ipv4Network>().unwrap().into();
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        kernel_routing_table.insert_route(kernel_route(
+            "10.0.0.0/24",
+            RT_TABLE_MAIN,
+            RTPROT_KERNEL,
+        ));
+        kernel_routing_table.insert_route(kernel_route(
+            "10.0.1.0/24",
+            RT_TABLE_MAIN,
+            RTPROT_KERNEL,
+        ));
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(
+            first,
+            candidate(1, false, vec![PathAttribute::NextHop([10, 0, 0, 1].into())]),
+        );
+        loc_rib.insert(
+            second,
+            candidate(2, false, vec![PathAttribute::NextHop([10, 0, 1, 2].into())]),
+        );
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &[]);
+        let version = loc_rib.version();
+
+        loc_rib.resolve_next_hops(&config.global, &kernel_routing_table, &[]);
+        assert_eq!(loc_rib.version(), version);
+
+        for network in ["10.0.0.0/24", "10.0.1.0/24"] {
+            kernel_routing_table.remove_route(kernel_route(network, RT_TABLE_MAIN, RTPROT_KERNEL));
+            kernel_routing_table.insert_route(KernelRoute {
+                metric: 5,
+                ..kernel_route(network, RT_TABLE_MAIN, RTPROT_KERNEL)
+            });
+        }
+        loc_rib.resolve_next_hops(
+            &config.global,
+            &kernel_routing_table,
+            &["10.0.0.0/24".parse::<Ipv4Network>().unwrap().into()],
+        );
+        assert_eq!(loc_rib.best_path(&first).unwrap().igp_metric, Some(5));
+        assert_eq!(loc_rib.best_path(&second).unwrap().igp_metric, Some(0));
+        assert!(loc_rib.version() > version);
+    }
+
+    #[test]
+    fn loc_rib_explains_why_each_path_lost() {
+        let network: IpNetwork = "10.100.220.0/24".parse::<Ipv4Network>().unwrap().into();
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(
+            network,
+            candidate(
+                1,
+                false,
+                vec![as_path(&[1]), PathAttribute::Origin(Origin::Igp)],
+            ),
+        );
+        loc_rib.insert(
+            network,
+            candidate(
+                2,
+                false,
+                vec![as_path(&[1, 2]), PathAttribute::Origin(Origin::Igp)],
+            ),
+        );
+        loc_rib.insert(
+            network,
+            candidate(
+                3,
+                false,
+                vec![as_path(&[3]), PathAttribute::Origin(Origin::Incomplete)],
+            ),
+        );
+
+        let decision = loc_rib.decision(&network).unwrap();
+        assert_eq!(
+            decision.best.source.peer_address,
+            Ipv4Addr::new(10, 0, 0, 1)
+        );
+        let rejected: Vec<SelectionReason> = decision.rejected.iter().map(|(_, r)| *r).collect();
+        assert_eq!(
+            rejected,
+            vec![SelectionReason::ShorterAsPath, SelectionReason::LowerOrigin]
+        );
+        assert_eq!(decision.reason, SelectionReason::LowerOrigin);
+        assert_eq!(decision.reason.to_string(), "lower ORIGIN");
+
+        let source = decision.best.source;
+        loc_rib.remove_source(&source);
+        assert_eq!(
+            loc_rib.best_path(&network).unwrap().source.peer_address,
+            Ipv4Addr::new(10, 0, 0, 3)
+        );
+
+        let version = loc_rib.version();
+        loc_rib.remove_source(&source);
+        assert_eq!(loc_rib.version(), version);
+    }
+
+    #[test]
+    fn adj_rib_out_sends_only_differences_from_loc_rib() {
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let other_network: Ipv4Network = "10.100.221.0/24".parse().unwrap();
+        let path = candidate(1, false, vec![as_path(&[1])]);
+        let mut loc_rib = LocRib::default();
+        loc_rib.insert(network.into(), path.clone());
+        loc_rib.insert(other_network.into(), path.clone());
+        let export = |_: &IpNetwork, path: &CandidatePath| Some(path.path_attributes.to_vec());
+        let mut adj_rib_out = AdjRibOut::new();
+
+        let updates = adj_rib_out.sync(&loc_rib, export);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            updates[0].network_layer_reachability_information,
+            vec![network, other_network]
+        );
+        assert!(adj_rib_out.is_synced_with(&loc_rib));
+        assert!(adj_rib_out.sync(&loc_rib, export).is_empty());
+
+        loc_rib.remove(&network.into(), &path.source);
+        assert!(!adj_rib_out.is_synced_with(&loc_rib));
+        let updates = adj_rib_out.sync(&loc_rib, export);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].withdrawn_routes, vec![network]);
+        assert!(updates[0].network_layer_reachability_information.is_empty());
+        assert!(adj_rib_out.get(&network.into()).is_none());
+    }
+
+    #[test]
+    fn adj_rib_out_groups_prefixes_by_attributes() {
+        let paths = [
+            candidate(1, false, vec![as_path(&[1])]),
+            candidate(2, false, vec![as_path(&[2])]),
+        ];
+        let mut loc_rib = LocRib::default();
+        let networks: Vec<Ipv4Network> = (0..6)
+            .map(|i| format!("10.100.{i}.0/24").parse().unwrap())
+            .collect();
+        for (i, network) in networks.iter().enumerate() {
+            loc_rib.insert((*network).into(), paths[i % 2].clone());
+        }
+        let export = |_: &IpNetwork, path: &CandidatePath| Some(path.path_attributes.to_vec());
+
+        let updates = AdjRibOut::new().sync(&loc_rib, export);
+        let grouped: Vec<(Vec<PathAttribute>, Vec<Ipv4Network>)> = updates
+            .iter()
+            .map(|update| {
+                (
+                    update.path_attributes.to_vec(),
+                    update.network_layer_reachability_information.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            grouped,
+            vec![
+                (
+                    vec![as_path(&[1])],
+                    vec![networks[0], networks[2], networks[4]]
+                ),
+                (
+                    vec![as_path(&[2])],
+                    vec![networks[1], networks[3], networks[5]]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn adj_rib_out_splits_updates_to_fit_the_message_size_limit() {
+        let ases: Vec<u32> = (0..300).map(|i| 4_200_000_000 + i).collect();
+        let path = candidate(
+            1,
+            false,
+            vec![
+                as_path(&ases),
+                PathAttribute::NextHop([10, 0, 0, 1].into()),
+                PathAttribute::MpReachNlri(MpReachNlri {
+                    next_hop: Ipv6NextHop {
+                        global: "2001:db8::1".parse().unwrap(),
+                        link_local: Some("fe80::1".parse().unwrap()),
+                    },
+                    nlri: vec![],
+                }),
+            ],
+        );
+        let mut loc_rib = LocRib::default();
+        for i in 0..1000u32 {
+            let ipv4: Ipv4Network = format!("{}/32", Ipv4Addr::from(0x0a00_0000 + i))
+                .parse()
+                .unwrap();
+            let ipv6: Ipv6Network = format!("2001:db8::{i:x}/128").parse().unwrap();
+            loc_rib.insert(ipv4.into(), path.clone());
+            loc_rib.insert(ipv6.into(), path.clone());
+        }
+        let export = |_: &IpNetwork, path: &CandidatePath| Some(path.path_attributes.to_vec());
+        let mut adj_rib_out = AdjRibOut::new();
+
+        let prefixes = |updates: &[UpdateMessage]| -> usize {
+            updates
+                .iter()
+                .map(|update| {
+                    for message in [update.clone(), update.clone().into_two_octet_as()] {
+                        assert!(BytesMut::from(message).len() <= 4096);
+                    }
+                    let ipv6_prefixes: usize = update
+                        .path_attributes
+                        .iter()
+                        .map(|p| match p {
+                            PathAttribute::MpReachNlri(m) => m.nlri.len(),
+                            PathAttribute::MpUnreachNlri(m) => m.withdrawn_routes.len(),
+                            _ => 0,
+                        })
+                        .sum();
+                    update.network_layer_reachability_information.len()
+                        + update.withdrawn_routes.len()
+                        + ipv6_prefixes
+                })
+                .sum()
+        };
+        assert_eq!(prefixes(&adj_rib_out.sync(&loc_rib, export)), 2000);
+
+        loc_rib.remove_source(&path.source);
+        assert_eq!(prefixes(&adj_rib_out.sync(&loc_rib, export)), 2000);
+    }
+
+    #[test]
+    fn adj_rib_in_stores_advertised_and_removes_withdrawn_routes() {
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let ipv6_network: Ipv6Network = "2001:db8:100::/48".parse().unwrap();
+        let path_attributes = Arc::new(vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(AsPath::sequence(vec![64513.into()])),
+            PathAttribute::NextHop("10.200.100.3".parse().unwrap()),
+            PathAttribute::MpReachNlri(MpReachNlri {
+                next_hop: Ipv6NextHop {
+                    global: "2001:db8::1".parse().unwrap(),
+                    link_local: None,
+                },
+                nlri: vec![ipv6_network],
+            }),
+        ]);
+        let mut adj_rib_in = AdjRibIn::new();
+
+        adj_rib_in.update(&UpdateMessage::new(
+            Arc::clone(&path_attributes),
+            vec![network],
+            vec![],
+        ));
+        assert_eq!(adj_rib_in.len(), 2);
+        assert_eq!(adj_rib_in.get(&network.into()), Some(&path_attributes));
+
+        adj_rib_in.update(&UpdateMessage::new(
+            Arc::new(vec![PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                withdrawn_routes: vec![ipv6_network],
+            })]),
+            vec![],
+            vec![network],
+        ));
+        assert!(adj_rib_in.is_empty());
+    }
+
+    #[test]
+    fn ipv6_network_round_trips_through_nlri_encoding() {
+        let networks: Vec<Ipv6Network> = vec![
+            "2001:db8::/32".parse().unwrap(),
+            "2001:db8:1:2::/64".parse().unwrap(),
+            "::/0".parse().unwrap(),
+        ];
+        let bytes: BytesMut = networks.iter().flat_map(BytesMut::from).collect();
+
+        assert_eq!(bytes.len(), 5 + 9 + 1);
+        assert_eq!(Ipv6Network::from_u8_slice(&bytes).unwrap(), networks);
+    }
+
+    #[test]
+    fn ipv4_network_round_trips_through_nlri_encoding() {
+        let networks: Vec<Ipv4Network> = vec![
+            "0.0.0.0/0".parse().unwrap(),
+            "10.0.0.0/8".parse().unwrap(),
+            "10.1.0.0/17".parse().unwrap(),
+            "10.1.2.3/32".parse().unwrap(),
+        ];
+        let bytes: BytesMut = networks.iter().flat_map(BytesMut::from).collect();
+
+        assert_eq!(
+            networks
+                .iter()
+                .map(Ipv4Network::bytes_len)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 4, 5]
+        );
+        assert_eq!(bytes.len(), 1 + 2 + 4 + 5);
+        assert_eq!(Ipv4Network::from_u8_slice(&bytes).unwrap(), networks);
+    }
+
+    fn kernel_route(network: &str, table: u8, protocol: u8) -> KernelRoute {
+        KernelRoute {
+            network: network.parse().unwrap(),
+            table,
+            gateway: None,
+            output_interface: Some(2),
+            metric: 0,
+            protocol,
+        }
+    }
+
+    #[test]
+    fn locrib_originates_configured_networks_only_while_in_kernel() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active 10.100.220.0/24"
+            .parse()
+            .unwrap();
+        let network: Ipv4Network = "10.100.220.0/24".parse().unwrap();
+        let route = kernel_route("10.100.220.0/24", RT_TABLE_MAIN, RTPROT_KERNEL);
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        let mut loc_rib = LocRib::default();
+
+        kernel_routing_table.insert_route(route);
+        loc_rib.originate_networks(&config.global, &kernel_routing_table);
+        let path = loc_rib.best_path(&network.into()).unwrap();
+        assert_eq!(path.source, PathSource::local(&config.global));
+        assert_eq!(
+            *path.path_attributes,
+            vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(AsPath::default()),
+                PathAttribute::NextHop("127.0.0.1".parse().unwrap()),
+            ]
+        );
+
+        let version = loc_rib.version();
+        loc_rib.originate_networks(&config.global, &kernel_routing_table);
+        assert_eq!(loc_rib.version(), version);
+
+        kernel_routing_table.remove_route(route);
+        loc_rib.originate_networks(&config.global, &kernel_routing_table);
+        assert!(loc_rib.best_path(&network.into()).is_none());
+    }
+
+    #[test]
+    fn locrib_honours_local_pref_set_on_originated_routes() {
+        let network: IpNetwork = "10.2.0.0/24".parse::<Ipv4Network>().unwrap().into();
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        kernel_routing_table.insert_route(kernel_route(
+            "10.2.0.0/24",
+            RT_TABLE_MAIN,
+            RTPROT_STATIC,
+        ));
+        let learned = candidate(
+            2,
+            true,
+            vec![
+                PathAttribute::Origin(Origin::Igp),
+                as_path(&[64513]),
+                PathAttribute::LocalPref(150),
+            ],
+        );
+
+        for (local_pref, local_wins) in [(200, true), (50, false)] {
+            let config: Config = format!(
+                "64512 127.0.0.1 64513 127.0.0.2 active \
+                redistribute static set-local-pref {local_pref}"
+            )
+            .parse()
+            .unwrap();
+            let mut loc_rib = LocRib::default();
+            loc_rib.insert(network, learned.clone());
+            loc_rib.originate_networks(&config.global, &kernel_routing_table);
+
+            let decision = loc_rib.decision(&network).unwrap();
+            assert_eq!(decision.best.source.local, local_wins);
+            assert_eq!(decision.reason, SelectionReason::HigherLocalPref);
+        }
+    }
+
+    #[test]
+    fn locrib_originates_redistributed_kernel_routes_as_incomplete() {
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active \
+            redistribute connected \
+            redistribute static prefix 10.2.0.0/16 set-med 5"
+            .parse()
+            .unwrap();
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        for route in [
+            kernel_route("10.1.0.0/24", RT_TABLE_MAIN, RTPROT_KERNEL),
+            kernel_route("10.1.1.0/24", 100, RTPROT_KERNEL),
+            kernel_route("10.2.0.0/24", RT_TABLE_MAIN, RTPROT_STATIC),
+            kernel_route("10.3.0.0/24", RT_TABLE_MAIN, RTPROT_STATIC),
+            kernel_route("10.4.0.0/24", RT_TABLE_MAIN, RTPROT_BOOT),
+        ] {
+            kernel_routing_table.insert_route(route);
+        }
+        let mut loc_rib = LocRib::default();
+
+        loc_rib.originate_networks(&config.global, &kernel_routing_table);
+
+        let originated: Vec<(IpNetwork, Vec<PathAttribute>)> = loc_rib
+            .best_paths()
+            .map(|(network, path)| (*network, path.path_attributes.to_vec()))
+            .collect();
+        let attributes = |multi_exit_disc: Option<u32>| {
+            let mut path_attributes = vec![
+                PathAttribute::Origin(Origin::Incomplete),
+                PathAttribute::AsPath(AsPath::default()),
+                PathAttribute::NextHop("127.0.0.1".parse().unwrap()),
+            ];
+            path_attributes.extend(multi_exit_disc.map(PathAttribute::MultiExitDisc));
+            path_attributes
+        };
+        assert_eq!(
+            originated,
+            vec![
+                (
+                    "10.1.0.0/24".parse::<Ipv4Network>().unwrap().into(),
+                    attributes(None)
+                ),
+                (
+                    "10.2.0.0/24".parse::<Ipv4Network>().unwrap().into(),
+                    attributes(Some(5))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn locrib_originates_redistributed_ipv6_routes_only_with_local_ipv6() {
+        let network: IpNetwork = "2001:db8:1::/48".parse().unwrap();
+        let mut kernel_routing_table = KernelRoutingTable::default();
+        kernel_routing_table.insert_route(kernel_route(
+            "2001:db8:1::/48",
+            RT_TABLE_MAIN,
+            RTPROT_STATIC,
+        ));
+
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active redistribute static"
+            .parse()
+            .unwrap();
+        let mut loc_rib = LocRib::default();
+        loc_rib.originate_networks(&config.global, &kernel_routing_table);
+        assert!(loc_rib.best_path(&network).is_none());
+
+        let config: Config = "64512 127.0.0.1 64513 127.0.0.2 active \
+            local-ipv6 2001:db8::1 redistribute static"
+            .parse()
+            .unwrap();
+        loc_rib.originate_networks(&config.global, &kernel_routing_table);
+        assert_eq!(
+            loc_rib
+                .best_path(&network)
+                .unwrap()
+                .path_attributes
+                .to_vec(),
+            vec![
+                PathAttribute::Origin(Origin::Incomplete),
+                PathAttribute::AsPath(AsPath::default()),
+                PathAttribute::MpReachNlri(MpReachNlri {
+                    next_hop: Ipv6NextHop {
+                        global: "2001:db8::1".parse().unwrap(),
+                        link_local: None,
+                    },
+                    nlri: vec![],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_or_too_long_ipv4_prefixes_are_rejected() {
+        for bytes in [&[24, 10, 0][..], &[33, 10, 0, 0, 0, 0]] {
+            assert_eq!(
+                Ipv4Network::from_u8_slice(bytes)
+                    .unwrap_err()
+                    .notification()
+                    .unwrap()
+                    .error_code,
+                ErrorCode::UpdateMessageError(UpdateMessageErrorSubcode::InvalidNetworkField)
+            );
+        }
+        assert_eq!(
+            Ipv4Network::from_u8_slice(&[0, 8, 10]).unwrap(),
+            vec!["0.0.0.0/0".parse().unwrap(), "10.0.0.0/8".parse().unwrap()]
+        );
     }
 }
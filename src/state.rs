@@ -2,6 +2,7 @@
 pub enum State {
     Idle,
     Connect,
+    Active,
     OpenSent,
     OpenConfirm,
     Established,
@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::Instant;
+
+/// A one-shot timer. The peer sleeps until the earliest deadline of its
+/// timers and then checks which of them expired.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct Timer {
+    duration: Duration,
+    deadline: Option<Instant>,
+}
+
+impl Timer {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            deadline: None,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    /// Starts the timer, or restarts it if it is already running. A timer
+    /// with a zero duration is never started.
+    pub fn start(&mut self) {
+        self.deadline = if self.duration.is_zero() {
+            None
+        } else {
+            Some(Instant::now() + self.duration)
+        };
+    }
+
+    pub fn stop(&mut self) {
+        self.deadline = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Returns true exactly once when the deadline has passed and stops the timer.
+    pub fn is_expired(&mut self) -> bool {
+        match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => {
+                self.deadline = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Exponential backoff for ConnectRetryTimer, doubling the interval on each
+/// consecutive failure up to `maximum` and applying the RFC 4271 §10 jitter.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct Backoff {
+    initial: Duration,
+    maximum: Duration,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, maximum: Duration) -> Self {
+        Self {
+            initial,
+            maximum,
+            attempts: 0,
+        }
+    }
+
+    pub fn next_interval(&mut self) -> Duration {
+        let interval = self
+            .initial
+            .checked_mul(2u32.saturating_pow(self.attempts))
+            .unwrap_or(self.maximum)
+            .min(self.maximum);
+        self.attempts = self.attempts.saturating_add(1);
+
+        interval.mul_f64(rand::thread_rng().gen_range(0.75..=1.0))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn timer_expires_once_after_its_duration() {
+        let mut timer = Timer::new(Duration::from_secs(3));
+        timer.start();
+        assert!(!timer.is_expired());
+
+        tokio::time::advance(Duration::from_secs(3)).await;
+        assert!(timer.is_expired());
+        assert!(!timer.is_expired());
+        assert!(!timer.is_running());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timer_with_zero_duration_never_starts() {
+        let mut timer = Timer::new(Duration::ZERO);
+        timer.start();
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(!timer.is_running());
+        assert!(!timer.is_expired());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_maximum_with_jitter() {
+        let mut backoff = Backoff::new(Duration::from_secs(4), Duration::from_secs(16));
+        let intervals: Vec<Duration> = (0..4).map(|_| backoff.next_interval()).collect();
+
+        for (interval, expected) in intervals.iter().zip([4, 8, 16, 16]) {
+            let expected = Duration::from_secs(expected);
+            assert!(*interval <= expected);
+            assert!(*interval >= expected.mul_f64(0.75));
+        }
+
+        backoff.reset();
+        assert!(backoff.next_interval() <= Duration::from_secs(4));
+    }
+}
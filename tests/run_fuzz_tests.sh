@@ -0,0 +1,8 @@
+#!/bin/bash
+# Runs every decoder fuzz target for SECONDS each (default 60), starting
+# from the seed corpus. Needs a nightly toolchain and cargo-fuzz.
+set -e
+cd "$(dirname "$0")/../fuzz"
+for target in $(cargo +nightly fuzz list); do
+  cargo +nightly fuzz run "$target" -- -max_total_time="${1:-60}"
+done